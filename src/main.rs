@@ -1,16 +1,182 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveTime, Timelike, Utc, Weekday};
 use clap::{Arg, Command};
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
+use std::io::{BufRead, BufReader, IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
 use std::env;
 
+// Version of the database layout this build reads and writes
+const SCHEMA_VERSION: u32 = 2;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+// Set from the database on every load, so a daemon picks up a changed
+// format when it reloads, see format_time
+static DATE_FORMAT: RwLock<Option<String>> = RwLock::new(None);
+
+// Set from the database's timezone on every load like DATE_FORMAT, see Zone
+static DEFAULT_ZONE: RwLock<Option<Zone>> = RwLock::new(None);
+
+// Set by --no-save: commands run against the loaded copy and changes are
+// dropped, and the links and hooks that go with them are only simulated
+static NO_SAVE: AtomicBool = AtomicBool::new(false);
+
+// Set by --run-hooks-on-dry-run: simulated rollovers still run their hooks,
+// with QC_DRY_RUN=1, so hook scripts can be tried out
+static RUN_HOOKS_ON_DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+// Set by --force-refresh: file metadata is recomputed even when the files look unchanged
+static FORCE_REFRESH: AtomicBool = AtomicBool::new(false);
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "avi", "mov"];
+
+const DEFAULT_LINK_TEMPLATE: &str = "{name}_ep{ep}";
+
+const DEFAULT_NFO_TEMPLATE: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<episodedetails>
+  <title>{title}</title>
+  <showtitle>{program}</showtitle>
+  <season>{season}</season>
+  <episode>{episode}</episode>
+</episodedetails>
+"#;
+
+// How late a scheduled update may be before it counts as missed
+const MISSED_UPDATE_GRACE_HOURS: i64 = 24;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Episode {
     path: PathBuf,
     episode_number: usize,
+    #[serde(default)]
+    duration_secs: Option<u32>,
+    #[serde(default)]
+    season: Option<u32>,
+    // A VIDEO_TS/BDMV disc folder linked as a whole rather than a single file
+    #[serde(default)]
+    folder: bool,
+    // Further files of a multi-part episode (CD2, Part 2, ...), aired with `path`
+    #[serde(default)]
+    extra_parts: Vec<PathBuf>,
+    // Name inside the program's zip archive; `path` is where it gets extracted
+    #[serde(default)]
+    archive_entry: Option<String>,
+    // File on the program's SFTP host; `path` is where it gets downloaded
+    #[serde(default)]
+    remote_path: Option<String>,
+    // Passed over instead of aired when the program reaches it
+    #[serde(default)]
+    skipped: Option<SkipReason>,
+    // The files as they were when duration_secs was measured
+    #[serde(default)]
+    probed: Option<FileStamp>,
+    // Set with set-title, shown instead of the file name
+    #[serde(default)]
+    title: Option<String>,
+}
+
+// Size and modification time of a set of files, to tell whether metadata
+// computed from them is still good without reading them again. Sizes must
+// match exactly, since mtimes alone miss a file replaced within a FAT
+// filesystem's 2 second resolution.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct FileStamp {
+    size: u64,
+    modified: Option<DateTime<Utc>>,
+}
+
+const MTIME_RESOLUTION_SECS: i64 = 2;
+
+impl FileStamp {
+    // Total size and latest modification of all the files, None if any is
+    // missing. Folders count as their own entry, not their contents.
+    fn of<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> Option<FileStamp> {
+        let mut stamp = FileStamp { size: 0, modified: None };
+        for path in paths {
+            let metadata = fs::metadata(path).ok()?;
+            stamp.size += metadata.len();
+            let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+            stamp.modified = stamp.modified.max(modified);
+        }
+        Some(stamp)
+    }
+
+    fn matches(&self, other: &FileStamp) -> bool {
+        let close = match (self.modified, other.modified) {
+            (Some(a), Some(b)) => (a - b).num_seconds().abs() <= MTIME_RESOLUTION_SECS,
+            (a, b) => a == b,
+        };
+        self.size == other.size && close
+    }
+}
+
+// Whether metadata computed when the files were at `stamp` can be kept.
+// Without a stamp the metadata predates stamping and is trusted.
+fn metadata_unchanged(stamp: Option<&FileStamp>, current: Option<&FileStamp>) -> bool {
+    if FORCE_REFRESH.load(AtomicOrdering::SeqCst) {
+        return false;
+    }
+    match (stamp, current) {
+        (None, _) => true,
+        (Some(stamp), Some(current)) => stamp.matches(current),
+        (Some(_), None) => false,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+enum SkipReason {
+    // Skipped once with `skip --episodes`
+    Manual,
+    // Permanently left out with `exclude`
+    Excluded,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SkipReason::Manual => write!(f, "manual"),
+            SkipReason::Excluded => write!(f, "excluded"),
+        }
+    }
+}
+
+impl Episode {
+    fn new(path: PathBuf, episode_number: usize) -> Self {
+        let season = parse_season(&path.file_name().unwrap_or_default().to_string_lossy());
+        Episode {
+            path,
+            episode_number,
+            duration_secs: None,
+            season,
+            folder: false,
+            extra_parts: Vec::new(),
+            archive_entry: None,
+            remote_path: None,
+            skipped: None,
+            probed: None,
+            title: None,
+        }
+    }
+
+    // The title set for the episode, or the file name without extension
+    fn title(&self) -> String {
+        match &self.title {
+            Some(title) => title.clone(),
+            None => self.path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+        }
+    }
+
+    fn parts(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.path).chain(&self.extra_parts)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -23,320 +189,9546 @@ struct Program {
     start_date: Option<DateTime<Utc>>,
     last_update: Option<DateTime<Utc>>,
     status: ProgramStatus,
+    #[serde(default)]
+    minutes_per_rollover: Option<u32>,
+    #[serde(default)]
+    pause_between_seasons: bool,
+    #[serde(default)]
+    confirmed_season: Option<u32>,
+    #[serde(default)]
+    airing_days: Vec<Weekday>,
+    #[serde(default)]
+    sort: EpisodeSort,
+    #[serde(default = "default_interval_days")]
+    interval_days: u32,
+    // Seconds between rollovers for an interval given in hours, which then
+    // replaces interval_days; None for a whole number of days
+    #[serde(default)]
+    interval_secs: Option<u32>,
+    // Links of the latest rollover, as written before schema 2. Only read to
+    // fill in active_links.
+    #[serde(default, skip_serializing)]
+    last_symlinks: Vec<PathBuf>,
+    // Every symlink queuecast made for the program that should still exist
+    #[serde(default)]
+    active_links: Vec<ActiveLink>,
+    // Episodes were added file by file rather than scanned from `directory`,
+    // so the directory listing says nothing about what belongs to the program
+    #[serde(default)]
+    curated: bool,
+    #[serde(default)]
+    folder_episodes: bool,
+    // `directory` is a zip file whose entries are the episodes
+    #[serde(default)]
+    archive: bool,
+    #[serde(default)]
+    hooks: ProgramHooks,
+    // Episode file extensions to scan for, empty for VIDEO_EXTENSIONS
+    #[serde(default)]
+    extensions: Vec<String>,
+    // How many episodes the viewer has watched, independent of airing
+    #[serde(default)]
+    watched_episode: usize,
+    // IANA zone its airing days are reckoned in, None for the database's
+    #[serde(default)]
+    timezone: Option<String>,
+    // sftp:// URL the episodes are listed from; `directory` is then the
+    // local folder they are downloaded to as they air
+    #[serde(default)]
+    remote: Option<String>,
+    // Folder for its links instead of the symlink directory, relative to it
+    #[serde(default)]
+    link_dir: Option<PathBuf>,
+    // Hash of the program this one was duplicated from, for a rerun
+    #[serde(default)]
+    duplicated_from: Option<String>,
+    // Take over the episode list whenever the original is rescanned
+    #[serde(default)]
+    follow_rescans: bool,
+    // Due rollovers that didn't happen since the last one that did
+    #[serde(default)]
+    stall: Option<Stall>,
+    // Frozen on its current episode: nothing moves the pointer or retargets
+    // its links until 'unpin'
+    #[serde(default)]
+    pinned: bool,
+    // Free-form label like "On Hiatus" shown instead of the status. While
+    // set, updates leave the program alone as if it were stopped.
+    #[serde(default)]
+    custom_status: Option<String>,
+    #[serde(default)]
+    season_release: SeasonRelease,
+    // Days to wait after releasing a whole season, None for the interval
+    // times the episodes released
+    #[serde(default)]
+    season_cooldown_days: Option<u32>,
+    // Days until the next rollover when the latest one wasn't a regular one,
+    // None for interval_days
+    #[serde(default)]
+    next_wait_days: Option<u32>,
+    // Place among the Ready programs waiting to start, lowest first
+    #[serde(default)]
+    queue_position: u32,
+    // Episodes linked per rollover, None for one
+    #[serde(default)]
+    batch_size: Option<u32>,
+    // Milestones already fired, so none fires twice
+    #[serde(default)]
+    milestones_reached: Vec<Milestone>,
+    // Rollovers whose links stay on air, older ones are unlinked; None to
+    // keep everything
+    #[serde(default)]
+    keep_window: Option<u32>,
+    // When it was added, None for programs added before this was recorded
+    #[serde(default)]
+    added_at: Option<DateTime<Utc>>,
+    // The airing slot the latest rollover stood in for when it was forced
+    // outside the airing window. The schedule counts on from it, while
+    // last_update keeps when the rollover really happened.
+    #[serde(default)]
+    schedule_slot: Option<DateTime<Utc>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-enum ProgramStatus {
-    Ready,
-    Running,
-    Finished,
-    Stopped,
+// How a program airs a season it reaches
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SeasonRelease {
+    // Episode by episode, one rollover each
+    #[default]
+    PerEpisode,
+    // All at once for a marathon, when a rollover reaches a season's first episode
+    WholeSeason,
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-struct Database {
-    programs: HashMap<String, Program>,
-    symlink_dir: Option<PathBuf>,
+// Why a due rollover didn't happen
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum StallReason {
+    // The folder, archive or server is missing or unreachable
+    SourceUnavailable,
+    EpisodeMissing,
+    // The pre hook vetoed the rollover or couldn't be run
+    HookVeto,
+    NoSpace,
+    // An episode file is still being written, tried again next update
+    StillDownloading,
+    // Link names clash, or a real file is in a link's place
+    LinkClash,
+    Error,
 }
 
-impl Database {
-    fn get_config_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
-        let home_dir = env::var("HOME")
-            .or_else(|_| env::var("USERPROFILE"))
-            .map_err(|_| "Could not find home directory")?;
-        
-        let config_dir = Path::new(&home_dir).join(".config").join("queuecast");
-        fs::create_dir_all(&config_dir)?;
-        
-        Ok(config_dir.join("queuecast.json"))
-    }
-
-    fn load() -> Result<Self, Box<dyn std::error::Error>> {
-        let config_path = Self::get_config_path()?;
-        if config_path.exists() {
-            let content = fs::read_to_string(&config_path)?;
-            Ok(serde_json::from_str(&content)?)
-        } else {
-            Ok(Database {
-                programs: HashMap::new(),
-                symlink_dir: None,
-            })
+impl std::fmt::Display for StallReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StallReason::SourceUnavailable => write!(f, "source unavailable"),
+            StallReason::EpisodeMissing => write!(f, "episode missing"),
+            StallReason::HookVeto => write!(f, "vetoed by hook"),
+            StallReason::NoSpace => write!(f, "no space"),
+            StallReason::StillDownloading => write!(f, "still downloading"),
+            StallReason::LinkClash => write!(f, "link clash"),
+            StallReason::Error => write!(f, "error"),
         }
     }
+}
 
-    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let config_path = Self::get_config_path()?;
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write(&config_path, content)?;
-        Ok(())
-    }
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Stall {
+    // Of the latest attempt
+    reason: StallReason,
+    attempts: u32,
+    since: DateTime<Utc>,
 }
 
-fn generate_hash(name: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(name.as_bytes());
-    let result = hasher.finalize();
-    format!("{:x}", result)[..8].to_string()
+// A program counts as stalled once this many due rollovers in a row failed
+const STALLED_AFTER_ATTEMPTS: u32 = 3;
+
+fn is_stalled(program: &Program) -> bool {
+    program.stall.as_ref().is_some_and(|stall| stall.attempts >= STALLED_AFTER_ATTEMPTS)
 }
 
-fn scan_episodes(dir: &Path) -> Result<Vec<Episode>, Box<dyn std::error::Error>> {
-    let mut episodes = Vec::new();
-    let mut entries: Vec<_> = fs::read_dir(dir)?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            entry.path().is_file() && 
-            entry.path().extension().map_or(false, |ext| {
-                matches!(ext.to_str(), Some("mp4") | Some("mkv") | Some("avi") | Some("mov"))
-            })
-        })
-        .collect();
-    
-    entries.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
-    
-    for (i, entry) in entries.iter().enumerate() {
-        episodes.push(Episode {
-            path: entry.path(),
-            episode_number: i + 1,
-        });
+// A failed rollover and why. Plain errors count as StallReason::Error.
+struct RolloverFailure(StallReason, Box<dyn std::error::Error>);
+
+impl<E: Into<Box<dyn std::error::Error>>> From<E> for RolloverFailure {
+    fn from(e: E) -> Self {
+        RolloverFailure(StallReason::Error, e.into())
     }
-    
-    Ok(episodes)
 }
 
-fn add_program(db: &mut Database, directory: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let dir_path = PathBuf::from(directory);
-    // Convert to absolute path
-    let abs_dir_path = if dir_path.is_absolute() {
-        dir_path
-    } else {
-        // Get current directory and join with the relative path
-        let current_dir = env::current_dir()?;
-        current_dir.join(&dir_path)
-    };
-    
-    // Check if the directory exists
-    if !abs_dir_path.exists() || !abs_dir_path.is_dir() {
-        return Err("Directory does not exist or is not a directory".into());
-    }
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ActiveLink {
+    path: PathBuf,
+    // Episode file (or part) the link points at
+    target: PathBuf,
+    created: DateTime<Utc>,
+}
 
-    // Get the canonical path to resolve any symlinks and ensure it's absolute
-    let canonical_dir_path = fs::canonicalize(&abs_dir_path)?;
+// How symlinks are named. The template's {name}, {ep}, {season} and
+// {original} are the program name, episode number, season and file name.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct LinkNaming {
+    // None is DEFAULT_LINK_TEMPLATE
+    template: Option<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    on_duplicate: DuplicateLinkNames,
+    // Put each program's links in a subdirectory named after it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    program_dirs: bool,
+    // Stands in for spaces in names and for characters file names can't
+    // hold, None for '_'
+    #[serde(default)]
+    replacement: Option<char>,
+}
 
-    let name = canonical_dir_path.file_name()
-        .ok_or("Invalid directory name")?
-        .to_string_lossy()
-        .to_string();
-    
-    let hash = generate_hash(&name);
-    let episodes = scan_episodes(&canonical_dir_path)?;
-    
-    if episodes.is_empty() {
-        return Err("No video files found in directory".into());
+impl LinkNaming {
+    fn template(&self) -> &str {
+        self.template.as_deref().unwrap_or(DEFAULT_LINK_TEMPLATE)
     }
 
-    let program = Program {
-        name: name.clone(),
-        hash: hash.clone(),
-        directory: canonical_dir_path,
-        episodes,
-        current_episode: 0,
-        start_date: None,
-        last_update: None,
-        status: ProgramStatus::Ready,
-    };
+    fn replacement(&self) -> char {
+        self.replacement.filter(|&c| valid_replacement(c)).unwrap_or('_')
+    }
+}
 
-    db.programs.insert(hash.clone(), program);
-    println!("Added program '{}' with hash '{}'", name, hash);
-    Ok(())
+// What to do when the template names several episodes of a program alike,
+// e.g. {original} with S01E01.mkv and S02E01.mkv
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+enum DuplicateLinkNames {
+    // Refuse the template, and refuse to update programs it would clash in
+    #[default]
+    Error,
+    // Append the season to every clashing name
+    AppendSeason,
 }
 
-fn list_programs(db: &Database, filter: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let status_filter = match filter {
-        "running" => Some(ProgramStatus::Running),
-        "ran" => Some(ProgramStatus::Finished),
-        "ready" => Some(ProgramStatus::Ready),
-        "stopped" => Some(ProgramStatus::Stopped),
-        _ => None,
-    };
+// What happens to the last episode's links once a program finishes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OnFinishSymlink {
+    // The final episode stays on air indefinitely
+    #[default]
+    Keep,
+    // The slot is emptied
+    Remove,
+}
 
-    for program in db.programs.values() {
-        if let Some(ref filter_status) = status_filter {
-            if &program.status != filter_status {
-                continue;
-            }
+// Commands run around every rollover. A failing pre hook vetoes the rollover.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Hooks {
+    pre: Option<String>,
+    post: Option<String>,
+    // Run when a program reaches a milestone, with QC_MILESTONE set
+    #[serde(default)]
+    milestone: Option<String>,
+}
+
+// A point in a program's run worth a notification, fired once per program
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Milestone {
+    // This share of the episodes has aired
+    Percent(u32),
+    // Only the finale is left to air
+    FinaleNext,
+}
+
+const DEFAULT_MILESTONES: &[Milestone] = &[Milestone::Percent(50), Milestone::FinaleNext];
+
+impl Milestone {
+    fn parse(raw: &str) -> Result<Milestone, String> {
+        if raw.eq_ignore_ascii_case("finale") {
+            return Ok(Milestone::FinaleNext);
+        }
+        match raw.trim_end_matches('%').parse::<u32>() {
+            Ok(percent) if (1..=99).contains(&percent) => Ok(Milestone::Percent(percent)),
+            _ => Err(format!("expected a percentage from 1% to 99% or 'finale', got '{}'", raw)),
+        }
+    }
+
+    // Value of QC_MILESTONE
+    fn key(self) -> String {
+        match self {
+            Milestone::Percent(percent) => format!("percent_{}", percent),
+            Milestone::FinaleNext => "finale_next".to_string(),
+        }
+    }
+
+    // The current_episode at which a program of `total` episodes reaches
+    // it, None if it never does
+    fn position(self, total: usize) -> Option<usize> {
+        let position = match self {
+            Milestone::Percent(percent) => (total * percent as usize).div_ceil(100),
+            Milestone::FinaleNext => total.checked_sub(1)?,
+        };
+        (1..total).contains(&position).then_some(position)
+    }
+
+    fn describe(self, program: &Program) -> String {
+        match self {
+            Milestone::Percent(percent) => format!("{} is {}% through ({} of {} episodes aired)",
+                program.name, percent, program.current_episode, program.episodes.len()),
+            Milestone::FinaleNext => format!("{} has one episode left, the finale airs next", program.name),
         }
-        
-        println!("{} [{}] ({}/{} episodes) - {:?}", 
-            program.hash, 
-            program.name,
-            program.current_episode,
-            program.episodes.len(),
-            program.status
-        );
     }
-    Ok(())
 }
 
-fn should_rollover(last_update: Option<DateTime<Utc>>) -> bool {
-    match last_update {
-        None => true, // First time, always rollover
-        Some(last) => {
-            let now = Utc::now();
-            let days_since = now.signed_duration_since(last).num_days();
-            days_since >= 7
+impl std::fmt::Display for Milestone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Milestone::Percent(percent) => write!(f, "{}%", percent),
+            Milestone::FinaleNext => write!(f, "finale next"),
         }
     }
 }
 
-fn update_program_symlink(db: &mut Database, program_hash: &str, force: bool) -> Result<(), Box<dyn std::error::Error>> {
-    let symlink_dir = db.symlink_dir.as_ref()
-        .ok_or("Symlink directory not configured. Use 'queuecast config symlink-dir <path>' to set it.")?;
+// Configured milestones in the order a program reaches them; ties go in
+// the configured order, so a short program fires each once, halfway
+// before the finale
+fn milestones_in_order(milestones: &[Milestone], total: usize) -> Vec<(usize, Milestone)> {
+    let mut ordered: Vec<(usize, Milestone)> = milestones.iter()
+        .filter_map(|&milestone| Some((milestone.position(total)?, milestone)))
+        .collect();
+    ordered.sort_by_key(|&(position, _)| position);
+    ordered
+}
 
-    let program = db.programs.get_mut(program_hash)
-        .ok_or("Program not found")?;
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct ProgramHooks {
+    #[serde(default)]
+    pre: HookOverride,
+    #[serde(default)]
+    post: HookOverride,
+}
 
-    // Create symlink directory if it doesn't exist
-    fs::create_dir_all(symlink_dir)?;
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+enum HookOverride {
+    // Use the global hook, if any
+    #[default]
+    Inherit,
+    // Run nothing, even when a global hook is configured
+    Disabled,
+    Command(String),
+}
 
-    // Start the program if it's ready
-    if program.status == ProgramStatus::Ready {
-        program.status = ProgramStatus::Running;
-        program.start_date = Some(Utc::now());
+impl HookOverride {
+    fn resolve<'a>(&'a self, global: Option<&'a str>) -> Option<&'a str> {
+        match self {
+            HookOverride::Inherit => global,
+            HookOverride::Disabled => None,
+            HookOverride::Command(command) => Some(command),
+        }
     }
+}
 
-    if program.status != ProgramStatus::Running {
-        return Ok(()); // Skip non-running programs
+fn default_interval_days() -> u32 {
+    7
+}
+
+// Time between rollovers as given to add: "2d", "6h" or a bare number of days.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Interval {
+    Days(u32),
+    Hours(u32),
+}
+
+impl Interval {
+    fn parse(text: &str) -> Result<Interval, String> {
+        let text = text.trim();
+        let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+            Some(at) => text.split_at(at),
+            None => (text, "d"),
+        };
+        let count = number.parse::<u32>().ok().filter(|&n| n > 0)
+            .ok_or_else(|| format!("Invalid interval '{}', expected e.g. 2d or 6h", text))?;
+        match unit.trim().to_ascii_lowercase().as_str() {
+            "d" | "day" | "days" => Ok(Interval::Days(count)),
+            // Whole days are stored as days, so the program looks like any other
+            "h" | "hour" | "hours" if count % 24 == 0 => Ok(Interval::Days(count / 24)),
+            "h" | "hour" | "hours" if count <= u32::MAX / 3600 => Ok(Interval::Hours(count)),
+            _ => Err(format!("Invalid interval '{}', expected e.g. 2d or 6h", text)),
+        }
     }
 
-    // Check if we should rollover to next episode
-    if !force && !should_rollover(program.last_update) {
-        return Ok(()); // Not time to rollover yet
+    fn interval_days(self) -> u32 {
+        match self {
+            Interval::Days(days) => days,
+            Interval::Hours(hours) => hours.div_ceil(24),
+        }
     }
 
-    // Check if we have more episodes
-    if program.current_episode >= program.episodes.len() {
-        program.status = ProgramStatus::Finished;
-        return Ok(());
+    fn interval_secs(self) -> Option<u32> {
+        match self {
+            Interval::Days(_) => None,
+            Interval::Hours(hours) => Some(hours * 3600),
+        }
     }
+}
 
-    let episode = &program.episodes[program.current_episode];
-    let symlink_path = symlink_dir.join(format!("{}_ep{:02}.{}", 
-        program.name.replace(" ", "_"),
-        episode.episode_number,
-        episode.path.extension().unwrap_or_default().to_string_lossy()
-    ));
+// The program's time between regular rollovers
+fn interval(program: &Program) -> chrono::Duration {
+    match program.interval_secs {
+        Some(secs) => chrono::Duration::seconds(i64::from(secs.max(1))),
+        None => chrono::Duration::days(i64::from(program.interval_days.max(1))),
+    }
+}
 
-    // Remove existing symlink if it exists
-    if symlink_path.exists() {
-        fs::remove_file(&symlink_path)?;
+fn describe_interval(program: &Program) -> String {
+    match program.interval_secs {
+        Some(secs) if secs % 3600 == 0 => format!("every {} hour(s)", secs / 3600),
+        Some(secs) => format!("every {} second(s)", secs),
+        None => format!("every {} day(s)", program.interval_days),
     }
+}
 
-    // Create new symlink
-    #[cfg(unix)]
-    std::os::unix::fs::symlink(&episode.path, &symlink_path)?;
-    #[cfg(windows)]
-    std::os::windows::fs::symlink_file(&episode.path, &symlink_path)?;
-    
-    println!("Created symlink for {} episode {}", program.name, episode.episode_number);
-    
-    // Advance to next episode and update timestamp
-    program.current_episode += 1;
-    program.last_update = Some(Utc::now());
-    
-    Ok(())
+// Per-program settings chosen when adding a directory.
+#[derive(Debug, Clone)]
+struct AddOptions {
+    name: Option<String>,
+    interval: Interval,
+    minutes_per_rollover: Option<u32>,
+    pause_between_seasons: bool,
+    airing_days: Vec<Weekday>,
+    sort: EpisodeSort,
+    folder_episodes: bool,
+    adopt: Option<PathBuf>,
+    from_archive: bool,
+    // Add even when the program overlaps the symlink directory
+    force: bool,
+    extensions: Vec<String>,
+    // Refuse to add when the episode numbering has gaps or duplicates
+    strict: bool,
+    // Print the episodes that would be added instead of adding them
+    dry_run: bool,
+    // Start before the programs already waiting
+    queue_top: bool,
+    batch_size: Option<u32>,
+    keep_window: Option<u32>,
 }
 
-fn update_symlinks(db: &mut Database, program_hash: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
-    match program_hash {
-        Some(hash) => {
-            // Update specific program, force rollover
-            update_program_symlink(db, hash, true)?;
-        }
-        None => {
-            // Update all running programs, respect weekly schedule
-            let program_hashes: Vec<String> = db.programs.keys().cloned().collect();
-            for hash in program_hashes {
-                if let Err(e) = update_program_symlink(db, &hash, false) {
-                    eprintln!("Error updating program {}: {}", hash, e);
-                }
-            }
+impl Default for AddOptions {
+    fn default() -> Self {
+        AddOptions {
+            name: None,
+            interval: Interval::Days(default_interval_days()),
+            minutes_per_rollover: None,
+            pause_between_seasons: false,
+            airing_days: Vec::new(),
+            sort: EpisodeSort::Natural,
+            folder_episodes: false,
+            adopt: None,
+            from_archive: false,
+            force: false,
+            extensions: Vec::new(),
+            strict: false,
+            dry_run: false,
+            queue_top: false,
+            batch_size: None,
+            keep_window: None,
         }
     }
-    Ok(())
 }
 
-fn remove_program(db: &mut Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
-    if let Some(program) = db.programs.remove(program_hash) {
-        println!("Removed program '{}'", program.name);
-    } else {
-        return Err("Program not found".into());
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+enum EpisodeSort {
+    // Programs stored before sort keys existed were ordered by plain filename
+    #[default]
+    Name,
+    Natural,
+    Mtime,
+    Ctime,
+}
+
+impl EpisodeSort {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "name" => Some(EpisodeSort::Name),
+            "natural" => Some(EpisodeSort::Natural),
+            "mtime" => Some(EpisodeSort::Mtime),
+            "ctime" => Some(EpisodeSort::Ctime),
+            _ => None,
+        }
     }
-    Ok(())
 }
 
-fn stop_program(db: &mut Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let program = db.programs.get_mut(program_hash)
-        .ok_or("Program not found")?;
-    
-    program.status = ProgramStatus::Stopped;
-    println!("Stopped program '{}'", program.name);
-    Ok(())
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+enum ProgramStatus {
+    Ready,
+    Running,
+    Finished,
+    Stopped,
+    AwaitingConfirmation,
 }
 
-fn set_symlink_dir(db: &mut Database, path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let dir_path = PathBuf::from(path);
-    
-    // Create directory if it doesn't exist
-    fs::create_dir_all(&dir_path)?;
-    
-    db.symlink_dir = Some(dir_path.clone());
-    println!("Set symlink directory to: {}", dir_path.display());
-    Ok(())
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct HistoryEntry {
+    // Derived from the other fields, so the same airing has the same id everywhere
+    #[serde(default)]
+    id: String,
+    program_hash: String,
+    program_name: String,
+    episode_number: usize,
+    path: PathBuf,
+    aired_at: DateTime<Utc>,
+    // Merged in from another machine by history import
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    imported: bool,
 }
 
-fn skip_episodes(db: &mut Database, program_hash: &str, count: usize) -> Result<(), Box<dyn std::error::Error>> {
-    let program = db.programs.get_mut(program_hash)
-        .ok_or("Program not found")?;
-    
-    program.current_episode = (program.current_episode + count).min(program.episodes.len());
-    println!("Skipped {} episodes for program '{}'", count, program.name);
-    Ok(())
+impl HistoryEntry {
+    fn new(program_hash: String, program_name: String, episode_number: usize, path: PathBuf, aired_at: DateTime<Utc>) -> Self {
+        let mut entry = HistoryEntry {
+            id: String::new(),
+            program_hash,
+            program_name,
+            episode_number,
+            path,
+            aired_at,
+            imported: false,
+        };
+        entry.id = entry.stable_id();
+        entry
+    }
+
+    fn stable_id(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.program_hash.as_bytes());
+        hasher.update(self.episode_number.to_le_bytes());
+        hasher.update(self.path.to_string_lossy().as_bytes());
+        hasher.update(self.aired_at.to_rfc3339().as_bytes());
+        format!("{:x}", hasher.finalize())[..16].to_string()
+    }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let matches = Command::new("queuecast")
-        .version("0.1.0")
-        .about("Manage TV show files with weekly scheduling")
-        .subcommand(
-            Command::new("add")
-                .about("Add directory to database")
-                .arg(Arg::new("directory").required(true))
-        )
-        .subcommand(
-            Command::new("list")
-                .about("List programs")
-                .arg(Arg::new("filter").value_parser(["running", "ran", "ready", "stopped"]))
-        )
-        .subcommand(
-            Command::new("update")
-                .about("Update symlinks for programs (all programs by default, or specific program)")
-                .arg(Arg::new("program").required(false))
-        )
-        .subcommand(
-            Command::new("remove")
-                .about("Remove program from database")
-                .arg(Arg::new("program").required(true))
-        )
-        .subcommand(
-            Command::new("stop")
-                .about("Stop program from broadcasting")
-                .arg(Arg::new("program").required(true))
-        )
-        .subcommand(
-            Command::new("skip")
+const HISTORY_EXPORT_FORMAT: &str = "queuecast-history";
+const HISTORY_EXPORT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct HistoryExport {
+    format: String,
+    version: u32,
+    // Event paths under the exporting database's path root are relative to
+    // it, and put under this database's root on import
+    #[serde(default)]
+    relative_paths: bool,
+    events: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+struct Database {
+    // Layout version the file was written with, absent before versioning
+    #[serde(default)]
+    schema_version: u32,
+    programs: HashMap<String, Program>,
+    symlink_dir: Option<PathBuf>,
+    #[serde(default)]
+    history: Vec<HistoryEntry>,
+    #[serde(default)]
+    collections: HashMap<String, BTreeSet<String>>,
+    // How often members come round in an interleaved channel, by collection
+    // and program hash; members not listed weigh 1
+    #[serde(default)]
+    collection_weights: HashMap<String, BTreeMap<String, u32>>,
+    #[serde(default)]
+    nextup_dir: Option<PathBuf>,
+    // JSON summary of what's on air, rewritten by every update
+    #[serde(default)]
+    dashboard_file: Option<PathBuf>,
+    // Date ranges when scheduled updates air nothing, sorted by start
+    #[serde(default)]
+    blackouts: Vec<Blackout>,
+    #[serde(default)]
+    archive_dir: Option<PathBuf>,
+    // URL the served folder is reachable under, for playlist entries
+    #[serde(default)]
+    serve_url: Option<String>,
+    // Folder served at serve_url, None for the symlink directory
+    #[serde(default)]
+    serve_path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    archive_per_program: bool,
+    #[serde(default)]
+    hooks: Hooks,
+    // What counts as a milestone, None for DEFAULT_MILESTONES
+    #[serde(default)]
+    milestones: Option<Vec<Milestone>>,
+    // Words marking a file as one part of an episode, None for DEFAULT_PART_MARKERS
+    #[serde(default)]
+    part_markers: Option<Vec<String>>,
+    // Suffixes of the files a download client writes next to an episode
+    // until it's complete, None for DEFAULT_DOWNLOAD_MARKERS
+    #[serde(default)]
+    download_markers: Option<Vec<String>>,
+    // Seconds between two size samples of the episodes about to air, None
+    // for DEFAULT_DOWNLOAD_SETTLE_SECONDS, 0 to skip the check
+    #[serde(default)]
+    download_settle_seconds: Option<u32>,
+    #[serde(default)]
+    link_naming: LinkNaming,
+    // Template for a .nfo written next to every new symlink, None disables them
+    #[serde(default)]
+    nfo_template: Option<String>,
+    // Source paths under this folder are stored relative to it. A relative
+    // root is taken from the folder holding the database.
+    #[serde(default)]
+    path_root: Option<PathBuf>,
+    // strftime format for times shown to the user, None for DEFAULT_DATE_FORMAT
+    #[serde(default)]
+    date_format: Option<String>,
+    // Touch <link>.ready after each new symlink
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    ready_markers: bool,
+    // Glob patterns for file names that are never episodes, in any program
+    #[serde(default)]
+    ignore_patterns: Vec<String>,
+    // Interval for new programs when add is given no --interval
+    #[serde(default)]
+    default_interval: Option<u32>,
+    // Values for add's other flags when they aren't given, by flag name;
+    // see ADD_DEFAULT_KEYS
+    #[serde(default)]
+    add_defaults: BTreeMap<String, String>,
+    // IANA zone for weekdays and displayed times, None for the system's
+    #[serde(default)]
+    timezone: Option<String>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    on_finish_symlink: OnFinishSymlink,
+    // Bytes to keep free where archive episodes are extracted and remote
+    // ones downloaded, None to not check
+    #[serde(default)]
+    min_free_bytes: Option<u64>,
+    // Delete a program's oldest copies that are off air when space runs short
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    prune_for_space: bool,
+    // Hours after a rollover during which forcing another needs
+    // --really-force, None for DEFAULT_FORCE_COOLDOWN_HOURS, 0 for never
+    #[serde(default)]
+    force_cooldown_hours: Option<u32>,
+    // Seconds after a rollover during which forcing the program again is a
+    // no-op without --really-force, None for DEFAULT_FORCE_GRACE_SECONDS
+    #[serde(default)]
+    force_grace_seconds: Option<u32>,
+    // Hex digits in the hash of newly added programs, None for 8
+    #[serde(default)]
+    hash_length: Option<usize>,
+    // Programs running at once, Ready ones wait in the queue for a slot;
+    // None for no limit
+    #[serde(default)]
+    max_running: Option<u32>,
+    // Episodes the scheduled updates air per week (Monday to Sunday) across
+    // all programs, None for no limit
+    #[serde(default)]
+    weekly_budget: Option<u32>,
+    // Environment overrides applied on load, undone again on save
+    #[serde(skip)]
+    env_overrides: Vec<EnvOverride>,
+    // Settings the running command set, kept on save even where an
+    // environment variable overrides them
+    #[serde(skip)]
+    set_by_command: Vec<&'static str>,
+    // The file as loaded, so save can tell whether anything changed
+    #[serde(skip)]
+    on_disk: Option<serde_json::Value>,
+    // Rollovers journaled since loading, cleared from disk by save
+    #[serde(skip)]
+    intents: Vec<RolloverIntent>,
+    // End of the last scheduled update pass that finished without errors
+    #[serde(default)]
+    last_successful_update: Option<DateTime<Utc>>,
+}
+
+impl Database {
+    // The database is state, not configuration, so it lives in the state
+    // directory. One left in the config directory by older versions is moved
+    // over by load.
+    fn get_database_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        #[cfg(test)]
+        if let Some(dir) = TEST_DATA_DIR.with(|dir| dir.borrow().clone()) {
+            return Ok(dir.join("queuecast.json"));
+        }
+        if let Some(portable) = PORTABLE.get() {
+            fs::create_dir_all(&portable.data_dir)?;
+            return Ok(portable.data_dir.join("queuecast.json"));
+        }
+
+        let state_dir = state_dir()?;
+        fs::create_dir_all(&state_dir)?;
+        Ok(state_dir.join("queuecast.json"))
+    }
+
+    // The file to load: the database in the state directory, or one older
+    // versions left in a config directory, which is moved over first
+    fn locate_database() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let path = Self::get_database_path()?;
+        #[cfg(test)]
+        if TEST_DATA_DIR.with(|dir| dir.borrow().is_some()) {
+            return Ok(path);
+        }
+        if path.exists() || PORTABLE.get().is_some() {
+            return Ok(path);
+        }
+        let Some(legacy) = legacy_config_dirs()?.into_iter()
+            .map(|dir| dir.join("queuecast.json"))
+            .find(|legacy| legacy.exists()) else {
+            return Ok(path);
+        };
+        // --no-save promises not to touch the file, so read it where it is
+        if simulating() {
+            return Ok(legacy);
+        }
+        migrate_legacy_database(&legacy, &path)?;
+        Ok(path)
+    }
+
+    fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let config_path = Self::locate_database()?;
+        let mut db: Database = if config_path.exists() {
+            let content = fs::read_to_string(&config_path)?;
+            let value: serde_json::Value = serde_json::from_str(&content)
+                .map_err(|e| format!("Could not parse {}: {}", config_path.display(), e))?;
+            let mut db: Database = serde_json::from_value(value.clone())
+                .map_err(|e| format!("Could not parse {}: {}", config_path.display(), e))?;
+            db.on_disk = Some(value);
+            db
+        } else {
+            Database::default()
+        };
+        if db.schema_version > SCHEMA_VERSION {
+            return Err(format!("{} was written by a newer queuecast (schema version {}, this build supports {})",
+                config_path.display(), db.schema_version, SCHEMA_VERSION).into());
+        }
+        let loaded_version = db.schema_version;
+        db.schema_version = SCHEMA_VERSION;
+        for entry in db.history.iter_mut().filter(|entry| entry.id.is_empty()) {
+            entry.id = entry.stable_id();
+        }
+        if let Some(root) = db.resolved_path_root(&config_path) {
+            db.map_paths(|path| root.join(path));
+        }
+        db.apply_env_overrides()?;
+        if loaded_version < 2 {
+            db.backfill_active_links();
+        }
+        let date_format = match db.date_format.as_deref().map(|format| (format, check_date_format(format))) {
+            Some((format, Ok(()))) => format.to_string(),
+            Some((_, Err(e))) => {
+                eprintln!("Ignoring date_format: {}", e);
+                DEFAULT_DATE_FORMAT.to_string()
+            }
+            None => DEFAULT_DATE_FORMAT.to_string(),
+        };
+        *DATE_FORMAT.write().unwrap_or_else(|e| e.into_inner()) = Some(date_format);
+        let zone = match db.timezone.as_deref().map(Zone::parse) {
+            Some(Ok(zone)) => zone,
+            Some(Err(e)) => {
+                eprintln!("Ignoring timezone: {}", e);
+                Zone::System
+            }
+            None => Zone::System,
+        };
+        *DEFAULT_ZONE.write().unwrap_or_else(|e| e.into_inner()) = Some(zone);
+        recover_interrupted_rollovers(&db)?;
+        Ok(db)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let config_path = Self::get_database_path()?;
+        let mut db = self.without_env_overrides()?;
+        if let Some(root) = db.resolved_path_root(&config_path) {
+            db.map_paths(|path| path.strip_prefix(&root).unwrap_or(path).to_path_buf());
+        }
+        let value = serde_json::to_value(&db)?;
+        if self.on_disk.as_ref() == Some(&value) {
+            return self.settle_journal();
+        }
+        if NO_SAVE.load(AtomicOrdering::SeqCst) {
+            eprintln!("Not saving changes to {}, this was a dry run", config_path.display());
+            return Ok(());
+        }
+
+        // Written under a temporary name and renamed over, so a crash
+        // mid-write leaves the previous database rather than half of one
+        let content = serde_json::to_string_pretty(&value)?;
+        let tmp = config_path.with_extension("json.tmp");
+        match fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, &config_path)) {
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::ReadOnlyFilesystem | std::io::ErrorKind::PermissionDenied) => {
+                Err(format!("Could not save changes, {} is read-only ({})", config_path.display(), e).into())
+            }
+            result => {
+                result?;
+                self.settle_journal()
+            }
+        }
+    }
+
+    // Journaled rollovers whose progress is on disk now stand; the rest stay
+    // journaled for recovery to undo
+    fn settle_journal(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.intents.is_empty() {
+            return Ok(());
+        }
+        let pending: Vec<RolloverIntent> = self.intents.iter()
+            .filter(|intent| self.programs.get(&intent.program_hash).is_some_and(|program| program.current_episode < intent.to))
+            .cloned()
+            .collect();
+        if pending.is_empty() {
+            remove_journal()
+        } else {
+            write_journal(&pending)
+        }
+    }
+
+    // Replace file values with QUEUECAST_* environment variables that are set
+    fn apply_env_overrides(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut value = serde_json::to_value(&*self)?;
+        let mut overrides = Vec::new();
+        for setting in SETTINGS {
+            let Ok(raw) = env::var(setting.env) else {
+                continue;
+            };
+            let env_value = setting.kind.parse(&raw)
+                .map_err(|e| format!("Invalid {}: {}", setting.env, e))?;
+            let file_value = value.pointer(setting.pointer).cloned().unwrap_or_default();
+            set_json_pointer(&mut value, setting.pointer, env_value);
+            overrides.push(EnvOverride { pointer: setting.pointer, file_value, loaded_value: serde_json::Value::Null });
+        }
+        if !overrides.is_empty() {
+            let on_disk = self.on_disk.take();
+            *self = serde_json::from_value(value)?;
+            self.on_disk = on_disk;
+            // What the environment's values load as, to tell them from
+            // values changed later
+            let loaded = serde_json::to_value(&*self)?;
+            for o in &mut overrides {
+                o.loaded_value = loaded.pointer(o.pointer).cloned().unwrap_or_default();
+            }
+            self.env_overrides = overrides;
+        }
+        Ok(())
+    }
+
+    // Record that the command set a setting, whatever its value
+    fn mark_set(&mut self, pointer: &'static str) {
+        if !self.set_by_command.contains(&pointer) {
+            self.set_by_command.push(pointer);
+        }
+    }
+
+    // Copy of the database with environment overrides swapped back for the
+    // file's values. Settings the command set, or changed some other way
+    // since loading, keep their new value.
+    fn without_env_overrides(&self) -> Result<Database, Box<dyn std::error::Error>> {
+        if self.env_overrides.is_empty() {
+            return Ok(self.clone());
+        }
+        let mut value = serde_json::to_value(self)?;
+        for o in &self.env_overrides {
+            // Settings at their default aren't written, so a missing key is null
+            if self.set_by_command.contains(&o.pointer) || value.pointer(o.pointer).cloned().unwrap_or_default() != o.loaded_value {
+                continue;
+            }
+            if o.file_value.is_null() {
+                remove_json_pointer(&mut value, o.pointer);
+            } else {
+                set_json_pointer(&mut value, o.pointer, o.file_value.clone());
+            }
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    // Folder that stored paths are relative to: the volume in portable mode,
+    // otherwise the configured path root
+    fn resolved_path_root(&self, config_path: &Path) -> Option<PathBuf> {
+        if let Some(portable) = PORTABLE.get() {
+            return Some(portable.root.clone());
+        }
+        let root = config_path.parent()?.join(self.path_root.as_ref()?);
+        // Must match the canonical paths episodes are stored with
+        Some(fs::canonicalize(&root).unwrap_or(root))
+    }
+
+    // Databases from before schema 2 only recorded the latest rollover's links.
+    // Rebuild the list from history and the current naming scheme, keeping
+    // older links only if they are still on disk.
+    fn backfill_active_links(&mut self) {
+        let Some(symlink_dir) = &self.symlink_dir else {
+            return;
+        };
+        for program in self.programs.values_mut() {
+            if !program.active_links.is_empty() {
+                continue;
+            }
+
+            let latest = last_aired_batch(&self.history, &program.hash);
+            let latest_at = latest.first().map(|entry| entry.aired_at);
+            let mut aired: Vec<&HistoryEntry> = self.history.iter()
+                .filter(|entry| entry.program_hash == program.hash && !entry.imported)
+                .collect();
+            aired.sort_by_key(|entry| entry.aired_at);
+
+            let mut links: Vec<ActiveLink> = Vec::new();
+            for entry in aired {
+                let Some(episode) = program.episodes.iter().find(|e| e.episode_number == entry.episode_number) else {
+                    continue;
+                };
+                let is_latest = Some(entry.aired_at) == latest_at;
+                for (target, path) in episode_links(symlink_dir, &self.link_naming, program, episode) {
+                    let on_disk = fs::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink());
+                    if is_latest || on_disk {
+                        links.retain(|link| link.path != path);
+                        links.push(ActiveLink { path, target: target.to_path_buf(), created: entry.aired_at });
+                    }
+                }
+            }
+
+            // The recorded names of the latest rollover beat the current template
+            let latest_links: Vec<usize> = links.iter().enumerate()
+                .filter(|(_, link)| Some(link.created) == latest_at)
+                .map(|(i, _)| i)
+                .collect();
+            if latest_links.len() == program.last_symlinks.len() {
+                for (i, recorded) in latest_links.into_iter().zip(&program.last_symlinks) {
+                    links[i].path = recorded.clone();
+                }
+            }
+            program.active_links = links;
+        }
+    }
+
+    // Apply f to every filesystem path stored in the database
+    fn map_paths(&mut self, f: impl Fn(&Path) -> PathBuf) {
+        for dir in [&mut self.symlink_dir, &mut self.nextup_dir, &mut self.dashboard_file, &mut self.archive_dir, &mut self.serve_path].into_iter().flatten() {
+            *dir = f(dir);
+        }
+        for program in self.programs.values_mut() {
+            program.directory = f(&program.directory);
+            for episode in &mut program.episodes {
+                episode.path = f(&episode.path);
+                for part in &mut episode.extra_parts {
+                    *part = f(part);
+                }
+            }
+            for link in &mut program.last_symlinks {
+                *link = f(link);
+            }
+            for link in &mut program.active_links {
+                link.path = f(&link.path);
+                link.target = f(&link.target);
+            }
+        }
+        for entry in &mut self.history {
+            entry.path = f(&entry.path);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EnvOverride {
+    pointer: &'static str,
+    file_value: serde_json::Value,
+    loaded_value: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SettingKind {
+    Path,
+    Text,
+    Bool,
+    Days,
+    Hours,
+    Size,
+    Choice(&'static [&'static str]),
+    DateFormat,
+    TimeZone,
+    HashLength,
+    Count,
+    Seconds,
+}
+
+impl SettingKind {
+    fn parse(self, raw: &str) -> Result<serde_json::Value, String> {
+        match self {
+            SettingKind::Path | SettingKind::Text => Ok(serde_json::Value::from(raw)),
+            SettingKind::Bool => match raw.to_ascii_lowercase().as_str() {
+                "1" | "true" | "yes" | "on" => Ok(serde_json::Value::from(true)),
+                "0" | "false" | "no" | "off" => Ok(serde_json::Value::from(false)),
+                _ => Err(format!("expected true or false, got '{}'", raw)),
+            },
+            SettingKind::DateFormat => {
+                check_date_format(raw)?;
+                Ok(serde_json::Value::from(raw))
+            }
+            SettingKind::TimeZone => {
+                Zone::parse(raw)?;
+                Ok(serde_json::Value::from(raw))
+            }
+            SettingKind::HashLength => match raw.parse::<usize>() {
+                Ok(length) if HASH_LENGTHS.contains(&length) => Ok(serde_json::Value::from(length)),
+                _ => Err(format!("expected a length from {} to {}, got '{}'", HASH_LENGTHS.start(), HASH_LENGTHS.end(), raw)),
+            },
+            SettingKind::Choice(choices) if choices.contains(&raw) => Ok(serde_json::Value::from(raw)),
+            SettingKind::Choice(choices) => Err(format!("expected one of {}, got '{}'", choices.join(", "), raw)),
+            SettingKind::Size => Ok(serde_json::Value::from(parse_size(raw)?)),
+            SettingKind::Hours => match raw.parse::<u32>() {
+                Ok(hours) => Ok(serde_json::Value::from(hours)),
+                _ => Err(format!("expected a whole number of hours, got '{}'", raw)),
+            },
+            SettingKind::Seconds => match raw.parse::<u32>() {
+                Ok(seconds) => Ok(serde_json::Value::from(seconds)),
+                _ => Err(format!("expected a whole number of seconds, got '{}'", raw)),
+            },
+            SettingKind::Count => match raw.parse::<u32>() {
+                Ok(count) if count >= 1 => Ok(serde_json::Value::from(count)),
+                _ => Err(format!("expected a whole number of at least 1, got '{}'", raw)),
+            },
+            SettingKind::Days => match raw.parse::<u32>() {
+                Ok(days) if days >= 1 => Ok(serde_json::Value::from(days)),
+                _ => Err(format!("expected a whole number of days of at least 1, got '{}'", raw)),
+            },
+        }
+    }
+}
+
+// A configuration key as seen by 'config show' and the environment
+struct Setting {
+    name: &'static str,
+    // Location in the database file
+    pointer: &'static str,
+    env: &'static str,
+    kind: SettingKind,
+    // Shown when neither the file nor the environment sets it
+    default: &'static str,
+}
+
+// Settings resolve as defaults, then the database file, then the
+// environment, then command line flags where a command has one.
+const SETTINGS: &[Setting] = &[
+    Setting { name: "symlink-dir", pointer: "/symlink_dir", env: "QUEUECAST_SYMLINK_DIR", kind: SettingKind::Path, default: "(unset)" },
+    Setting { name: "nextup-dir", pointer: "/nextup_dir", env: "QUEUECAST_NEXTUP_DIR", kind: SettingKind::Path, default: "(disabled)" },
+    Setting { name: "dashboard-file", pointer: "/dashboard_file", env: "QUEUECAST_DASHBOARD_FILE", kind: SettingKind::Path, default: "(disabled)" },
+    Setting { name: "archive-dir", pointer: "/archive_dir", env: "QUEUECAST_ARCHIVE_DIR", kind: SettingKind::Path, default: "(disabled)" },
+    Setting { name: "serve-root-url", pointer: "/serve_url", env: "QUEUECAST_SERVE_ROOT_URL", kind: SettingKind::Text, default: "(disabled)" },
+    Setting { name: "serve-root-path", pointer: "/serve_path", env: "QUEUECAST_SERVE_ROOT_PATH", kind: SettingKind::Path, default: "(symlink dir)" },
+    Setting { name: "archive-per-program", pointer: "/archive_per_program", env: "QUEUECAST_ARCHIVE_PER_PROGRAM", kind: SettingKind::Bool, default: "false" },
+    Setting { name: "ready-marker", pointer: "/ready_markers", env: "QUEUECAST_READY_MARKER", kind: SettingKind::Bool, default: "false" },
+    Setting { name: "default-interval", pointer: "/default_interval", env: "QUEUECAST_DEFAULT_INTERVAL", kind: SettingKind::Days, default: "7" },
+    Setting { name: "min-free", pointer: "/min_free_bytes", env: "QUEUECAST_MIN_FREE", kind: SettingKind::Size, default: "(unchecked)" },
+    Setting { name: "min-free-prune", pointer: "/prune_for_space", env: "QUEUECAST_MIN_FREE_PRUNE", kind: SettingKind::Bool, default: "false" },
+    Setting { name: "on-finish-symlink", pointer: "/on_finish_symlink", env: "QUEUECAST_ON_FINISH_SYMLINK", kind: SettingKind::Choice(&["keep", "remove"]), default: "keep" },
+    Setting { name: "force-cooldown", pointer: "/force_cooldown_hours", env: "QUEUECAST_FORCE_COOLDOWN", kind: SettingKind::Hours, default: "20" },
+    Setting { name: "download-settle", pointer: "/download_settle_seconds", env: "QUEUECAST_DOWNLOAD_SETTLE", kind: SettingKind::Seconds, default: "2" },
+    Setting { name: "force-grace", pointer: "/force_grace_seconds", env: "QUEUECAST_FORCE_GRACE", kind: SettingKind::Seconds, default: "60" },
+    Setting { name: "date-format", pointer: "/date_format", env: "QUEUECAST_DATE_FORMAT", kind: SettingKind::DateFormat, default: DEFAULT_DATE_FORMAT },
+    Setting { name: "max-running", pointer: "/max_running", env: "QUEUECAST_MAX_RUNNING", kind: SettingKind::Count, default: "(unlimited)" },
+    Setting { name: "weekly-budget", pointer: "/weekly_budget", env: "QUEUECAST_WEEKLY_BUDGET", kind: SettingKind::Count, default: "(unlimited)" },
+    Setting { name: "hash-length", pointer: "/hash_length", env: "QUEUECAST_HASH_LENGTH", kind: SettingKind::HashLength, default: "8" },
+    Setting { name: "timezone", pointer: "/timezone", env: "QUEUECAST_TIMEZONE", kind: SettingKind::TimeZone, default: "(system)" },
+    Setting { name: "link-template", pointer: "/link_naming/template", env: "QUEUECAST_LINK_TEMPLATE", kind: SettingKind::Text, default: DEFAULT_LINK_TEMPLATE },
+    Setting { name: "link-program-dirs", pointer: "/link_naming/program_dirs", env: "QUEUECAST_LINK_PROGRAM_DIRS", kind: SettingKind::Bool, default: "false" },
+    Setting { name: "hook-pre", pointer: "/hooks/pre", env: "QUEUECAST_HOOK_PRE", kind: SettingKind::Text, default: "(none)" },
+    Setting { name: "hook-post", pointer: "/hooks/post", env: "QUEUECAST_HOOK_POST", kind: SettingKind::Text, default: "(none)" },
+    Setting { name: "hook-milestone", pointer: "/hooks/milestone", env: "QUEUECAST_HOOK_MILESTONE", kind: SettingKind::Text, default: "(none)" },
+];
+
+// Like Value::pointer_mut, but creating missing objects along the way
+fn set_json_pointer(value: &mut serde_json::Value, pointer: &str, new: serde_json::Value) {
+    let mut current = value;
+    for key in pointer.trim_start_matches('/').split('/') {
+        if !current.is_object() {
+            *current = serde_json::Value::Object(serde_json::Map::new());
+        }
+        current = current.as_object_mut().unwrap()
+            .entry(key)
+            .or_insert(serde_json::Value::Null);
+    }
+    *current = new;
+}
+
+// Drop the key a pointer names, so it deserializes to its default
+fn remove_json_pointer(value: &mut serde_json::Value, pointer: &str) {
+    let Some((parent, key)) = pointer.rsplit_once('/') else {
+        return;
+    };
+    if let Some(object) = value.pointer_mut(parent).and_then(|parent| parent.as_object_mut()) {
+        object.remove(key);
+    }
+}
+
+// For settings that are only written once they differ from the default, so
+// the file shows what was configured
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[derive(Serialize)]
+struct ResolvedSetting {
+    // Defaults for new programs are named "default-<add flag>"
+    name: &'static str,
+    value: String,
+    // "default", "file", "env QUEUECAST_..." or "portable"
+    source: String,
+}
+
+// Every setting in effect and where its value comes from. Any value the file
+// holds counts as coming from it, even one equal to the default.
+fn resolved_config(db: &Database) -> Result<Vec<ResolvedSetting>, Box<dyn std::error::Error>> {
+    let value = serde_json::to_value(db)?;
+    let source = |pointer: &str| {
+        if db.env_overrides.iter().any(|o| o.pointer == pointer) {
+            if let Some(setting) = SETTINGS.iter().find(|s| s.pointer == pointer) {
+                return format!("env {}", setting.env);
+            }
+        }
+        let stored = db.on_disk.as_ref().and_then(|file| file.pointer(pointer));
+        match stored {
+            Some(stored) if !stored.is_null() => "file".to_string(),
+            _ => "default".to_string(),
+        }
+    };
+
+    let mut resolved: Vec<ResolvedSetting> = SETTINGS.iter()
+        .map(|setting| {
+            let value = match value.pointer(setting.pointer) {
+                Some(serde_json::Value::String(text)) => text.clone(),
+                Some(serde_json::Value::Null) | None => setting.default.to_string(),
+                Some(other) => other.to_string(),
+            };
+            ResolvedSetting { name: setting.name, value, source: source(setting.pointer) }
+        })
+        .collect();
+
+    // File-only settings, set through their own commands
+    for (key, name) in ADD_DEFAULT_KEYS.iter().filter(|(key, _)| *key != "interval") {
+        let (value, source) = match db.add_defaults.get(*key) {
+            Some(value) => (value.clone(), "file"),
+            None => ("(add's own)".to_string(), "default"),
+        };
+        resolved.push(ResolvedSetting { name, value, source: source.to_string() });
+    }
+    resolved.push(ResolvedSetting {
+        name: "link-on-duplicate",
+        value: match db.link_naming.on_duplicate {
+            DuplicateLinkNames::Error => "error",
+            DuplicateLinkNames::AppendSeason => "season",
+        }.to_string(),
+        source: source("/link_naming/on_duplicate"),
+    });
+    resolved.push(ResolvedSetting {
+        name: "link-replacement",
+        value: db.link_naming.replacement().to_string(),
+        source: source("/link_naming/replacement"),
+    });
+    resolved.push(ResolvedSetting {
+        name: "nfo",
+        value: match db.nfo_template.as_deref() {
+            None => "(disabled)",
+            Some(DEFAULT_NFO_TEMPLATE) => "default template",
+            Some(_) => "custom template",
+        }.to_string(),
+        source: source("/nfo_template"),
+    });
+    resolved.push(match (PORTABLE.get(), &db.path_root) {
+        (Some(portable), _) => ResolvedSetting { name: "path-root", value: portable.root.display().to_string(), source: "portable".to_string() },
+        (None, Some(root)) => ResolvedSetting { name: "path-root", value: root.display().to_string(), source: source("/path_root") },
+        (None, None) => ResolvedSetting { name: "path-root", value: "(absolute paths)".to_string(), source: "default".to_string() },
+    });
+    resolved.push(ResolvedSetting {
+        name: "part-markers",
+        value: match part_markers(db).as_slice() {
+            [] => "(none)".to_string(),
+            markers => markers.join(", "),
+        },
+        source: source("/part_markers"),
+    });
+    resolved.push(ResolvedSetting {
+        name: "download-markers",
+        value: match download_markers(db).as_slice() {
+            [] => "(none)".to_string(),
+            markers => markers.join(", "),
+        },
+        source: source("/download_markers"),
+    });
+    resolved.push(ResolvedSetting {
+        name: "ignore",
+        value: if db.ignore_patterns.is_empty() { "(none)".to_string() } else { db.ignore_patterns.join(", ") },
+        source: source("/ignore_patterns"),
+    });
+    let own_extensions = db.programs.values().filter(|p| !p.extensions.is_empty()).count();
+    resolved.push(ResolvedSetting {
+        name: "extensions",
+        value: if own_extensions == 0 {
+            VIDEO_EXTENSIONS.join(", ")
+        } else {
+            format!("{} ({} program(s) use their own)", VIDEO_EXTENSIONS.join(", "), own_extensions)
+        },
+        source: if own_extensions == 0 { "default" } else { "file" }.to_string(),
+    });
+    let dir_source = |env_var: &str| if PORTABLE.get().is_some() {
+        "portable".to_string()
+    } else if env::var(env_var).is_ok_and(|dir| !dir.is_empty()) {
+        format!("env {}", env_var)
+    } else {
+        "default".to_string()
+    };
+    resolved.push(ResolvedSetting { name: "database", value: Database::get_database_path()?.display().to_string(), source: dir_source("XDG_STATE_HOME") });
+    resolved.push(ResolvedSetting { name: "state-dir", value: state_dir()?.display().to_string(), source: dir_source("XDG_STATE_HOME") });
+    resolved.push(ResolvedSetting { name: "cache-dir", value: cache_dir()?.display().to_string(), source: dir_source("XDG_CACHE_HOME") });
+    resolved.push(ResolvedSetting { name: "daemon-socket", value: daemon_socket_path()?.display().to_string(), source: dir_source("XDG_RUNTIME_DIR") });
+    Ok(resolved)
+}
+
+fn show_config(db: &Database, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let resolved = resolved_config(db)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&resolved)?);
+        return Ok(());
+    }
+    let (defaults, settings): (Vec<&ResolvedSetting>, Vec<&ResolvedSetting>) = resolved.iter()
+        .partition(|setting| setting.name.starts_with("default-"));
+    for setting in settings {
+        println!("  {:<20} {:<40} ({})", setting.name, setting.value, setting.source);
+    }
+    println!("Defaults for new programs:");
+    for setting in defaults {
+        println!("  {:<20} {:<40} ({})", setting.name.trim_start_matches("default-"), setting.value, setting.source);
+    }
+    Ok(())
+}
+
+// Parse "10G", "500M" or a plain number of bytes. Units are powers of 1024.
+fn parse_size(raw: &str) -> Result<u64, String> {
+    let raw = raw.trim();
+    let (number, unit) = match raw.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => raw.split_at(i),
+        None => (raw, ""),
+    };
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("expected a size like 500M or 10G, got '{}'", raw)),
+    };
+    number.parse::<u64>().ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("expected a size like 500M or 10G, got '{}'", raw))
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn set_min_free(db: &mut Database, size: Option<u64>, prune: bool) -> Result<(), Box<dyn std::error::Error>> {
+    db.min_free_bytes = size;
+    db.prune_for_space = prune && size.is_some();
+    db.mark_set("/min_free_bytes");
+    db.mark_set("/prune_for_space");
+    match size {
+        Some(size) => println!("Keeping {} free when extracting or downloading episodes{}", format_size(size),
+            if prune { ", deleting the oldest copies off air to make room" } else { "" }),
+        None => println!("No longer checking free space"),
+    }
+    Ok(())
+}
+
+fn set_max_running(db: &mut Database, max: Option<u32>) -> Result<(), Box<dyn std::error::Error>> {
+    db.max_running = max;
+    db.mark_set("/max_running");
+    match max {
+        Some(max) => println!("At most {} program(s) run at once, the others wait in 'queuecast queue'", max),
+        None => println!("Every Ready program starts at the next update"),
+    }
+    Ok(())
+}
+
+fn set_weekly_budget(db: &mut Database, budget: Option<u32>) -> Result<(), Box<dyn std::error::Error>> {
+    db.weekly_budget = budget;
+    db.mark_set("/weekly_budget");
+    match budget {
+        Some(budget) => println!("Scheduled updates air at most {} episode(s) a week; 'queuecast schedule' shows the weeks ahead", budget),
+        None => println!("No weekly episode budget"),
+    }
+    Ok(())
+}
+
+// Midnight starting the Monday of the week `at` falls in, in the database's zone
+fn week_start(at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let zone = Zone::database();
+    let date = zone.date(at);
+    zone.start_of_day(date.checked_sub_days(Days::new(u64::from(date.weekday().num_days_from_monday())))?)
+}
+
+// Episodes aired here since this week's Monday, forced ones included
+fn aired_this_week(db: &Database) -> usize {
+    let Some(start) = week_start(Utc::now()) else {
+        return 0;
+    };
+    db.history.iter().filter(|entry| !entry.imported && entry.aired_at >= start).count()
+}
+
+// The weekly budget and how much of it is used, if a budget is set and used up
+fn budget_reached(db: &Database) -> Option<(u32, usize)> {
+    let budget = db.weekly_budget?;
+    let aired = aired_this_week(db);
+    (aired >= budget as usize).then_some((budget, aired))
+}
+
+// Episodes the weekly budget has left this week, None without a budget
+fn budget_left(db: &Database) -> Option<usize> {
+    db.weekly_budget.map(|budget| (budget as usize).saturating_sub(aired_this_week(db)))
+}
+
+// Episodes aired and projected per week for the next `weeks` weeks, against
+// the budget. Projections assume every program airs when due, so a week over
+// budget shows the overload the scheduled updates will defer.
+fn show_schedule(db: &Database, weeks: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let now = Utc::now();
+    let this_week = week_start(now).ok_or("Cannot work out the start of this week")?;
+    let weeks = weeks.max(1) as usize;
+    let mut projected: Vec<BTreeMap<&str, usize>> = vec![BTreeMap::new(); weeks];
+    for program in db.programs.values() {
+        for (at, batch) in projected_rollovers(program, &db.blackouts) {
+            let Some(start) = week_start(at) else {
+                continue;
+            };
+            // Weeks aren't always 7 days long in zones with daylight saving
+            let week = ((start - this_week).num_hours() + 12).div_euclid(24 * 7);
+            let Some(counts) = usize::try_from(week).ok().and_then(|week| projected.get_mut(week)) else {
+                continue;
+            };
+            let episodes = program.episodes[batch].iter().filter(|e| e.skipped.is_none()).count();
+            *counts.entry(program.name.as_str()).or_default() += episodes;
+        }
+    }
+
+    let aired = aired_this_week(db);
+    let zone = Zone::database();
+    for (week, counts) in projected.iter().enumerate() {
+        let monday = zone.date(this_week) + chrono::Duration::days(7 * week as i64);
+        let upcoming: usize = counts.values().sum();
+        let total = upcoming + if week == 0 { aired } else { 0 };
+        let load = if week == 0 {
+            format!("{} aired, {} more projected", aired, upcoming)
+        } else {
+            format!("{} projected", upcoming)
+        };
+        let budget = match db.weekly_budget {
+            Some(budget) if total > budget as usize => format!(" - over the budget of {} by {}", budget, total - budget as usize),
+            Some(budget) => format!(" - {} of {}", total, budget),
+            None => String::new(),
+        };
+        println!("Week of {}: {}{}", monday.format("%a %Y-%m-%d"), load, budget);
+        for (name, count) in counts {
+            println!("    {} {}", count, name);
+        }
+    }
+    Ok(())
+}
+
+fn set_on_finish_symlink(db: &mut Database, mode: OnFinishSymlink) -> Result<(), Box<dyn std::error::Error>> {
+    db.on_finish_symlink = mode;
+    db.mark_set("/on_finish_symlink");
+    match mode {
+        OnFinishSymlink::Keep => println!("Finished programs keep their last episode on air"),
+        OnFinishSymlink::Remove => println!("Finished programs have their last episode's links removed"),
+    }
+    Ok(())
+}
+
+const DEFAULT_FORCE_COOLDOWN_HOURS: u32 = 20;
+
+fn set_force_cooldown(db: &mut Database, hours: u32) -> Result<(), Box<dyn std::error::Error>> {
+    db.force_cooldown_hours = Some(hours);
+    db.mark_set("/force_cooldown_hours");
+    if hours == 0 {
+        println!("Forced updates always advance");
+    } else {
+        println!("Forcing a program that rolled over in the last {} hour(s) needs --really-force", hours);
+    }
+    Ok(())
+}
+
+const DEFAULT_FORCE_GRACE_SECONDS: u32 = 60;
+
+fn set_force_grace(db: &mut Database, seconds: u32) -> Result<(), Box<dyn std::error::Error>> {
+    db.force_grace_seconds = Some(seconds);
+    db.mark_set("/force_grace_seconds");
+    if seconds == 0 {
+        println!("Repeating a forced update always goes through the force cooldown");
+    } else {
+        println!("Forcing a program again within {} second(s) of its rollover does nothing", seconds);
+    }
+    Ok(())
+}
+
+// How hard an update pushes a program
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Force {
+    // A scheduled update: airs when due, within the weekly budget
+    No,
+    // 'update <program>': airs now, unless it only just rolled over
+    Yes,
+    // 'update <program> --really-force': airs now whatever the cooldown says
+    Really,
+}
+
+// Whether forcing a program should go ahead. Forcing it again within the
+// grace period is taken for a repeated command and holds it; after that,
+// refuse during the cooldown so running 'update <program>' twice doesn't
+// lose an episode.
+fn force_cooldown(db: &Database, program: &Program) -> Result<Option<Hold>, Box<dyn std::error::Error>> {
+    let cooldown = chrono::Duration::hours(i64::from(db.force_cooldown_hours.unwrap_or(DEFAULT_FORCE_COOLDOWN_HOURS)));
+    let grace = chrono::Duration::seconds(i64::from(db.force_grace_seconds.unwrap_or(DEFAULT_FORCE_GRACE_SECONDS)));
+    let Some(last) = program.last_update.filter(|_| program.status == ProgramStatus::Running) else {
+        return Ok(None);
+    };
+    if let Some(slot) = program.schedule_slot.filter(|&slot| slot > Utc::now()) {
+        return Err(format!("'{}' already aired its rollover for {}; use --really-force to advance again",
+            program.name, format_time_in(slot, Zone::of(program))).into());
+    }
+    let since = Utc::now().signed_duration_since(last);
+    if since < grace {
+        return Ok(Some(Hold::Repeated(since.num_seconds())));
+    }
+    if since >= cooldown {
+        return Ok(None);
+    }
+    let ago = if since.num_hours() > 0 {
+        format!("{} hour(s)", since.num_hours())
+    } else {
+        format!("{} minute(s)", since.num_minutes())
+    };
+    Err(format!("'{}' already rolled over {} ago; use --really-force to advance again", program.name, ago).into())
+}
+
+fn set_default_interval(db: &mut Database, days: u32) -> Result<(), Box<dyn std::error::Error>> {
+    db.default_interval = Some(days);
+    db.mark_set("/default_interval");
+    println!("New programs roll over every {} day(s) unless given --interval", days);
+    Ok(())
+}
+
+// add flags that `config default` can preset, with the name config show
+// lists them under. The interval is kept in default_interval, the rest in
+// add_defaults.
+const ADD_DEFAULT_KEYS: &[(&str, &str)] = &[
+    ("interval", "default-interval"),
+    ("minutes-per-rollover", "default-minutes-per-rollover"),
+    ("batch-size", "default-batch-size"),
+    ("keep-window", "default-keep-window"),
+    ("pause-between-seasons", "default-pause-between-seasons"),
+    ("airing-days", "default-airing-days"),
+    ("sort", "default-sort"),
+    ("folder-episodes", "default-folder-episodes"),
+    ("extensions", "default-extensions"),
+    ("strict", "default-strict"),
+];
+
+// Set one of add's options from its flag name and a value as typed
+fn set_add_option(options: &mut AddOptions, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let flag = || match SettingKind::Bool.parse(value)? {
+        serde_json::Value::Bool(flag) => Ok(flag),
+        _ => Err(format!("expected true or false, got '{}'", value)),
+    };
+    let count = || value.parse::<u32>().ok().filter(|&n| n >= 1)
+        .ok_or_else(|| format!("expected a whole number of at least 1, got '{}'", value));
+    match key {
+        "interval" => options.interval = Interval::Days(count()?),
+        "minutes-per-rollover" => options.minutes_per_rollover = Some(count()?),
+        "batch-size" => options.batch_size = Some(count()?).filter(|&size| size > 1),
+        "keep-window" => options.keep_window = Some(count()?),
+        "pause-between-seasons" => options.pause_between_seasons = flag()?,
+        "airing-days" => options.airing_days = parse_weekdays(value)?,
+        "sort" => options.sort = EpisodeSort::parse(value)
+            .ok_or_else(|| format!("expected name, natural, mtime or ctime, got '{}'", value))?,
+        "folder-episodes" => options.folder_episodes = flag()?,
+        "extensions" => options.extensions = parse_extensions(value)?,
+        "strict" => options.strict = flag()?,
+        _ => {
+            let keys: Vec<&str> = ADD_DEFAULT_KEYS.iter().map(|(key, _)| *key).collect();
+            return Err(format!("Unknown default '{}', expected one of {}", key, keys.join(", ")).into());
+        }
+    }
+    Ok(())
+}
+
+// Fill in the options whose flag wasn't given from the configured defaults
+fn apply_add_defaults(db: &Database, options: &mut AddOptions, given: impl Fn(&str) -> bool) -> Result<(), Box<dyn std::error::Error>> {
+    if let (Some(days), false) = (db.default_interval, given("interval")) {
+        options.interval = Interval::Days(days);
+    }
+    for (key, value) in &db.add_defaults {
+        // Archives are never scanned for folders
+        if given(key) || (key == "folder-episodes" && options.from_archive) {
+            continue;
+        }
+        set_add_option(options, key, value)
+            .map_err(|e| format!("Invalid default {}: {}", key, e))?;
+    }
+    Ok(())
+}
+
+// Set, or with None clear, the value add uses for a flag it isn't given.
+// Programs already added keep their settings.
+fn set_add_default(db: &mut Database, key: &str, value: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(value) = value else {
+        if !ADD_DEFAULT_KEYS.iter().any(|(known, _)| *known == key) {
+            set_add_option(&mut AddOptions::default(), key, "")?;
+        }
+        if key == "interval" {
+            db.default_interval = None;
+            db.mark_set("/default_interval");
+        } else {
+            db.add_defaults.remove(key);
+        }
+        println!("New programs get add's own default for --{}", key);
+        return Ok(());
+    };
+
+    // Checked now rather than when the next add trips over it
+    set_add_option(&mut AddOptions::default(), key, value)
+        .map_err(|e| match key {
+            "interval" => format!("{} (the default interval is in days; pass --interval 6h to add for hours)", e),
+            _ => e.to_string(),
+        })?;
+    if key == "interval" {
+        return set_default_interval(db, value.parse()?);
+    }
+    db.add_defaults.insert(key.to_string(), value.to_string());
+    println!("New programs added without --{} get {}", key, value);
+    Ok(())
+}
+
+// Portable mode keeps all state in data/ next to the executable and stores
+// paths relative to the root of the volume the executable lives on, so a
+// USB stick works wherever it is mounted.
+struct Portable {
+    data_dir: PathBuf,
+    root: PathBuf,
+}
+
+static PORTABLE: OnceLock<Portable> = OnceLock::new();
+
+#[cfg(test)]
+thread_local! {
+    // Data folder of the test running on this thread, instead of the user's
+    static TEST_DATA_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
+// Portable mode is on with --portable or a queuecast.portable file beside the binary
+fn init_portable_mode(flag: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let exe = env::current_exe()?.canonicalize()?;
+    let exe_dir = exe.parent().ok_or("Could not locate the executable's directory")?;
+    if flag || exe_dir.join("queuecast.portable").exists() {
+        let _ = PORTABLE.set(Portable {
+            data_dir: exe_dir.join("data"),
+            root: volume_root(exe_dir)?,
+        });
+    }
+    Ok(())
+}
+
+// Mount point of the filesystem holding path: the highest ancestor on the same device
+#[cfg(unix)]
+fn volume_root(path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let device = fs::metadata(path)?.dev();
+    let mut root = path;
+    while let Some(parent) = root.parent() {
+        if fs::metadata(parent).map(|m| m.dev()).ok() != Some(device) {
+            break;
+        }
+        root = parent;
+    }
+    Ok(root.to_path_buf())
+}
+
+// Drive the path is on, e.g. E:\
+#[cfg(windows)]
+fn volume_root(path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    use std::path::Component;
+
+    Ok(path.components()
+        .take_while(|c| matches!(c, Component::Prefix(_) | Component::RootDir))
+        .collect())
+}
+
+const DEFAULT_HASH_LENGTH: usize = 8;
+// A SHA-256 digest has 64 hex digits
+const HASH_LENGTHS: std::ops::RangeInclusive<usize> = 4..=64;
+
+fn generate_hash(name: &str, length: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(name.as_bytes());
+    let result = hasher.finalize();
+    format!("{:x}", result)[..length.clamp(*HASH_LENGTHS.start(), *HASH_LENGTHS.end())].to_string()
+}
+
+// Hash for a new program. Existing programs keep whatever length they were
+// added with, so one hash being a prefix of another is a clash too: it
+// would make that prefix ambiguous.
+fn new_program_hash(db: &Database, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let hash = generate_hash(name, db.hash_length.unwrap_or(DEFAULT_HASH_LENGTH));
+    let clash = db.programs.values()
+        .find(|p| p.hash.starts_with(&hash) || hash.starts_with(&p.hash));
+    match clash {
+        Some(existing) if existing.name == name => {
+            Err(format!("A program named '{}' already exists ({})", existing.name, existing.hash).into())
+        }
+        Some(existing) => Err(format!(
+            "The hash {} of '{}' clashes with '{}' ({}), raise 'config hash-length' or pick another --name",
+            hash, name, existing.name, existing.hash
+        ).into()),
+        None => Ok(hash),
+    }
+}
+
+fn set_hash_length(db: &mut Database, length: usize) -> Result<(), Box<dyn std::error::Error>> {
+    db.hash_length = Some(length);
+    db.mark_set("/hash_length");
+    println!("New programs get {}-digit hashes, existing ones keep theirs", length);
+    Ok(())
+}
+
+// Whether a file name has one of the program's extensions, or a video
+// extension when the program has none of its own, ignoring case.
+fn has_episode_extension(path: &Path, extensions: &[String]) -> bool {
+    let Some(ext) = path.extension().and_then(|ext| ext.to_str()) else {
+        return false;
+    };
+    if extensions.is_empty() {
+        VIDEO_EXTENSIONS.iter().any(|video| video.eq_ignore_ascii_case(ext))
+    } else {
+        extensions.iter().any(|allowed| allowed.eq_ignore_ascii_case(ext))
+    }
+}
+
+// Case-insensitive glob match of a whole file name; * matches any run of
+// characters and ? any single character.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last * and the name position it currently covers up to
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, covered)) = backtrack {
+            p = star + 1;
+            n = covered + 1;
+            backtrack = Some((star, covered + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn is_ignored(path: &Path, ignore_patterns: &[String]) -> bool {
+    let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+        return false;
+    };
+    ignore_patterns.iter().any(|pattern| glob_match(pattern, &name))
+}
+
+fn scan_episodes(dir: &Path, sort: EpisodeSort, folder_episodes: bool, extensions: &[String], ignore_patterns: &[String], part_markers: &[String]) -> Result<Vec<Episode>, Box<dyn std::error::Error>> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let path = entry.path();
+            !is_ignored(&path, ignore_patterns)
+                && ((path.is_file() && has_episode_extension(&path, extensions))
+                    || (folder_episodes && is_disc_folder(&path)))
+        })
+        .collect();
+    sort_dir_entries(&mut entries, sort);
+    let paths: Vec<PathBuf> = entries.iter().map(fs::DirEntry::path).collect();
+    Ok(group_episodes(&paths, 1, part_markers))
+}
+
+fn sort_dir_entries(entries: &mut [fs::DirEntry], sort: EpisodeSort) {
+    // Filename is the final tiebreaker for every key so the order never flaps
+    entries.sort_by_key(|entry| entry.file_name());
+    match sort {
+        EpisodeSort::Name => {}
+        EpisodeSort::Natural => {
+            entries.sort_by(|a, b| natural_cmp(&a.file_name().to_string_lossy(), &b.file_name().to_string_lossy()));
+        }
+        EpisodeSort::Mtime => {
+            entries.sort_by_cached_key(|entry| entry.metadata().and_then(|m| m.modified()).ok());
+        }
+        EpisodeSort::Ctime => {
+            entries.sort_by_cached_key(|entry| entry.metadata().and_then(|m| m.created()).ok());
+        }
+    }
+}
+
+// Turn sorted paths into episodes numbered from first_number. Consecutive
+// files that only differ by a part marker form one episode.
+fn group_episodes(paths: &[PathBuf], first_number: usize, part_markers: &[String]) -> Vec<Episode> {
+    let mut episodes: Vec<Episode> = Vec::new();
+    let mut previous_part: Option<(String, u32)> = None;
+    for path in paths {
+        let path = path.clone();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let part = if path.is_dir() { None } else { split_part_marker(&file_name, part_markers) };
+
+        let continues_previous = match (&part, &previous_part, episodes.last()) {
+            (Some((base, number)), Some((previous_base, previous_number)), Some(_)) =>
+                base == previous_base && *number == previous_number + 1,
+            _ => false,
+        };
+
+        if continues_previous {
+            episodes.last_mut().unwrap().extra_parts.push(path);
+        } else {
+            let mut episode = Episode::new(path.clone(), first_number + episodes.len());
+            episode.folder = path.is_dir();
+            episodes.push(episode);
+        }
+        // Only a first part can start a group
+        previous_part = part.filter(|(_, number)| continues_previous || *number == 1);
+    }
+    episodes
+}
+
+const DEFAULT_PART_MARKERS: &[&str] = &["cd", "part", "pt"];
+
+fn part_markers(db: &Database) -> Vec<String> {
+    match &db.part_markers {
+        Some(markers) => markers.clone(),
+        None => DEFAULT_PART_MARKERS.iter().map(|marker| marker.to_string()).collect(),
+    }
+}
+
+// Recognise multi-part markers like "CD1", "Part 2", "pt.3" (with an optional
+// space, dot, dash or underscore before the number), from the lowercase
+// marker words given. Returns the file name with the marker removed and the
+// part number.
+fn split_part_marker(file_name: &str, markers: &[String]) -> Option<(String, u32)> {
+    let lower = file_name.to_lowercase();
+    let bytes = lower.as_bytes();
+
+    for marker in markers {
+        for (start, _) in lower.match_indices(marker.as_str()) {
+            // The marker has to be a word of its own
+            if start > 0 && bytes[start - 1].is_ascii_alphanumeric() {
+                continue;
+            }
+            let mut digits_start = start + marker.len();
+            if matches!(bytes.get(digits_start), Some(b' ' | b'.' | b'-' | b'_')) {
+                digits_start += 1;
+            }
+            let digits = bytes[digits_start.min(bytes.len())..].iter().take_while(|b| b.is_ascii_digit()).count();
+            if digits == 0 || digits > 2 {
+                continue;
+            }
+            let end = digits_start + digits;
+            if bytes.get(end).is_some_and(|b| b.is_ascii_alphanumeric()) {
+                continue;
+            }
+
+            let number = lower[digits_start..end].parse().ok()?;
+            let base = format!("{}{}", lower[..start].trim_end_matches([' ', '.', '-', '_']), &lower[end..]);
+            return Some((base, number));
+        }
+    }
+    None
+}
+
+#[cfg(not(feature = "archives"))]
+const ARCHIVES_DISABLED: &str = "This build of queuecast has no archive support, rebuild with '--features archives'";
+
+fn home_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    env::var("HOME")
+        .or_else(|_| env::var("USERPROFILE"))
+        .map(PathBuf::from)
+        .map_err(|_| "Could not find home directory".into())
+}
+
+// Where the database used to live. Nothing is kept there any more. Old
+// versions only ever used ~/.config, so it's checked even when
+// XDG_CONFIG_HOME points elsewhere.
+fn legacy_config_dirs() -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut dirs = Vec::new();
+    if let Ok(dir) = env::var("XDG_CONFIG_HOME") {
+        if !dir.is_empty() {
+            dirs.push(Path::new(&dir).join("queuecast"));
+        }
+    }
+    let home_config = home_dir()?.join(".config").join("queuecast");
+    if !dirs.contains(&home_config) {
+        dirs.push(home_config);
+    }
+    Ok(dirs)
+}
+
+// The database with its history. XDG_STATE_HOME wins everywhere; otherwise
+// each platform's usual place for application data that isn't a setting.
+fn state_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(portable) = PORTABLE.get() {
+        return Ok(portable.data_dir.clone());
+    }
+    if let Ok(dir) = env::var("XDG_STATE_HOME") {
+        if !dir.is_empty() {
+            return Ok(Path::new(&dir).join("queuecast"));
+        }
+    }
+    if cfg!(windows) {
+        if let Ok(dir) = env::var("LOCALAPPDATA") {
+            return Ok(Path::new(&dir).join("queuecast"));
+        }
+    }
+    if cfg!(target_os = "macos") {
+        return Ok(home_dir()?.join("Library").join("Application Support").join("queuecast"));
+    }
+    Ok(home_dir()?.join(".local").join("state").join("queuecast"))
+}
+
+// Move a database from the config directory to `path`, leaving a note where
+// it was. A relative path root was relative to the old folder, so it's made
+// absolute on the way.
+fn migrate_legacy_database(legacy: &Path, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut content = fs::read_to_string(legacy)?;
+    if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&content) {
+        let relative_root = value.get("path_root")
+            .and_then(|root| root.as_str())
+            .map(PathBuf::from)
+            .filter(|root| root.is_relative());
+        if let (Some(root), Some(old_dir)) = (relative_root, legacy.parent()) {
+            let root = old_dir.join(root);
+            value["path_root"] = serde_json::to_value(fs::canonicalize(&root).unwrap_or(root))?;
+            content = serde_json::to_string_pretty(&value)?;
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // In place before the old file goes, so a crash leaves one or the other
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)?;
+    fs::remove_file(legacy)?;
+    let note = format!("queuecast now keeps its database in\n{}\n\nIt was moved there from this folder, which often gets synced with other\nsettings. Nothing else is stored here.\n", path.display());
+    if let Err(e) = fs::write(legacy.with_file_name("MOVED.txt"), note) {
+        eprintln!("WARNING: Could not leave a note in {}: {}", legacy.parent().unwrap_or(legacy).display(), e);
+    }
+    eprintln!("Moved the database from {} to {}", legacy.display(), path.display());
+    Ok(())
+}
+
+fn cache_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(portable) = PORTABLE.get() {
+        return Ok(portable.data_dir.join("cache"));
+    }
+    let cache_home = match env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir()?.join(".cache"),
+    };
+    Ok(cache_home.join("queuecast"))
+}
+
+// Where episodes of an archive program are extracted to when they air.
+fn archive_cache_dir(program_hash: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(cache_dir()?.join("archives").join(program_hash))
+}
+
+// Where episodes of an SFTP program are downloaded to when they air.
+fn remote_cache_dir(program_hash: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(cache_dir()?.join("sftp").join(program_hash))
+}
+
+// List the video entries of a zip archive as episodes. Nothing is extracted
+// yet, each episode's path is where it will be extracted to in `cache_dir`.
+#[cfg(feature = "archives")]
+fn scan_archive(archive: &Path, sort: EpisodeSort, cache_dir: &Path, extensions: &[String], ignore_patterns: &[String]) -> Result<Vec<Episode>, Box<dyn std::error::Error>> {
+    if matches!(sort, EpisodeSort::Mtime | EpisodeSort::Ctime) {
+        return Err("Archive episodes can only be sorted by name".into());
+    }
+
+    let zip = zip::ZipArchive::new(fs::File::open(archive)?)
+        .map_err(|e| format!("Cannot read archive '{}': {}", archive.display(), e))?;
+    let mut names: Vec<String> = zip.file_names()
+        .filter(|name| !name.ends_with('/'))
+        .filter(|name| has_episode_extension(Path::new(name), extensions))
+        .filter(|name| !is_ignored(Path::new(name), ignore_patterns))
+        .map(str::to_string)
+        .collect();
+
+    names.sort();
+    if sort == EpisodeSort::Natural {
+        names.sort_by(|a, b| natural_cmp(a, b));
+    }
+
+    Ok(names.into_iter().enumerate()
+        .map(|(i, name)| {
+            // Entries are flattened into the cache, numbered so that files with
+            // the same name in different folders don't collide
+            let file_name = Path::new(&name).file_name().unwrap_or_default().to_string_lossy().to_string();
+            let mut episode = Episode::new(cache_dir.join(format!("{:03}_{}", i + 1, file_name)), i + 1);
+            episode.season = parse_season(&file_name);
+            episode.archive_entry = Some(name);
+            episode
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "archives"))]
+fn scan_archive(_archive: &Path, _sort: EpisodeSort, _cache_dir: &Path, _extensions: &[String], _ignore_patterns: &[String]) -> Result<Vec<Episode>, Box<dyn std::error::Error>> {
+    Err(ARCHIVES_DISABLED.into())
+}
+
+// Extract an archive episode into the cache unless it's already there. The
+// entry is written to a temporary file first so a failed extraction never
+// leaves a truncated episode behind.
+#[cfg(feature = "archives")]
+fn extract_archive_episode(archive: &Path, episode: &Episode) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(entry_name) = &episode.archive_entry else {
+        return Ok(());
+    };
+    if episode.path.exists() {
+        return Ok(());
+    }
+
+    let failed = |e: &dyn std::fmt::Display| format!("Failed to extract '{}' from '{}': {}", entry_name, archive.display(), e);
+    if let Some(parent) = episode.path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = episode.path.with_extension("partial");
+
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let mut zip = zip::ZipArchive::new(fs::File::open(archive)?)?;
+        let mut entry = zip.by_name(entry_name)?;
+        let mut out = fs::File::create(&tmp)?;
+        std::io::copy(&mut entry, &mut out)?;
+        out.sync_all()?;
+        fs::rename(&tmp, &episode.path)?;
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(failed(&e).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "archives"))]
+fn extract_archive_episode(_archive: &Path, _episode: &Episode) -> Result<(), Box<dyn std::error::Error>> {
+    Err(ARCHIVES_DISABLED.into())
+}
+
+#[cfg(not(feature = "sftp"))]
+const SFTP_DISABLED: &str = "This build of queuecast has no SFTP support, rebuild with '--features sftp'";
+
+// An sftp://[user@]host[:port]/path source. The system's sftp client does
+// the work, so keys, agents and ~/.ssh/config apply as they do for ssh and
+// queuecast never sees a credential.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "sftp"), allow(dead_code))]
+struct SftpSource {
+    destination: String,
+    port: Option<u16>,
+    path: String,
+}
+
+impl SftpSource {
+    fn parse(url: &str) -> Result<SftpSource, Box<dyn std::error::Error>> {
+        let rest = url.strip_prefix("sftp://")
+            .ok_or_else(|| format!("'{}' is not an sftp:// URL", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user), host_port),
+            None => (None, authority),
+        };
+        if user.is_some_and(|user| user.contains(':')) {
+            return Err("Don't put a password in the URL, use an SSH key or agent instead".into());
+        }
+        let (host, port) = match host_port.split_once(':') {
+            Some((host, port)) => (host, Some(port.parse::<u16>().map_err(|_| format!("Invalid port '{}' in '{}'", port, url))?)),
+            None => (host_port, None),
+        };
+        if host.is_empty() {
+            return Err(format!("No host in '{}'", url).into());
+        }
+        // ssh would take these for options, e.g. sftp://-oProxyCommand=...
+        if host.starts_with('-') || user.is_some_and(|user| user.starts_with('-')) {
+            return Err(format!("User and host may not start with '-' in '{}'", url).into());
+        }
+        // Paths go into sftp batch commands in double quotes
+        if path.contains(['"', '\n']) {
+            return Err(format!("Unsupported characters in the path of '{}'", url).into());
+        }
+
+        let destination = match user {
+            Some(user) => format!("{}@{}", user, host),
+            None => host.to_string(),
+        };
+        Ok(SftpSource { destination, port, path: path.trim_end_matches('/').to_string() })
+    }
+
+    // Last path segment, the default program name
+    fn name(&self) -> Option<&str> {
+        self.path.rsplit('/').next().filter(|name| !name.is_empty())
+    }
+}
+
+// Run sftp commands non-interactively. Batch mode never prompts, so a host
+// that wants a password fails instead of hanging a scheduled update.
+#[cfg(feature = "sftp")]
+fn run_sftp(source: &SftpSource, commands: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut command = std::process::Command::new("sftp");
+    command.args(["-q", "-b", "-"]);
+    if let Some(port) = source.port {
+        command.arg("-P").arg(port.to_string());
+    }
+    let mut child = command.arg("--").arg(&source.destination)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run sftp: {}", e))?;
+    child.stdin.take().ok_or("sftp has no stdin")?.write_all(commands.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format!("sftp to {} failed: {}", source.destination, String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// A remote path as an sftp batch argument. sftp expands globs in the paths
+// `ls` and `get` are given, so their special characters are escaped too.
+#[cfg(feature = "sftp")]
+fn sftp_remote_arg(path: &str) -> String {
+    let mut arg = String::from("\"");
+    for c in path.chars() {
+        if matches!(c, '[' | ']' | '*' | '?' | '\\') {
+            arg.push('\\');
+        }
+        arg.push(c);
+    }
+    arg.push('"');
+    arg
+}
+
+// List the video files of a remote folder as episodes. Nothing is
+// downloaded yet, each episode's path is where it will land in `cache_dir`.
+#[cfg(feature = "sftp")]
+fn scan_remote(source: &SftpSource, sort: EpisodeSort, cache_dir: &Path, extensions: &[String], ignore_patterns: &[String]) -> Result<Vec<Episode>, Box<dyn std::error::Error>> {
+    if matches!(sort, EpisodeSort::Mtime | EpisodeSort::Ctime) {
+        return Err("Remote episodes can only be sorted by name".into());
+    }
+
+    let listing = run_sftp(source, &format!("ls -1 {}\n", sftp_remote_arg(&source.path)))?;
+    let mut names: Vec<String> = listing.lines()
+        // Batch mode echoes each command
+        .filter(|line| !line.starts_with("sftp>"))
+        // Names may start or end with spaces, only the line ending goes
+        .filter_map(|line| line.trim_end_matches('\r').rsplit('/').next())
+        .filter(|name| !name.is_empty() && has_episode_extension(Path::new(name), extensions))
+        .filter(|name| !is_ignored(Path::new(name), ignore_patterns))
+        .map(str::to_string)
+        .collect();
+
+    names.sort();
+    if sort == EpisodeSort::Natural {
+        names.sort_by(|a, b| natural_cmp(a, b));
+    }
+
+    Ok(names.into_iter().enumerate()
+        .map(|(i, name)| {
+            let mut episode = Episode::new(cache_dir.join(&name), i + 1);
+            episode.remote_path = Some(format!("{}/{}", source.path, name));
+            episode
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "sftp"))]
+fn scan_remote(_source: &SftpSource, _sort: EpisodeSort, _cache_dir: &Path, _extensions: &[String], _ignore_patterns: &[String]) -> Result<Vec<Episode>, Box<dyn std::error::Error>> {
+    Err(SFTP_DISABLED.into())
+}
+
+// Download a remote episode into the cache unless it's already there, via a
+// temporary file like archive extraction.
+#[cfg(feature = "sftp")]
+fn fetch_remote_episode(source: &SftpSource, episode: &Episode) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(remote_path) = &episode.remote_path else {
+        return Ok(());
+    };
+    if episode.path.exists() {
+        return Ok(());
+    }
+
+    if let Some(parent) = episode.path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = episode.path.with_extension("partial");
+    let local = tmp.to_str().filter(|path| !path.contains(['"', '\n']))
+        .ok_or_else(|| format!("Unsupported characters in {}", tmp.display()))?;
+
+    let result = run_sftp(source, &format!("get {} \"{}\"\n", sftp_remote_arg(remote_path), local))
+        .and_then(|_| Ok(fs::rename(&tmp, &episode.path)?));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("Failed to download '{}' from {}: {}", remote_path, source.destination, e).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "sftp"))]
+fn fetch_remote_episode(_source: &SftpSource, _episode: &Episode) -> Result<(), Box<dyn std::error::Error>> {
+    Err(SFTP_DISABLED.into())
+}
+
+#[cfg(feature = "sftp")]
+fn remote_file_size(source: &SftpSource, remote_path: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    let listing = run_sftp(source, &format!("ls -l {}\n", sftp_remote_arg(remote_path)))?;
+    listing.lines()
+        .filter(|line| !line.starts_with("sftp>"))
+        .find_map(|line| line.split_whitespace().nth(4)?.parse().ok())
+        .ok_or_else(|| format!("Could not read the size of '{}' on {}", remote_path, source.destination).into())
+}
+
+#[cfg(not(feature = "sftp"))]
+fn remote_file_size(_source: &SftpSource, _remote_path: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    Err(SFTP_DISABLED.into())
+}
+
+#[cfg(feature = "archives")]
+fn archive_entry_size(archive: &Path, entry_name: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut zip = zip::ZipArchive::new(fs::File::open(archive)?)?;
+    let entry = zip.by_name(entry_name)?;
+    Ok(entry.size())
+}
+
+#[cfg(not(feature = "archives"))]
+fn archive_entry_size(_archive: &Path, _entry_name: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    Err(ARCHIVES_DISABLED.into())
+}
+
+// Bytes an unprivileged process may still write to the filesystem holding path
+#[cfg(unix)]
+fn available_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: c_path is NUL-terminated and stat is a plain C struct that
+    // statvfs fills in
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    #[allow(clippy::useless_conversion)]
+    Ok(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+}
+
+#[cfg(windows)]
+fn available_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    // SAFETY: wide is NUL-terminated and the totals we don't want may be null
+    let ok = unsafe {
+        windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, std::ptr::null_mut(), std::ptr::null_mut())
+    };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available_space(_path: &Path) -> std::io::Result<u64> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "free space can't be checked on this platform"))
+}
+
+// Before extracting or downloading a batch, make sure it fits in the cache
+// with min_free_bytes to spare. With prune_for_space, the program's oldest
+// copies that are no longer on air are deleted, with their links, until it
+// does. Nothing is copied when it won't fit, so no partial file is left.
+fn ensure_cache_space(program: &mut Program, batch: std::ops::Range<usize>, min_free: u64, prune: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let source = program.remote.as_deref().map(SftpSource::parse).transpose()?;
+    let mut needed = 0u64;
+    let mut cache_dir = None;
+    for episode in program.episodes[batch.clone()].iter().filter(|e| e.skipped.is_none() && !e.path.exists()) {
+        needed += match (&source, &episode.remote_path, &episode.archive_entry) {
+            (Some(source), Some(remote_path), _) => remote_file_size(source, remote_path)?,
+            (None, _, Some(entry)) => archive_entry_size(&program.directory, entry)?,
+            _ => continue,
+        };
+        cache_dir = cache_dir.or_else(|| episode.path.parent().map(Path::to_path_buf));
+    }
+    let Some(cache_dir) = cache_dir else {
+        return Ok(());
+    };
+    fs::create_dir_all(&cache_dir)?;
+
+    let on_air: Vec<PathBuf> = expected_link_targets(program).into_iter().map(|(_, target)| target).collect();
+    let mut prunable = program.episodes[..batch.start].iter()
+        .map(|e| e.path.clone())
+        .filter(|path| path.starts_with(&cache_dir) && path.exists() && !on_air.contains(path))
+        .collect::<Vec<_>>()
+        .into_iter();
+    loop {
+        let available = available_space(&cache_dir)?;
+        if available >= needed.saturating_add(min_free) {
+            return Ok(());
+        }
+        let Some(oldest) = prunable.next().filter(|_| prune) else {
+            return Err(format!(
+                "Not enough free space in {}: {} needs {} plus the {} reserve, {} available",
+                cache_dir.display(), program.name, format_size(needed), format_size(min_free), format_size(available)
+            ).into());
+        };
+
+        for link in program.active_links.iter().filter(|link| link.target == oldest) {
+            if fs::read_link(&link.path).is_ok_and(|target| target == oldest) {
+                remove_symlink(&link.path)?;
+            }
+        }
+        program.active_links.retain(|link| link.target != oldest);
+        fs::remove_file(&oldest)?;
+        println!("Deleted {} to make room for {}", oldest.display(), program.name);
+    }
+}
+
+// A DVD or Blu-ray rip: a directory holding a VIDEO_TS or BDMV folder.
+fn is_disc_folder(path: &Path) -> bool {
+    path.is_dir() && fs::read_dir(path).is_ok_and(|entries| {
+        entries.filter_map(|entry| entry.ok()).any(|entry| {
+            let name = entry.file_name().to_string_lossy().to_uppercase();
+            (name == "VIDEO_TS" || name == "BDMV") && entry.path().is_dir()
+        })
+    })
+}
+
+// Compare filenames so that embedded numbers order by value ("Ep 2" before
+// "Ep 10"), ignoring case elsewhere.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut x_digits = String::new();
+                while let Some(c) = a_chars.next_if(|c| c.is_ascii_digit()) {
+                    x_digits.push(c);
+                }
+                let mut y_digits = String::new();
+                while let Some(c) = b_chars.next_if(|c| c.is_ascii_digit()) {
+                    y_digits.push(c);
+                }
+
+                let x_trimmed = x_digits.trim_start_matches('0');
+                let y_trimmed = y_digits.trim_start_matches('0');
+                let ordering = x_trimmed.len().cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+// Extract the season number from an "S01E02"-style marker in a filename.
+fn parse_season(file_name: &str) -> Option<u32> {
+    parse_episode_marker(file_name).map(|(season, _)| season)
+}
+
+// Season and episode numbers of an "S01E02"-style marker. Multi-episode
+// files like "S01E02E03" or "S01E02-E03" cover the whole range.
+fn parse_episode_marker(file_name: &str) -> Option<(u32, std::ops::RangeInclusive<u32>)> {
+    let lower = file_name.to_lowercase();
+    let bytes = lower.as_bytes();
+    let digits_at = |start: usize| bytes.get(start..).map_or(0, |rest| rest.iter().take_while(|b| b.is_ascii_digit()).count());
+
+    for (i, _) in lower.match_indices('s') {
+        let digits = digits_at(i + 1);
+        if digits == 0 || digits > 3 {
+            continue;
+        }
+        let after = i + 1 + digits;
+        let episode_digits = digits_at(after + 1);
+        if bytes.get(after) != Some(&b'e') || episode_digits == 0 {
+            continue;
+        }
+        let season = lower[i + 1..after].parse().ok()?;
+        let first: u32 = lower[after + 1..after + 1 + episode_digits].parse().ok()?;
+
+        let mut end = after + 1 + episode_digits;
+        let mut last = first;
+        loop {
+            let (next, bare) = match bytes.get(end..end + 2) {
+                Some(b"-e") => (end + 2, false),
+                Some([b'e', _]) => (end + 1, false),
+                Some([b'-', _]) => (end + 1, true),
+                _ => break,
+            };
+            let count = digits_at(next);
+            // "S01E02-03" but not "S01E02-720p" or "S01E02-2019"
+            if bare && (count > 3 || bytes.get(next + count).is_some_and(|b| b.is_ascii_alphanumeric())) {
+                break;
+            }
+            match lower[next..next + count].parse::<u32>() {
+                Ok(number) if count > 0 && number > last => {
+                    last = number;
+                    end = next + count;
+                }
+                _ => break,
+            }
+        }
+        return Some((season, first..=last));
+    }
+    None
+}
+
+// Gaps and duplicates in the SxxEyy numbering of a program's files, one line
+// each. Every season is expected to run from episode 1 to its highest one.
+// Programs whose files carry no markers are numbered by position and have
+// nothing to check.
+fn numbering_problems(episodes: &[Episode]) -> Vec<String> {
+    let mut seasons: BTreeMap<u32, BTreeMap<u32, usize>> = BTreeMap::new();
+    for episode in episodes {
+        let name = episode.path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some((season, numbers)) = parse_episode_marker(&name) {
+            // No file holds a hundred episodes; a span that wide is a typo
+            // and only its ends are counted
+            let numbers: Vec<u32> = if numbers.end() - numbers.start() < 100 {
+                numbers.collect()
+            } else {
+                vec![*numbers.start(), *numbers.end()]
+            };
+            for number in numbers {
+                *seasons.entry(season).or_default().entry(number).or_default() += 1;
+            }
+        }
+    }
+
+    let mut problems = Vec::new();
+    for (season, numbers) in &seasons {
+        // Gaps are found between the numbers present, and reported as
+        // ranges, so a stray E9999 costs one line rather than thousands
+        let mut missing = Vec::new();
+        let mut previous = 0;
+        for &number in numbers.keys() {
+            match number.saturating_sub(previous) {
+                0 | 1 => {}
+                2 => missing.push((previous + 1).to_string()),
+                _ => missing.push(format!("{}-{}", previous + 1, number - 1)),
+            }
+            previous = number;
+        }
+        if !missing.is_empty() {
+            problems.push(format!("Season {}: missing episode {}", season, missing.join(", ")));
+        }
+        for (number, count) in numbers.iter().filter(|(_, &count)| count > 1) {
+            problems.push(format!("Season {}: episode {} appears in {} files", season, number, count));
+        }
+    }
+    problems
+}
+
+fn print_numbering_problems(name: &str, problems: &[String]) {
+    eprintln!("Warning: the episode numbering of '{}' has gaps or duplicates:", name);
+    for problem in problems {
+        eprintln!("  {}", problem);
+    }
+}
+
+// Total runtime of all parts of an episode, if every part could be measured.
+fn probe_episode_duration(episode: &Episode) -> Option<u32> {
+    episode.parts().map(|part| probe_duration(part)).sum()
+}
+
+fn probe_duration(path: &Path) -> Option<u32> {
+    let output = process::Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration", "-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let secs: f64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+    Some(secs.round() as u32)
+}
+
+fn parse_weekdays(days: &str) -> Result<Vec<Weekday>, Box<dyn std::error::Error>> {
+    let mut weekdays = Vec::new();
+    for day in days.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let weekday: Weekday = day.parse()
+            .map_err(|_| format!("Invalid weekday '{}'", day))?;
+        if !weekdays.contains(&weekday) {
+            weekdays.push(weekday);
+        }
+    }
+    weekdays.sort_by_key(|d| d.num_days_from_monday());
+    Ok(weekdays)
+}
+
+fn resolve_directory(directory: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir_path = PathBuf::from(directory);
+    // Convert to absolute path
+    let abs_dir_path = if dir_path.is_absolute() {
+        dir_path
+    } else {
+        // Get current directory and join with the relative path
+        let current_dir = env::current_dir()?;
+        current_dir.join(&dir_path)
+    };
+    
+    // Check if the directory exists
+    if !abs_dir_path.exists() || !abs_dir_path.is_dir() {
+        return Err("Directory does not exist or is not a directory".into());
+    }
+
+    // Get the canonical path to resolve any symlinks and ensure it's absolute
+    Ok(fs::canonicalize(&abs_dir_path)?)
+}
+
+fn find_program_by_directory<'a>(db: &'a Database, directory: &Path) -> Option<&'a Program> {
+    db.programs.values().find(|p| !p.curated && p.directory == directory)
+}
+
+fn add_program(db: &mut Database, directory: &str, options: &AddOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if directory.starts_with("sftp://") {
+        return add_remote_program(db, directory, options);
+    }
+    let canonical_dir_path = if options.from_archive {
+        let path = fs::canonicalize(directory)
+            .map_err(|e| format!("Cannot access '{}': {}", directory, e))?;
+        if !path.is_file() {
+            return Err(format!("'{}' is not an archive file", directory).into());
+        }
+        path
+    } else {
+        resolve_directory(directory)?
+    };
+
+    if let Some(existing) = find_program_by_directory(db, &canonical_dir_path) {
+        return Err(format!("Directory already added as program '{}' ({})", existing.name, existing.hash).into());
+    }
+    if !options.force {
+        check_symlink_dir_overlap(db.symlink_dir.as_deref(), &canonical_dir_path)?;
+    }
+
+    let default_name = if options.from_archive {
+        canonical_dir_path.file_stem()
+    } else {
+        canonical_dir_path.file_name()
+    };
+    let name = match &options.name {
+        Some(name) => name.clone(),
+        None => default_name
+            .ok_or("Invalid directory name")?
+            .to_string_lossy()
+            .to_string(),
+    };
+    
+    let hash = new_program_hash(db, &name)?;
+
+    let mut episodes = if options.from_archive {
+        scan_archive(&canonical_dir_path, options.sort, &archive_cache_dir(&hash)?, &options.extensions, &db.ignore_patterns)?
+    } else {
+        scan_episodes(&canonical_dir_path, options.sort, options.folder_episodes, &options.extensions, &db.ignore_patterns, &part_markers(db))?
+    };
+
+    let adopted = match &options.adopt {
+        Some(adopt_dir) => {
+            let links = read_adopted_links(adopt_dir, &canonical_dir_path)?;
+            if links.is_empty() {
+                return Err(format!("No symlinks into {} found in {}", canonical_dir_path.display(), adopt_dir.display()).into());
+            }
+            links
+        }
+        None => Vec::new(),
+    };
+
+    // Adopted episodes keep the order they were linked in and take the places
+    // their files have in the scan, while files not linked keep theirs. The
+    // program resumes after the highest-numbered link, so files scanned
+    // before it count as aired even if their links were deleted since.
+    let mut current_episode = 0;
+    if !adopted.is_empty() {
+        let linked_paths: HashSet<&Path> = adopted.iter().map(|link| link.target.as_path()).collect();
+        let linked: Vec<(bool, Episode)> = adopted.iter().map(|link| match episodes.iter().position(|e| e.path == link.target) {
+            Some(i) => (true, episodes[i].clone()),
+            None => {
+                let mut episode = Episode::new(link.target.clone(), 0);
+                episode.folder = link.target.is_dir();
+                (false, episode)
+            }
+        }).collect();
+        let mut linked = linked.into_iter();
+        let mut ordered = Vec::new();
+        for episode in episodes.drain(..) {
+            if !linked_paths.contains(episode.path.as_path()) {
+                ordered.push(episode);
+                continue;
+            }
+            // A link to a file the scan skipped goes with the next link,
+            // or after the last one
+            for (scanned, episode) in linked.by_ref() {
+                ordered.push(episode);
+                if scanned {
+                    break;
+                }
+            }
+            current_episode = ordered.len();
+        }
+        let rest: Vec<Episode> = linked.map(|(_, episode)| episode).collect();
+        let resume = current_episode + rest.len();
+        ordered.splice(current_episode..current_episode, rest);
+        current_episode = resume;
+        for (i, episode) in ordered.iter_mut().enumerate() {
+            episode.episode_number = i + 1;
+        }
+        episodes = ordered;
+    }
+
+    if episodes.is_empty() {
+        return Err("No video files found in directory".into());
+    }
+
+    let problems = numbering_problems(&episodes);
+    if !problems.is_empty() {
+        print_numbering_problems(&name, &problems);
+        if options.strict {
+            return Err(format!("Not adding '{}' because of its episode numbering, fix the files or add without --strict", name).into());
+        }
+    }
+
+    let program = Program {
+        name: name.clone(),
+        hash: hash.clone(),
+        directory: canonical_dir_path,
+        episodes,
+        current_episode,
+        start_date: adopted.iter().map(|link| link.created).min(),
+        last_update: adopted.last().map(|link| link.created),
+        status: if adopted.is_empty() { ProgramStatus::Ready } else { ProgramStatus::Running },
+        minutes_per_rollover: options.minutes_per_rollover,
+        pause_between_seasons: options.pause_between_seasons,
+        confirmed_season: None,
+        airing_days: options.airing_days.clone(),
+        sort: options.sort,
+        interval_days: options.interval.interval_days(),
+        interval_secs: options.interval.interval_secs(),
+        last_symlinks: Vec::new(),
+        active_links: adopted.iter()
+            .map(|link| ActiveLink { path: link.link.clone(), target: link.target.clone(), created: link.created })
+            .collect(),
+        curated: false,
+        folder_episodes: options.folder_episodes,
+        archive: options.from_archive,
+        hooks: ProgramHooks::default(),
+        extensions: options.extensions.clone(),
+        watched_episode: 0,
+        timezone: None,
+        remote: None,
+        link_dir: None,
+        duplicated_from: None,
+        follow_rescans: false,
+        stall: None,
+        pinned: false,
+        custom_status: None,
+        season_release: SeasonRelease::PerEpisode,
+        season_cooldown_days: None,
+        next_wait_days: None,
+        queue_position: 0,
+        batch_size: options.batch_size,
+        milestones_reached: Vec::new(),
+        keep_window: options.keep_window,
+        added_at: Some(Utc::now()),
+        schedule_slot: None,
+    };
+
+    if options.pause_between_seasons && program.episodes.iter().all(|e| e.season.is_none()) {
+        eprintln!("Warning: no season markers found in filenames, season pauses will never trigger");
+    }
+
+    check_link_names(&db.link_naming, &program)?;
+    if options.dry_run {
+        println!("Would add program '{}' with hash '{}' ({} episodes):", name, hash, program.episodes.len());
+        print_episode_list(&program);
+        return Ok(());
+    }
+
+    for link in &adopted {
+        if let Some(episode) = program.episodes.iter().find(|e| e.path == link.target) {
+            db.history.push(HistoryEntry::new(hash.clone(), name.clone(), episode.episode_number, episode.path.clone(), link.created));
+        }
+    }
+    if !adopted.is_empty() {
+        println!("Adopted {} linked episode(s) from existing symlinks, resuming after episode {}", adopted.len(), program.current_episode);
+    }
+
+    let ready = program.status == ProgramStatus::Ready;
+    db.programs.insert(hash.clone(), program);
+    if ready {
+        enqueue(db, &hash, options.queue_top);
+    }
+    println!("Added program '{}' with hash '{}'", name, hash);
+    Ok(())
+}
+
+// Add a program whose episodes live on an SFTP host. They are copied into a
+// local cache as they air and linked from there, since a symlink can't
+// point at a remote file.
+fn add_remote_program(db: &mut Database, url: &str, options: &AddOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if options.from_archive || options.folder_episodes || options.adopt.is_some() {
+        return Err("--from-archive, --folder-episodes and --adopt don't work with sftp:// sources".into());
+    }
+    let source = SftpSource::parse(url)?;
+    if let Some(existing) = db.programs.values().find(|p| p.remote.as_deref() == Some(url)) {
+        return Err(format!("{} already added as program '{}' ({})", url, existing.name, existing.hash).into());
+    }
+
+    let name = match &options.name {
+        Some(name) => name.clone(),
+        None => source.name().ok_or("Invalid remote directory name")?.to_string(),
+    };
+    let hash = new_program_hash(db, &name)?;
+    let cache_dir = remote_cache_dir(&hash)?;
+    let episodes = scan_remote(&source, options.sort, &cache_dir, &options.extensions, &db.ignore_patterns)?;
+    if episodes.is_empty() {
+        return Err(format!("No video files found in {}", url).into());
+    }
+
+    let problems = numbering_problems(&episodes);
+    if !problems.is_empty() {
+        print_numbering_problems(&name, &problems);
+        if options.strict {
+            return Err(format!("Not adding '{}' because of its episode numbering, fix the files or add without --strict", name).into());
+        }
+    }
+
+    let program = Program {
+        name: name.clone(),
+        hash: hash.clone(),
+        directory: cache_dir,
+        episodes,
+        current_episode: 0,
+        start_date: None,
+        last_update: None,
+        status: ProgramStatus::Ready,
+        minutes_per_rollover: options.minutes_per_rollover,
+        pause_between_seasons: options.pause_between_seasons,
+        confirmed_season: None,
+        airing_days: options.airing_days.clone(),
+        sort: options.sort,
+        interval_days: options.interval.interval_days(),
+        interval_secs: options.interval.interval_secs(),
+        last_symlinks: Vec::new(),
+        active_links: Vec::new(),
+        curated: false,
+        folder_episodes: false,
+        archive: false,
+        hooks: ProgramHooks::default(),
+        extensions: options.extensions.clone(),
+        watched_episode: 0,
+        timezone: None,
+        remote: Some(url.to_string()),
+        link_dir: None,
+        duplicated_from: None,
+        follow_rescans: false,
+        stall: None,
+        pinned: false,
+        custom_status: None,
+        season_release: SeasonRelease::PerEpisode,
+        season_cooldown_days: None,
+        next_wait_days: None,
+        queue_position: 0,
+        batch_size: options.batch_size,
+        milestones_reached: Vec::new(),
+        keep_window: options.keep_window,
+        added_at: Some(Utc::now()),
+        schedule_slot: None,
+    };
+
+    check_link_names(&db.link_naming, &program)?;
+    if options.dry_run {
+        println!("Would add program '{}' with hash '{}' ({} episodes):", name, hash, program.episodes.len());
+        print_episode_list(&program);
+        return Ok(());
+    }
+
+    db.programs.insert(hash.clone(), program);
+    enqueue(db, &hash, options.queue_top);
+    println!("Added program '{}' with hash '{}', episodes are downloaded as they air", name, hash);
+    Ok(())
+}
+
+// A symlink made by hand before the program was added to queuecast.
+struct AdoptedLink {
+    link: PathBuf,
+    target: PathBuf,
+    created: DateTime<Utc>,
+}
+
+// Existing links in `adopt_dir` that point into `program_dir`, in their
+// numbering order. A target linked more than once is only adopted once.
+fn read_adopted_links(adopt_dir: &Path, program_dir: &Path) -> Result<Vec<AdoptedLink>, Box<dyn std::error::Error>> {
+    let mut links: Vec<AdoptedLink> = Vec::new();
+    for entry in fs::read_dir(adopt_dir)?.filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_ok_and(|t| t.is_symlink()) {
+            continue;
+        }
+        let Ok(target) = fs::canonicalize(entry.path()) else {
+            eprintln!("Skipping broken symlink {}", entry.path().display());
+            continue;
+        };
+        if !target.starts_with(program_dir) {
+            continue;
+        }
+
+        let created = fs::symlink_metadata(entry.path())
+            .and_then(|m| m.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        links.push(AdoptedLink { link: entry.path(), target, created });
+    }
+
+    links.sort_by(|a, b| natural_cmp(
+        &a.link.file_name().unwrap_or_default().to_string_lossy(),
+        &b.link.file_name().unwrap_or_default().to_string_lossy(),
+    ));
+    let mut seen = HashSet::new();
+    links.retain(|link| seen.insert(link.target.clone()));
+    Ok(links)
+}
+
+// Schedule a single file: either as a new one-episode program named after the
+// file, or appended as the next episode of an existing program.
+fn add_file(db: &mut Database, file: &str, target: Option<&str>, options: &AddOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let path = fs::canonicalize(file)
+        .map_err(|e| format!("Cannot access '{}': {}", file, e))?;
+    if !path.is_file() {
+        return Err(format!("'{}' is not a file", file).into());
+    }
+    let file_name = path.file_name().ok_or("Invalid file name")?.to_string_lossy().to_string();
+    if !options.force {
+        check_symlink_dir_overlap(db.symlink_dir.as_deref(), path.parent().ok_or("Invalid file path")?)?;
+    }
+
+    let Some(program_hash) = target else {
+        let name = match &options.name {
+            Some(name) => name.clone(),
+            None => path.file_stem().ok_or("Invalid file name")?.to_string_lossy().to_string(),
+        };
+        let hash = new_program_hash(db, &name)?;
+
+        let program = Program {
+            name: name.clone(),
+            hash: hash.clone(),
+            directory: path.parent().ok_or("Invalid file path")?.to_path_buf(),
+            episodes: vec![Episode::new(path, 1)],
+            current_episode: 0,
+            start_date: None,
+            last_update: None,
+            status: ProgramStatus::Ready,
+            minutes_per_rollover: options.minutes_per_rollover,
+            pause_between_seasons: options.pause_between_seasons,
+            confirmed_season: None,
+            airing_days: options.airing_days.clone(),
+            sort: options.sort,
+            interval_days: options.interval.interval_days(),
+            interval_secs: options.interval.interval_secs(),
+            last_symlinks: Vec::new(),
+            active_links: Vec::new(),
+            curated: true,
+            folder_episodes: false,
+            archive: false,
+            hooks: ProgramHooks::default(),
+            extensions: Vec::new(),
+            watched_episode: 0,
+            timezone: None,
+            remote: None,
+            link_dir: None,
+            duplicated_from: None,
+            follow_rescans: false,
+            stall: None,
+            pinned: false,
+            custom_status: None,
+            season_release: SeasonRelease::PerEpisode,
+            season_cooldown_days: None,
+            next_wait_days: None,
+            queue_position: 0,
+            batch_size: options.batch_size,
+            milestones_reached: Vec::new(),
+            keep_window: options.keep_window,
+            added_at: Some(Utc::now()),
+            schedule_slot: None,
+        };
+
+        db.programs.insert(hash.clone(), program);
+        enqueue(db, &hash, options.queue_top);
+        println!("Added program '{}' with hash '{}'", name, hash);
+        return Ok(());
+    };
+
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    if program.episodes.iter().any(|e| e.parts().any(|part| *part == path)) {
+        return Err(format!("'{}' is already an episode of '{}'", file_name, program.name).into());
+    }
+
+    let episode_number = program.episodes.iter().map(|e| e.episode_number).max().unwrap_or(0) + 1;
+    program.episodes.push(Episode::new(path, episode_number));
+    program.curated = true;
+
+    // A finished program has something to air again
+    if program.status == ProgramStatus::Finished {
+        program.status = ProgramStatus::Running;
+    }
+    println!("Added '{}' as episode {} of '{}'", file_name, episode_number, program.name);
+    Ok(())
+}
+
+// Add every directory listed in a manifest (one per line, "-" for stdin). Lines
+// may carry "|name=..." or "|interval=..." overrides; blank lines and "#"
+// comments are ignored, and directories already in the database are skipped.
+// Returns the number of lines that failed.
+fn add_from_file(db: &mut Database, source: &str, options: &AddOptions) -> Result<usize, Box<dyn std::error::Error>> {
+    let content = if source == "-" {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        fs::read_to_string(source)?
+    };
+
+    let (mut added, mut skipped, mut failed) = (0, 0, 0);
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let result = parse_manifest_line(line, options).and_then(|(directory, line_options)| {
+            let canonical = resolve_directory(&directory)?;
+            if find_program_by_directory(db, &canonical).is_some() {
+                return Ok(false);
+            }
+            add_program(db, &directory, &line_options)?;
+            Ok(true)
+        });
+
+        match result {
+            Ok(true) => added += 1,
+            Ok(false) => skipped += 1,
+            Err(e) => {
+                eprintln!("Line {}: {}: {}", line_number + 1, line, e);
+                failed += 1;
+            }
+        }
+    }
+
+    let verb = if options.dry_run { "Would add" } else { "Added" };
+    println!("{} {} program(s), skipped {} already added, {} failed", verb, added, skipped, failed);
+    Ok(failed)
+}
+
+// Clean up a program name given by hand. It ends up in link and folder
+// names, so path separators and control characters become '-' and runs of
+// whitespace one space.
+fn clean_program_name(raw: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let cleaned: String = raw.chars()
+        .map(|c| if matches!(c, '/' | '\\') || c.is_control() { '-' } else { c })
+        .collect();
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    if cleaned.trim_matches('-').is_empty() {
+        return Err("Program name must not be empty".into());
+    }
+    if cleaned != raw {
+        println!("Naming the program '{}'", cleaned);
+    }
+    Ok(cleaned)
+}
+
+fn parse_manifest_line(line: &str, defaults: &AddOptions) -> Result<(String, AddOptions), Box<dyn std::error::Error>> {
+    let mut parts = line.split('|');
+    let directory = parts.next().unwrap_or_default().trim().to_string();
+    let mut options = defaults.clone();
+
+    for part in parts {
+        let (key, value) = part.split_once('=')
+            .ok_or_else(|| format!("Expected key=value, got '{}'", part.trim()))?;
+        let value = value.trim();
+        match key.trim() {
+            "name" => options.name = Some(clean_program_name(value)?),
+            "interval" => {
+                options.interval = Interval::parse(value)?;
+            }
+            other => return Err(format!("Unknown option '{}'", other).into()),
+        }
+    }
+    Ok((directory, options))
+}
+
+// Time filters for 'list'. Programs added before added_at was recorded, or
+// that never aired, match no filter on the time they lack.
+#[derive(Default)]
+struct ListTimes {
+    added_since: Option<DateTime<Utc>>,
+    updated_since: Option<DateTime<Utc>>,
+    updated_before: Option<DateTime<Utc>>,
+}
+
+impl ListTimes {
+    fn matches(&self, program: &Program) -> bool {
+        let after = |time: Option<DateTime<Utc>>, since: Option<DateTime<Utc>>| since.is_none_or(|since| time.is_some_and(|time| time >= since));
+        after(program.added_at, self.added_since)
+            && after(program.last_update, self.updated_since)
+            && self.updated_before.is_none_or(|before| program.last_update.is_some_and(|time| time < before))
+    }
+}
+
+fn list_programs(db: &Database, filter: &str, stalled_only: bool, times: &ListTimes) -> Result<(), Box<dyn std::error::Error>> {
+    let status_filter = parse_status(filter);
+
+    for program in db.programs.values() {
+        if !times.matches(program) {
+            continue;
+        }
+        if let Some(ref filter_status) = status_filter {
+            if &program.status != filter_status {
+                continue;
+            }
+        }
+        if stalled_only && !is_stalled(program) {
+            continue;
+        }
+        
+        println!("{} [{}] ({}/{} episodes, {} watched) - {}{}{}{}", 
+            program.hash, 
+            program.name,
+            program.current_episode,
+            program.episodes.len(),
+            program.watched_episode.min(program.episodes.len()),
+            status_label(program),
+            if program.pinned { " [PINNED]" } else { "" },
+            match &program.stall {
+                Some(stall) if is_stalled(program) => format!(" [STALLED: {}]", stall.reason),
+                _ => String::new(),
+            },
+            if has_tampered_links(program) { " [!links]" } else { "" }
+        );
+        if let (true, Some(stall)) = (stalled_only, &program.stall) {
+            println!("    {} due rollover(s) in a row failed since {}", stall.attempts, format_time(stall.since));
+        }
+    }
+
+    let awaiting = db.programs.values()
+        .filter(|p| p.status == ProgramStatus::AwaitingConfirmation)
+        .count();
+    if awaiting > 0 {
+        println!("{} program(s) awaiting confirmation before the next season, use 'queuecast continue <program>'", awaiting);
+    }
+    let stalled = db.programs.values().filter(|p| is_stalled(p)).count();
+    if stalled > 0 && !stalled_only {
+        println!("{} program(s) stalled, see 'queuecast list --stalled'", stalled);
+    }
+    if let Some(warning) = missed_update_warning(db) {
+        eprintln!("WARNING: {}", warning);
+    }
+    Ok(())
+}
+
+// Warn when scheduled updates seem to have stopped running, e.g. a broken
+// cron job. Overdue means older than the shortest active interval plus grace.
+fn missed_update_warning(db: &Database) -> Option<String> {
+    let shortest_interval = db.programs.values()
+        .filter(|p| matches!(p.status, ProgramStatus::Ready | ProgramStatus::Running) && p.custom_status.is_none())
+        .map(interval)
+        .min()?;
+
+    let Some(last) = db.last_successful_update else {
+        return Some("No scheduled update has completed yet, is 'queuecast update' being run?".to_string());
+    };
+    let allowed = shortest_interval
+        + chrono::Duration::hours(MISSED_UPDATE_GRACE_HOURS);
+    if Utc::now().signed_duration_since(last) > allowed {
+        Some(format!("No update has run since {}, scheduled updates may have stopped", format_time(last)))
+    } else {
+        None
+    }
+}
+
+fn health(db: &Database) -> bool {
+    match db.last_successful_update {
+        Some(last) => println!("Last successful update: {}", format_time(last)),
+        None => println!("Last successful update: never"),
+    }
+    match missed_update_warning(db) {
+        Some(warning) => {
+            println!("WARNING: {}", warning);
+            false
+        }
+        None => {
+            println!("OK");
+            true
+        }
+    }
+}
+
+// What happens to a rollover falling due during a blackout
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum BlackoutPolicy {
+    // Air it the first day after, later rollovers count from then
+    Shift,
+    // Drop it and keep to the interval, as if the blackout had aired
+    Skip,
+}
+
+// Whole days, in each program's time zone, when scheduled updates air
+// nothing. Forced updates ignore them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Blackout {
+    start: NaiveDate,
+    end: NaiveDate,
+    policy: BlackoutPolicy,
+}
+
+impl Blackout {
+    fn contains(&self, date: NaiveDate) -> bool {
+        (self.start..=self.end).contains(&date)
+    }
+}
+
+impl std::fmt::Display for Blackout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}..{}", self.start, self.end)
+        }
+    }
+}
+
+// Parse "2025-12-20..2026-01-05", or a single date for one day
+fn parse_date_range(range: &str) -> Result<(NaiveDate, NaiveDate), Box<dyn std::error::Error>> {
+    let parse = |date: &str| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date.trim()));
+    let (start, end) = match range.split_once("..") {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => (parse(range)?, parse(range)?),
+    };
+    if end < start {
+        return Err(format!("Blackout '{}' ends before it starts", range).into());
+    }
+    Ok((start, end))
+}
+
+// Parse a point in time for a filter: a date (midnight starting it in the
+// database's zone) or a span back from now like 30d or 12h
+fn parse_since(raw: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(raw.trim(), "%Y-%m-%d") {
+        return Zone::database().start_of_day(date).ok_or_else(|| format!("{} has no midnight in the database's time zone", date));
+    }
+    let span = match Interval::parse(raw).map_err(|_| format!("Invalid time '{}', expected YYYY-MM-DD or a span back from now like 30d or 12h", raw.trim()))? {
+        Interval::Days(days) => chrono::Duration::days(i64::from(days)),
+        Interval::Hours(hours) => chrono::Duration::hours(i64::from(hours)),
+    };
+    Ok(Utc::now() - span)
+}
+
+fn add_blackout(db: &mut Database, range: &str, policy: BlackoutPolicy) -> Result<(), Box<dyn std::error::Error>> {
+    let (start, end) = parse_date_range(range)?;
+    if let Some(existing) = db.blackouts.iter().find(|b| b.start == start && b.end == end) {
+        return Err(format!("Blackout {} already exists ({:?})", existing, existing.policy).into());
+    }
+    let blackout = Blackout { start, end, policy };
+    println!("Nothing airs {} (missed rollovers {})", blackout, match policy {
+        BlackoutPolicy::Shift => "air the day after",
+        BlackoutPolicy::Skip => "are dropped",
+    });
+    db.blackouts.push(blackout);
+    db.blackouts.sort_by_key(|b| (b.start, b.end));
+    Ok(())
+}
+
+fn remove_blackout(db: &mut Database, range: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (start, end) = parse_date_range(range)?;
+    let index = db.blackouts.iter().position(|b| b.start == start && b.end == end)
+        .ok_or_else(|| format!("No blackout {}, see 'queuecast blackout list'", range))?;
+    let blackout = db.blackouts.remove(index);
+    println!("Removed blackout {}", blackout);
+    Ok(())
+}
+
+fn list_blackouts(db: &Database) {
+    if db.blackouts.is_empty() {
+        println!("No blackouts");
+    }
+    let today = Zone::database().date(Utc::now());
+    for blackout in &db.blackouts {
+        let note = if blackout.contains(today) {
+            " (now)"
+        } else if blackout.end < today {
+            " (past)"
+        } else {
+            ""
+        };
+        println!("{} {}{}", blackout, format!("{:?}", blackout.policy).to_lowercase(), note);
+    }
+}
+
+// Move a due time out of any blackouts, following each one's policy
+fn defer_for_blackouts(program: &Program, blackouts: &[Blackout], due: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let zone = Zone::of(program);
+    let interval = interval(program);
+    let mut at = due;
+    // Back-to-back blackouts can hand the time on, but not forever
+    for _ in 0..=blackouts.len() {
+        let Some(blackout) = blackouts.iter().find(|b| b.contains(zone.date(at))) else {
+            return Some(at);
+        };
+        let after = blackout.end.succ_opt()?;
+        match blackout.policy {
+            BlackoutPolicy::Shift => at = zone.start_of_day(after)?,
+            BlackoutPolicy::Skip => {
+                while zone.date(at) < after {
+                    at += interval;
+                }
+            }
+        }
+    }
+    None
+}
+
+// Time between the latest rollover and the next
+fn wait(program: &Program) -> chrono::Duration {
+    match program.next_wait_days {
+        Some(days) => chrono::Duration::days(i64::from(days)),
+        None => interval(program),
+    }
+}
+
+// An empty airing window means any day is fine
+fn in_airing_window(program: &Program, at: DateTime<Utc>) -> bool {
+    program.airing_days.is_empty() || program.airing_days.contains(&Zone::of(program).weekday(at))
+}
+
+// When the program last aired as far as its schedule goes
+fn schedule_base(program: &Program) -> Option<DateTime<Utc>> {
+    program.schedule_slot.or(program.last_update)
+}
+
+fn should_rollover(program: &Program, blackouts: &[Blackout]) -> bool {
+    let zone = Zone::of(program);
+    let now = Utc::now();
+    if !in_airing_window(program, now) {
+        return false;
+    }
+    if blackouts.iter().any(|b| b.contains(zone.date(now))) {
+        return false;
+    }
+
+    match schedule_base(program) {
+        None => true, // First time, always rollover
+        Some(last) => {
+            let due = last + wait(program);
+            defer_for_blackouts(program, blackouts, due).is_some_and(|due| now >= due)
+        }
+    }
+}
+
+// When the scheduled update will next roll this program over, mirroring
+// should_rollover. None if it won't air again without intervention.
+fn next_rollover_time(program: &Program, blackouts: &[Blackout]) -> Option<DateTime<Utc>> {
+    if program.pinned || program.custom_status.is_some() || !matches!(program.status, ProgramStatus::Ready | ProgramStatus::Running) {
+        return None;
+    }
+
+    let mut at = match schedule_base(program) {
+        None => Utc::now(),
+        Some(last) => last + wait(program),
+    };
+    // Blackouts and the airing window can each push the other along
+    let zone = Zone::of(program);
+    for _ in 0..=7 + blackouts.len() {
+        at = defer_for_blackouts(program, blackouts, at)?;
+        if in_airing_window(program, at) {
+            return Some(at);
+        }
+        // Move to the start of the next day in the airing window, in the program's zone
+        let next_day = zone.date(at).checked_add_days(Days::new(1))?;
+        at = zone.start_of_day(next_day)?;
+    }
+    None
+}
+
+// Roll a program over if it is due, or regardless with `force`. A forced
+// rollover is the program's occurrence for this interval: the interval
+// restarts from it, so neither a scheduled update nor --catch-up-limit airs
+// the program again until the interval has passed once more. Outside the
+// airing window it is scheduled as the slot it stands in for, the one missed
+// or the next one, so forcing on an off day doesn't move the airing days
+// along.
+fn update_program_symlink(db: &mut Database, program_hash: &str, force: Force) -> Result<(), Box<dyn std::error::Error>> {
+    let symlink_dir = db.symlink_dir.clone()
+        .ok_or("Symlink directory not configured. Use 'queuecast config symlink-dir <path>' to set it.")?;
+
+    let program = db.programs.get(program_hash)
+        .ok_or("Program not found")?;
+    check_position(program)?;
+    let plan = plan_rollover(db, program, force, budget_left(db))?;
+    if let RolloverOutcome::Held(hold) = &plan.outcome {
+        println!("{}", hold.describe(program));
+        return Ok(());
+    }
+
+    // Create symlink directory if it doesn't exist
+    create_link_folder(&symlink_dir)?;
+
+    let slot = (force != Force::No && !in_airing_window(program, Utc::now()))
+        .then(|| next_rollover_time(program, &db.blackouts))
+        .flatten();
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    if plan.start {
+        program.status = ProgramStatus::Running;
+        program.start_date = Some(Utc::now());
+    }
+    if let Some(season) = plan.confirm_season {
+        program.status = ProgramStatus::Running;
+        program.confirmed_season = season;
+    }
+
+    // Pass over skipped episodes so the next one that should air is current
+    for episode in program.episodes.get(program.current_episode..plan.resume_at).unwrap_or_default() {
+        println!("Skipped {} episode {} ({})", program.name, episode.episode_number, episode.skipped.unwrap_or(SkipReason::Manual));
+    }
+    program.current_episode = plan.resume_at;
+
+    match plan.outcome {
+        RolloverOutcome::Idle | RolloverOutcome::Held(_) => return Ok(()),
+        RolloverOutcome::Finish => {
+            program.status = ProgramStatus::Finished;
+            program.stall = None;
+            if db.on_finish_symlink == OnFinishSymlink::Remove {
+                for link in finished_links(program) {
+                    remove_ready_marker(&link)?;
+                    remove_sidecar(&link)?;
+                    remove_symlink(&link)?;
+                    program.active_links.retain(|active| active.path != link);
+                    println!("Removed {} now that {} has finished", link.display(), program.name);
+                }
+            }
+            return Ok(());
+        }
+        RolloverOutcome::AwaitSeason(next_season) => {
+            program.status = ProgramStatus::AwaitingConfirmation;
+            println!("{} reached the end of a season, run 'queuecast continue {}' to air season {}",
+                program.name, program.hash, next_season);
+            return Ok(());
+        }
+        RolloverOutcome::Air => {}
+    }
+
+    let result = air_batch(db, program_hash, &symlink_dir, plan.limit);
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    match result {
+        Ok(()) => {
+            program.stall = None;
+            program.schedule_slot = slot;
+            Ok(())
+        }
+        Err(RolloverFailure(reason, e)) => {
+            record_stall(program, reason);
+            // A veto is the hook doing its job and a download finishes on
+            // its own, neither is an error
+            if matches!(reason, StallReason::HookVeto | StallReason::StillDownloading) {
+                println!("{}", e);
+                return Ok(());
+            }
+            Err(e)
+        }
+    }
+}
+
+// A rollover whose links are being changed but whose progress isn't saved
+// yet. The journal is written before the first link is touched and removed
+// once the database is saved, so a run that dies in between (a crash, a
+// failed save) leaves it behind, and the next start undoes the links.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct RolloverIntent {
+    // Process making the rollover; its journal is left alone while it runs
+    pid: u32,
+    program_hash: String,
+    // current_episode before and after the rollover
+    from: usize,
+    to: usize,
+    // Links made, with what each replaced
+    links: Vec<IntentLink>,
+    // Older links the rollover unlinks (keep_window)
+    #[serde(default)]
+    unlinked: Vec<IntentLink>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IntentLink {
+    path: PathBuf,
+    target: PathBuf,
+    previous: Option<PathBuf>,
+}
+
+const JOURNAL_PREFIX: &str = "rollover-journal";
+
+// Each process journals to a file of its own, so a cron update running next
+// to the watch daemon never replaces or removes the daemon's entries
+fn journal_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(Database::get_database_path()?.with_file_name(format!("{}-{}.json", JOURNAL_PREFIX, process::id())))
+}
+
+// Written under a temporary name and renamed over like the dashboard, so a
+// crash never leaves half a journal
+fn write_journal(intents: &[RolloverIntent]) -> Result<(), Box<dyn std::error::Error>> {
+    // Simulated rollovers make no links to undo
+    if simulating() {
+        return Ok(());
+    }
+    let path = journal_path()?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(intents)?)?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+fn remove_journal() -> Result<(), Box<dyn std::error::Error>> {
+    if simulating() {
+        return Ok(());
+    }
+    match fs::remove_file(journal_path()?) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+// Record the rollover about to happen
+fn journal_intent(intents: &mut Vec<RolloverIntent>, intent: RolloverIntent) -> Result<(), Box<dyn std::error::Error>> {
+    intents.retain(|pending| pending.program_hash != intent.program_hash || pending.from != intent.from);
+    intents.push(intent);
+    write_journal(intents)
+}
+
+#[cfg(unix)]
+fn process_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks; EPERM means it exists under another user
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+fn process_running(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, ERROR_ACCESS_DENIED, STILL_ACTIVE};
+    use windows_sys::Win32::System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    // SAFETY: the handle is checked before use and closed once
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            // Like EPERM, it exists under another user
+            return GetLastError() == ERROR_ACCESS_DENIED;
+        }
+        let mut code = 0u32;
+        let running = GetExitCodeProcess(handle, &mut code) != 0 && code == STILL_ACTIVE as u32;
+        CloseHandle(handle);
+        running
+    }
+}
+
+// Without a way to tell, another process's journal is left alone rather
+// than undoing links it may still be making
+#[cfg(not(any(unix, windows)))]
+fn process_running(_pid: u32) -> bool {
+    true
+}
+
+// Put the links a journaled rollover changed back the way they were
+fn undo_intent(intent: &RolloverIntent) -> Result<(), Box<dyn std::error::Error>> {
+    for link in &intent.links {
+        // Only undo links that are still the ones the rollover made
+        if fs::read_link(&link.path).ok().as_ref() != Some(&link.target) {
+            continue;
+        }
+        // A leftover marker or sidecar is untidy, a leftover link is wrong
+        if let Err(e) = remove_ready_marker(&link.path).and_then(|_| remove_sidecar(&link.path)) {
+            eprintln!("WARNING: Could not remove the files beside {}: {}", link.path.display(), e);
+        }
+        match &link.previous {
+            Some(previous) => replace_symlink(previous, &link.path)?,
+            None => remove_symlink(&link.path)?,
+        }
+    }
+    for link in &intent.unlinked {
+        if fs::symlink_metadata(&link.path).is_err() {
+            create_symlink(&link.target, &link.path)?;
+        }
+    }
+    Ok(())
+}
+
+// Undo the links of journaled rollovers whose progress never reached the
+// database: a program short of `to` never saved the rollover. Journals of
+// processes still running are theirs to settle.
+fn recover_interrupted_rollovers(db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+    // --no-save leaves links and journals as they are
+    if simulating() {
+        return Ok(());
+    }
+    let database_path = Database::get_database_path()?;
+    let Some(dir) = database_path.parent() else {
+        return Ok(());
+    };
+    let mut journals: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.file_name().and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(JOURNAL_PREFIX) && name.ends_with(".json")))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    journals.sort();
+
+    for path in journals {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            // Its process settled it since the listing
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let intents: Vec<RolloverIntent> = serde_json::from_str(&content)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+        if intents.iter().any(|intent| intent.pid != process::id() && process_running(intent.pid)) {
+            continue;
+        }
+
+        for intent in &intents {
+            let Some(program) = db.programs.get(&intent.program_hash) else {
+                continue;
+            };
+            if program.current_episode >= intent.to {
+                continue;
+            }
+            undo_intent(intent)?;
+            eprintln!("Undid the links of an interrupted rollover of '{}' (episodes {} to {}) that was never saved",
+                program.name, intent.from + 1, intent.to);
+        }
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Take a rollover that failed part way back out: its links are undone and
+// the program and history restored, so nothing stays on air that the
+// database doesn't record. If the links can't be undone the intent stays
+// journaled for the next run to retry.
+fn abandon_rollover(db: &mut Database, intent: &RolloverIntent, before: Program, history_len: usize) {
+    db.history.truncate(history_len);
+    db.programs.insert(intent.program_hash.clone(), before);
+    let undone = undo_intent(intent).and_then(|_| {
+        db.intents.retain(|pending| pending.program_hash != intent.program_hash || pending.from != intent.from);
+        if db.intents.is_empty() {
+            remove_journal()
+        } else {
+            write_journal(&db.intents)
+        }
+    });
+    if let Err(e) = undone {
+        eprintln!("WARNING: Could not undo the links of the failed rollover of episodes {} to {}: {}; the next run retries",
+            intent.from + 1, intent.to, e);
+    }
+}
+
+// Air the batch of a program whose rollover is due
+fn air_batch(db: &mut Database, program_hash: &str, symlink_dir: &Path, limit: Option<usize>) -> Result<(), RolloverFailure> {
+    let renamed = db.programs.get(program_hash).and_then(|program| find_renamed_directory(db, program));
+    let download_markers = download_markers(db);
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    // Never let one episode's link overwrite another's
+    check_link_names(&db.link_naming, program)
+        .map_err(|e| RolloverFailure(StallReason::LinkClash, e))?;
+
+    // An unmounted drive shows up as the whole folder missing
+    if !program.curated && program.remote.is_none() && !program.directory.exists() {
+        let hint = renamed.map(|found| format!(", it looks renamed to {} (see 'queuecast check')", found.directory.display()));
+        return Err(RolloverFailure(StallReason::SourceUnavailable, format!("{} is missing{}", program.directory.display(), hint.unwrap_or_default()).into()));
+    }
+    let whole_season = season_batch(program).is_some();
+    let batch = rollover_batch(program);
+    let batch = limit_batch(program, batch, limit);
+    if !program.archive && program.remote.is_none() {
+        let missing = program.episodes[batch.clone()].iter()
+            .filter(|e| e.skipped.is_none())
+            .flat_map(|e| e.parts())
+            .find(|part| !part.exists());
+        if let Some(missing) = missing {
+            return Err(RolloverFailure(StallReason::EpisodeMissing, format!("Episode file {} is missing", missing.display()).into()));
+        }
+        let parts: Vec<&Path> = program.episodes[batch.clone()].iter()
+            .filter(|e| e.skipped.is_none())
+            .flat_map(|e| e.parts())
+            .map(PathBuf::as_path)
+            .collect();
+        let settle = db.download_settle_seconds.unwrap_or(DEFAULT_DOWNLOAD_SETTLE_SECONDS);
+        if let Some(why) = incomplete_download(&parts, &download_markers, settle) {
+            return Err(RolloverFailure(StallReason::StillDownloading, format!("{} is still downloading ({}), trying again next update", program.name, why).into()));
+        }
+    }
+    let now = Utc::now();
+
+    let first = program.episodes.get(batch.start)
+        .ok_or_else(|| position_error(program))?;
+    let hook_env = vec![
+        ("QC_PROGRAM_NAME", program.name.clone()),
+        ("QC_PROGRAM_HASH", program.hash.clone()),
+        ("QC_EPISODE_NUMBER", first.episode_number.to_string()),
+        ("QC_EPISODE_PATH", first.path.display().to_string()),
+        ("QC_EPISODE_TITLE", first.title()),
+        ("QC_SYMLINK_PATH", episode_links(symlink_dir, &db.link_naming, program, first)[0].1.display().to_string()),
+        ("QC_PREVIOUS_EPISODE_PATH", batch.start.checked_sub(1)
+            .and_then(|i| program.episodes.get(i))
+            .map(|e| e.path.display().to_string())
+            .unwrap_or_default()),
+        ("QC_REMAINING", program.episodes.len().saturating_sub(batch.end).to_string()),
+        ("QC_FINISHED", if batch.end >= program.episodes.len() { "1" } else { "0" }.to_string()),
+        ("QC_LINK_MODE", "symlink".to_string()),
+    ];
+
+    if let Some(pre_hook) = program.hooks.pre.resolve(db.hooks.pre.as_deref()) {
+        let vetoed = !run_hook("pre", pre_hook, &hook_env)
+            .map_err(|e| RolloverFailure(StallReason::HookVeto, e))?;
+        if vetoed {
+            return Err(RolloverFailure(StallReason::HookVeto, format!("Pre hook vetoed the rollover of {}", program.name).into()));
+        }
+    }
+
+    if let Some(min_free) = db.min_free_bytes.filter(|_| (program.archive || program.remote.is_some()) && !simulating()) {
+        ensure_cache_space(program, batch.clone(), min_free, db.prune_for_space)
+            .map_err(|e| RolloverFailure(StallReason::NoSpace, e))?;
+    }
+
+    // Extract or download everything up front so a failure doesn't leave
+    // half a batch linked. Nothing is linked when simulating, so nothing
+    // needs to be there.
+    if program.archive && !simulating() {
+        for episode in program.episodes[batch.clone()].iter().filter(|e| e.skipped.is_none()) {
+            extract_archive_episode(&program.directory, episode)?;
+        }
+    }
+    if let Some(url) = program.remote.as_ref().filter(|_| !simulating()) {
+        let source = SftpSource::parse(url)?;
+        for episode in program.episodes[batch.clone()].iter().filter(|e| e.skipped.is_none()) {
+            fetch_remote_episode(&source, episode)
+                .map_err(|e| RolloverFailure(StallReason::SourceUnavailable, e))?;
+        }
+    }
+
+    let links: Vec<IntentLink> = program.episodes[batch.clone()].iter()
+        .filter(|e| e.skipped.is_none())
+        .flat_map(|episode| episode_links(symlink_dir, &db.link_naming, program, episode))
+        .map(|(target, path)| IntentLink { previous: fs::read_link(&path).ok(), path, target: target.to_path_buf() })
+        .collect();
+    let mut intent = RolloverIntent {
+        pid: process::id(),
+        program_hash: program.hash.clone(),
+        from: program.current_episode,
+        to: batch.end,
+        links,
+        unlinked: Vec::new(),
+    };
+    journal_intent(&mut db.intents, intent.clone())?;
+
+    let before = program.clone();
+    let history_len = db.history.len();
+    if let Err(e) = link_batch(db, program_hash, symlink_dir, batch, whole_season, now, &mut intent) {
+        abandon_rollover(db, &intent, before, history_len);
+        return Err(e);
+    }
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    if let Some(post_hook) = program.hooks.post.resolve(db.hooks.post.as_deref()) {
+        if !run_hook("post", post_hook, &hook_env)? {
+            eprintln!("Post hook for {} failed", program.name);
+        }
+    }
+
+    let milestones = db.milestones.as_deref().unwrap_or(DEFAULT_MILESTONES);
+    for (position, milestone) in milestones_in_order(milestones, program.episodes.len()) {
+        let reached = match milestone {
+            Milestone::FinaleNext => program.current_episode == position,
+            Milestone::Percent(_) => program.current_episode >= position,
+        };
+        if !reached || program.milestones_reached.contains(&milestone) {
+            continue;
+        }
+        program.milestones_reached.push(milestone);
+        println!("{}", milestone.describe(program));
+        if let Some(milestone_hook) = &db.hooks.milestone {
+            let mut env = hook_env.clone();
+            env.push(("QC_MILESTONE", milestone.key()));
+            env.push(("QC_AIRED", program.current_episode.to_string()));
+            env.push(("QC_TOTAL", program.episodes.len().to_string()));
+            if !run_hook("milestone", milestone_hook, &env)? {
+                eprintln!("Milestone hook for {} failed", program.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Link the batch `intent` journals and record it as aired. Whatever fails
+// part way is left for air_batch to undo.
+fn link_batch(db: &mut Database, program_hash: &str, symlink_dir: &Path, batch: std::ops::Range<usize>, whole_season: bool, now: DateTime<Utc>, intent: &mut RolloverIntent) -> Result<(), RolloverFailure> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    for episode in &program.episodes[batch.clone()] {
+        if let Some(reason) = episode.skipped {
+            println!("Skipped {} episode {} ({})", program.name, episode.episode_number, reason);
+            continue;
+        }
+
+        for (target, symlink_path) in episode_links(symlink_dir, &db.link_naming, program, episode) {
+            // Replace an existing symlink, even one whose target is gone, but
+            // never a real file someone put in its place
+            if let Ok(metadata) = fs::symlink_metadata(&symlink_path) {
+                if !metadata.file_type().is_symlink() {
+                    return Err(RolloverFailure(StallReason::LinkClash, format!("{} is not a symlink, refusing to replace it", symlink_path.display()).into()));
+                }
+                remove_ready_marker(&symlink_path)?;
+                remove_sidecar(&symlink_path)?;
+            }
+
+            if let Some(parent) = symlink_path.parent() {
+                create_link_folder(parent)?;
+            }
+            replace_symlink(target, &symlink_path)?;
+            if let Some(template) = &db.nfo_template {
+                write_sidecar(&symlink_path, render_nfo(template, program, episode).as_bytes())?;
+            }
+            if db.ready_markers {
+                write_ready_marker(&symlink_path)?;
+            }
+            program.active_links.retain(|link| link.path != symlink_path);
+            program.active_links.push(ActiveLink { path: symlink_path, target: target.to_path_buf(), created: now });
+        }
+        
+        println!("Created symlink for {} episode {}", program.name, episode.episode_number);
+
+        let entry = HistoryEntry::new(program.hash.clone(), program.name.clone(), episode.episode_number, episode.path.clone(), now);
+
+        // Re-airs are already in the archive; imported history aired elsewhere
+        // and never reached it, as the weekly budget doesn't count it either
+        let aired_before = db.history.iter()
+            .any(|e| e.program_hash == entry.program_hash && e.episode_number == entry.episode_number && !e.imported);
+        if let (Some(archive_dir), false) = (&db.archive_dir, aired_before) {
+            let line = episode_m3u_entries(&program.name, episode, |part| part.display().to_string());
+            append_to_playlist(&archive_dir.join("aired.m3u8"), &line)?;
+            if db.archive_per_program {
+                append_to_playlist(&archive_dir.join(format!("{}.m3u8", symlink_stem(&program.name, db.link_naming.replacement()))), &line)?;
+            }
+        }
+
+        db.history.push(entry);
+    }
+
+    if let Some(keep) = program.keep_window {
+        let stale: Vec<PathBuf> = links_outside_window(&program.active_links, keep).into_iter()
+            .map(|link| link.path.clone())
+            .collect();
+        if !stale.is_empty() {
+            intent.unlinked = program.active_links.iter()
+                .filter(|link| stale.contains(&link.path))
+                .map(|link| IntentLink { path: link.path.clone(), target: link.target.clone(), previous: None })
+                .collect();
+            journal_intent(&mut db.intents, intent.clone())?;
+        }
+        for link in stale {
+            remove_ready_marker(&link)?;
+            remove_sidecar(&link)?;
+            match remove_symlink(&link) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            program.active_links.retain(|active| active.path != link);
+            println!("Removed {}, older than the last {} rollover(s) of {}", link.display(), keep, program.name);
+        }
+    }
+    
+    // Advance past the aired episodes and update timestamp
+    program.next_wait_days = wait_after(program, &batch, whole_season);
+    if let Some(days) = program.next_wait_days {
+        println!("Released a whole season of {}, next rollover in {} day(s)", program.name, days);
+    }
+    program.current_episode = batch.end;
+    // Rollovers count from when they happen, catch_up_program redates its own
+    program.last_update = Some(now);
+    program.schedule_slot = None;
+    Ok(())
+}
+
+fn set_part_markers(db: &mut Database, markers: Option<Vec<String>>) -> Result<(), Box<dyn std::error::Error>> {
+    let markers = markers.map(|markers| markers.iter().map(|marker| marker.trim().to_lowercase()).collect::<Vec<_>>());
+    if let Some(bad) = markers.iter().flatten().find(|marker| marker.is_empty() || !marker.chars().all(char::is_alphabetic)) {
+        return Err(format!("Part marker '{}' must be a word, like part or disc; the number follows it", bad).into());
+    }
+
+    db.part_markers = markers;
+    match part_markers(db).as_slice() {
+        [] => println!("Files are no longer grouped into multi-part episodes by name ('join-parts' still groups them)"),
+        markers => println!("Consecutive files marked {} and a number (like '{}1') form one episode", markers.join(", "), markers[0]),
+    }
+    println!("Programs pick this up when rescanned");
+    Ok(())
+}
+
+fn set_milestones(db: &mut Database, milestones: Vec<Milestone>) -> Result<(), Box<dyn std::error::Error>> {
+    if milestones.is_empty() {
+        println!("No milestones");
+    } else {
+        let names: Vec<String> = milestones.iter().map(|m| m.to_string()).collect();
+        println!("Milestones: {}", names.join(", "));
+    }
+    db.milestones = Some(milestones);
+    Ok(())
+}
+
+// Milestones the running programs are projected to reach, soonest first
+fn show_milestones(db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+    let milestones = db.milestones.as_deref().unwrap_or(DEFAULT_MILESTONES);
+    let now = Utc::now();
+    let zone = Zone::database();
+    let mut upcoming = Vec::new();
+    for program in db.programs.values() {
+        let rollovers = projected_rollovers(program, &db.blackouts);
+        for (position, milestone) in milestones_in_order(milestones, program.episodes.len()) {
+            if program.milestones_reached.contains(&milestone) {
+                continue;
+            }
+            let at = rollovers.iter().find(|(_, batch)| match milestone {
+                Milestone::FinaleNext => batch.end == position,
+                Milestone::Percent(_) => batch.end >= position,
+            });
+            if let Some((at, _)) = at {
+                upcoming.push((*at, milestone, program));
+            }
+        }
+    }
+    if upcoming.is_empty() {
+        println!("No milestones ahead");
+        return Ok(());
+    }
+    upcoming.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.name.cmp(&b.2.name)));
+    for (at, milestone, program) in upcoming {
+        let days = zone.date(at).signed_duration_since(zone.date(now)).num_days();
+        let when = match days {
+            ..=0 => "at the next update".to_string(),
+            1..=13 => format!("in {} day(s)", days),
+            _ => format!("in {} weeks", days / 7),
+        };
+        println!("{}  {}: {} {}", format_time(at), program.name, milestone, when);
+    }
+    Ok(())
+}
+
+const DEFAULT_DOWNLOAD_MARKERS: &[&str] = &[".part", ".!qB", ".crdownload"];
+const DEFAULT_DOWNLOAD_SETTLE_SECONDS: u32 = 2;
+
+fn download_markers(db: &Database) -> Vec<String> {
+    match &db.download_markers {
+        Some(markers) => markers.clone(),
+        None => DEFAULT_DOWNLOAD_MARKERS.iter().map(|marker| marker.to_string()).collect(),
+    }
+}
+
+// Why episode files look half downloaded, if they do: a marker file next to
+// one ("Show.mkv.part" or "Show.part"), an empty file, or a file whose size
+// changes within `settle_seconds`. Folder episodes are left to their files'
+// own names.
+fn incomplete_download(parts: &[&Path], markers: &[String], settle_seconds: u32) -> Option<String> {
+    let files: Vec<&Path> = parts.iter().copied().filter(|part| !part.is_dir()).collect();
+    for file in &files {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        for marker in markers {
+            let candidates = [file.with_file_name(format!("{}{}", name, marker)), file.with_extension(marker.trim_start_matches('.'))];
+            if let Some(found) = candidates.iter().find(|candidate| candidate.as_path() != *file && candidate.exists()) {
+                return Some(format!("{} exists", found.display()));
+            }
+        }
+        if fs::metadata(file).is_ok_and(|metadata| metadata.len() == 0) {
+            return Some(format!("{} is empty", file.display()));
+        }
+    }
+    if settle_seconds == 0 || files.is_empty() {
+        return None;
+    }
+    let size = |file: &Path| fs::metadata(file).ok().map(|metadata| metadata.len());
+    let before: Vec<Option<u64>> = files.iter().map(|file| size(file)).collect();
+    std::thread::sleep(Duration::from_secs(u64::from(settle_seconds)));
+    files.iter().zip(before)
+        .find(|(file, before)| size(file) != *before)
+        .map(|(file, _)| format!("{} is still growing", file.display()))
+}
+
+fn set_download_markers(db: &mut Database, markers: Option<Vec<String>>) -> Result<(), Box<dyn std::error::Error>> {
+    let markers = markers.map(|markers| markers.iter().map(|marker| marker.trim().to_string()).collect::<Vec<_>>());
+    if let Some(bad) = markers.iter().flatten().find(|marker| marker.trim_start_matches('.').is_empty() || marker.contains(['/', '\\'])) {
+        return Err(format!("Download marker '{}' must be a file suffix, like .part", bad).into());
+    }
+
+    db.download_markers = markers;
+    match download_markers(db).as_slice() {
+        [] => println!("Episodes are aired without looking for download marker files"),
+        markers => println!("Episodes with a {} file next to them wait until it's gone", markers.join(", ")),
+    }
+    Ok(())
+}
+
+fn set_download_settle(db: &mut Database, seconds: u32) -> Result<(), Box<dyn std::error::Error>> {
+    db.download_settle_seconds = Some(seconds);
+    db.mark_set("/download_settle_seconds");
+    if seconds == 0 {
+        println!("Episodes are aired without checking that their size has settled");
+    } else {
+        println!("Episodes whose size changes within {} second(s) wait for the next update", seconds);
+    }
+    Ok(())
+}
+
+fn record_stall(program: &mut Program, reason: StallReason) {
+    match &mut program.stall {
+        Some(stall) => {
+            stall.attempts += 1;
+            stall.reason = reason;
+        }
+        None => program.stall = Some(Stall { reason, attempts: 1, since: Utc::now() }),
+    }
+}
+
+// A current_episode past the end only comes from a hand-edited or damaged
+// database. Updating would misbehave, so say how to repair it instead.
+fn check_position(program: &Program) -> Result<(), Box<dyn std::error::Error>> {
+    if program.current_episode > program.episodes.len() {
+        return Err(position_error(program));
+    }
+    Ok(())
+}
+
+fn position_error(program: &Program) -> Box<dyn std::error::Error> {
+    format!("'{}' is at episode index {} but has {} episodes, the database may have been edited by hand. \
+        'queuecast check' lists such problems; 'queuecast rescan {}' resumes it after the last file that aired",
+        program.name, program.current_episode, program.episodes.len(), program.hash).into()
+}
+
+// Run a hook command through the shell with the rollover details in its
+// environment. Returns whether it exited successfully; a hook skipped
+// because the rollover is only simulated always does.
+fn run_hook(kind: &str, command: &str, env: &[(&str, String)]) -> Result<bool, Box<dyn std::error::Error>> {
+    let dry_run = simulating();
+    if dry_run && !RUN_HOOKS_ON_DRY_RUN.load(AtomicOrdering::SeqCst) {
+        println!("Would run {} hook '{}'", kind, command);
+        return Ok(true);
+    }
+    #[cfg(unix)]
+    let mut shell = {
+        let mut shell = process::Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    };
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = process::Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    };
+
+    let status = shell
+        .envs(env.iter().map(|(key, value)| (*key, value)))
+        .env("QC_HOOK", kind)
+        .env("QC_DRY_RUN", if dry_run { "1" } else { "0" })
+        .status()
+        .map_err(|e| format!("Could not run {} hook '{}': {}", kind, command, e))?;
+    Ok(status.success())
+}
+
+// The link's whole name plus .nfo, like its .ready marker. Replacing the
+// extension instead would give every disc folder link named like
+// "Mr.Robot_ep01" the same "Mr.nfo".
+fn sidecar_path(link: &Path) -> PathBuf {
+    let mut name = link.file_name().unwrap_or_default().to_os_string();
+    name.push(".nfo");
+    link.with_file_name(name)
+}
+
+// Where older versions put the sidecar of a video file link, with the
+// extension replaced
+fn legacy_sidecar_path(link: &Path) -> Option<PathBuf> {
+    let extension = link.extension()?.to_string_lossy();
+    VIDEO_EXTENSIONS.iter()
+        .any(|video| video.eq_ignore_ascii_case(&extension))
+        .then(|| link.with_extension("nfo"))
+}
+
+fn write_sidecar(link: &Path, content: &[u8]) -> std::io::Result<()> {
+    if simulating() {
+        return Ok(());
+    }
+    fs::write(sidecar_path(link), content)
+}
+
+fn remove_sidecar(link: &Path) -> std::io::Result<()> {
+    if simulating() {
+        return Ok(());
+    }
+    for path in std::iter::once(sidecar_path(link)).chain(legacy_sidecar_path(link)) {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn xml_escape(value: &str) -> String {
+    value.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Fill in {program}, {hash}, {season}, {episode} and {title}, escaped for XML.
+// The title is the one set with set-title, or the file name without its
+// extension.
+fn render_nfo(template: &str, program: &Program, episode: &Episode) -> String {
+    let title = episode.title();
+    template.replace("{program}", &xml_escape(&program.name))
+        .replace("{hash}", &program.hash)
+        .replace("{season}", &episode.season.map(|s| s.to_string()).unwrap_or_default())
+        .replace("{episode}", &episode.episode_number.to_string())
+        .replace("{title}", &xml_escape(&title))
+}
+
+fn set_path_root(db: &mut Database, path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    match path {
+        Some(path) => {
+            let root = Database::get_database_path()?.parent().ok_or("Invalid database path")?.join(path);
+            if !root.is_dir() {
+                return Err(format!("'{}' is not a directory", root.display()).into());
+            }
+            db.path_root = Some(PathBuf::from(path));
+            println!("Storing paths relative to {}", fs::canonicalize(&root)?.display());
+        }
+        None => {
+            db.path_root = None;
+            println!("Storing absolute paths");
+        }
+    }
+    Ok(())
+}
+
+fn set_nfo_template(db: &mut Database, template_file: Option<&str>, disable: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if disable {
+        db.nfo_template = None;
+        println!("Disabled .nfo sidecars");
+        return Ok(());
+    }
+    match template_file {
+        Some(file) => {
+            db.nfo_template = Some(fs::read_to_string(file)
+                .map_err(|e| format!("Could not read template {}: {}", file, e))?);
+            println!("Writing .nfo sidecars from template {}", file);
+        }
+        None => {
+            db.nfo_template = Some(DEFAULT_NFO_TEMPLATE.to_string());
+            println!("Writing .nfo sidecars with the default template");
+        }
+    }
+    Ok(())
+}
+
+// Whether links, the files beside them and hooks are left alone. Under
+// --no-save the changes they go with are dropped, so every helper that
+// touches them checks this and does nothing; commands simulate instead.
+fn simulating() -> bool {
+    NO_SAVE.load(AtomicOrdering::SeqCst)
+}
+
+#[cfg(unix)]
+fn os_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn os_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    if target.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    if simulating() {
+        return Ok(());
+    }
+    os_symlink(target, link)
+}
+
+#[cfg(unix)]
+fn os_remove_symlink(link: &Path) -> std::io::Result<()> {
+    fs::remove_file(link)
+}
+
+// Windows directory symlinks are removed as directories
+#[cfg(windows)]
+fn os_remove_symlink(link: &Path) -> std::io::Result<()> {
+    fs::remove_file(link).or_else(|_| fs::remove_dir(link))
+}
+
+fn remove_symlink(link: &Path) -> std::io::Result<()> {
+    if simulating() {
+        return Ok(());
+    }
+    os_remove_symlink(link)
+}
+
+// Build the link under a temporary name beside `link` and rename it over,
+// so anything watching the directory never sees it missing or half made.
+fn replace_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    if simulating() {
+        return Ok(());
+    }
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(link.file_name().unwrap_or_default());
+    tmp_name.push(".tmp");
+    let tmp = link.with_file_name(tmp_name);
+    let _ = remove_symlink(&tmp);
+    create_symlink(target, &tmp)?;
+    fs::rename(&tmp, link)
+}
+
+// Written after a new link and its sidecar are in place, for consumers that
+// want a race-free signal that an episode is ready
+fn ready_marker_path(link: &Path) -> PathBuf {
+    let mut name = link.file_name().unwrap_or_default().to_os_string();
+    name.push(".ready");
+    link.with_file_name(name)
+}
+
+fn remove_ready_marker(link: &Path) -> std::io::Result<()> {
+    if simulating() {
+        return Ok(());
+    }
+    match fs::remove_file(ready_marker_path(link)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn write_ready_marker(link: &Path) -> std::io::Result<()> {
+    if simulating() {
+        return Ok(());
+    }
+    fs::write(ready_marker_path(link), b"")
+}
+
+// A folder links go in: the symlink or next-up directory, or a program's
+// folder inside one
+fn create_link_folder(folder: &Path) -> std::io::Result<()> {
+    if simulating() {
+        return Ok(());
+    }
+    fs::create_dir_all(folder)
+}
+
+// Remove `folder` if it's empty, then each parent left empty up to `root`,
+// which stays. remove_dir refuses anything but an empty folder.
+fn remove_empty_link_folders(folder: &Path, root: &Path) {
+    if simulating() {
+        return;
+    }
+    for folder in folder.ancestors().take_while(|folder| *folder != root && folder.starts_with(root)) {
+        if fs::remove_dir(folder).is_err() {
+            break;
+        }
+    }
+}
+
+// Characters some system refuses in file names: '/' and NUL everywhere,
+// ':' on macOS, and these on Windows, where control characters and names
+// ending in a dot or space are refused too
+const RESERVED_FILE_NAME_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+// Windows device names, refused as file names whatever the extension
+const RESERVED_FILE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// Longest sanitized name in bytes. File names are capped at 255 bytes on
+// most systems; this leaves room for the extension, a _partN or _sNN suffix
+// and the .ready and .tmp names made beside a link.
+const MAX_FILE_NAME_BYTES: usize = 200;
+
+fn valid_replacement(c: char) -> bool {
+    !RESERVED_FILE_NAME_CHARS.contains(&c) && !c.is_control() && c != '.'
+}
+
+// Make `name` usable as a file name on every system, since the symlink
+// directory may be a share another one reads: reserved and control
+// characters become `replacement`, leading and trailing dots and spaces
+// go, a Windows device name gets `replacement` appended and the result is
+// cut to MAX_FILE_NAME_BYTES.
+fn sanitize_file_name(name: &str, replacement: char) -> String {
+    let replaced: String = name.chars()
+        .map(|c| if RESERVED_FILE_NAME_CHARS.contains(&c) || c.is_control() { replacement } else { c })
+        .collect();
+    let trim = |s: &str| s.trim_matches(|c: char| c == '.' || c.is_whitespace()).to_string();
+    let mut sanitized = trim(&replaced);
+    if sanitized.len() > MAX_FILE_NAME_BYTES {
+        sanitized = trim(truncate_bytes(&sanitized, MAX_FILE_NAME_BYTES));
+    }
+    let device = sanitized.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_FILE_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(device)) {
+        sanitized.insert(device.len(), replacement);
+    }
+    if sanitized.is_empty() {
+        sanitized.push(replacement);
+    }
+    sanitized
+}
+
+fn truncate_bytes(text: &str, max: usize) -> &str {
+    let mut end = max.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+// A name as it appears in link and playlist file names, spaces included
+fn symlink_stem(name: &str, replacement: char) -> String {
+    sanitize_file_name(&name.replace(' ', &replacement.to_string()), replacement)
+}
+
+// Link names keep the target's extension; disc folders have none.
+fn link_file_name(stem: &str, target: &Path, folder: bool) -> String {
+    if folder {
+        stem.to_string()
+    } else {
+        format!("{}.{}", stem, target.extension().unwrap_or_default().to_string_lossy())
+    }
+}
+
+fn render_link_stem(naming: &LinkNaming, program: &Program, episode: &Episode) -> String {
+    // Long names share the length limit so the numbers after them survive.
+    // Spaces are replaced here, everything else once the whole name is put
+    // together.
+    let template = naming.template();
+    let texts = ["{name}", "{original}", "{title}"].iter().filter(|text| template.contains(*text)).count().max(1);
+    let budget = MAX_FILE_NAME_BYTES.saturating_sub(template.len() + 10) / texts;
+    let text = |value: &str| truncate_bytes(value, budget).replace(' ', &naming.replacement().to_string());
+    let stem = template.replace("{name}", &text(&program.name))
+        .replace("{ep}", &format!("{:02}", episode.episode_number))
+        .replace("{season}", &episode.season.map(|s| format!("{:02}", s)).unwrap_or_default())
+        .replace("{original}", truncate_bytes(&episode.path.file_stem().unwrap_or_default().to_string_lossy(), budget))
+        .replace("{title}", &text(&episode.title().replace(['/', '\\'], "-")));
+    sanitize_file_name(&stem, naming.replacement())
+}
+
+// Link name of an episode without extension, disambiguated by season when
+// configured to and another episode of the program renders the same.
+fn link_stem(naming: &LinkNaming, program: &Program, episode: &Episode) -> String {
+    let stem = render_link_stem(naming, program, episode);
+    let clashes = naming.on_duplicate == DuplicateLinkNames::AppendSeason
+        && program.episodes.iter().any(|other| {
+            other.episode_number != episode.episode_number
+                && render_link_stem(naming, program, other) == stem
+        });
+    if clashes {
+        format!("{}_s{:02}", stem, episode.season.unwrap_or_default())
+    } else {
+        stem
+    }
+}
+
+// Error if two episodes of the program would still share a link name, which
+// would make one rollover silently overwrite another's link.
+fn check_link_names(naming: &LinkNaming, program: &Program) -> Result<(), Box<dyn std::error::Error>> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for episode in &program.episodes {
+        let stem = link_stem(naming, program, episode);
+        if let Some(other) = seen.insert(stem.clone(), episode.episode_number) {
+            let hint = match naming.on_duplicate {
+                DuplicateLinkNames::Error => "use --on-duplicate season or put {ep} in the template",
+                DuplicateLinkNames::AppendSeason => "put {ep} in the template",
+            };
+            return Err(format!("Episodes {} and {} of '{}' would both be linked as '{}', {}",
+                other, episode.episode_number, program.name, stem, hint).into());
+        }
+    }
+    Ok(())
+}
+
+fn set_link_naming(db: &mut Database, template: &str, on_duplicate: DuplicateLinkNames) -> Result<(), Box<dyn std::error::Error>> {
+    let naming = LinkNaming { template: Some(template.to_string()), on_duplicate, ..db.link_naming.clone() };
+    for program in db.programs.values() {
+        check_link_names(&naming, program)?;
+    }
+    db.link_naming = naming;
+    db.mark_set("/link_naming/template");
+    println!("Naming new symlinks as '{}'; 'queuecast relink' renames the ones on air", template);
+    Ok(())
+}
+
+fn set_link_replacement(db: &mut Database, replacement: char) -> Result<(), Box<dyn std::error::Error>> {
+    if !valid_replacement(replacement) {
+        return Err(format!("'{}' can't stand in for characters file names can't hold, pick one like _ or -", replacement.escape_default()).into());
+    }
+    let naming = LinkNaming { replacement: Some(replacement), ..db.link_naming.clone() };
+    for program in db.programs.values() {
+        check_link_names(&naming, program)?;
+    }
+    db.link_naming = naming;
+    println!("Spaces and characters file names can't hold become '{}' in new link names; 'queuecast relink' renames the ones on air", replacement);
+    Ok(())
+}
+
+// Folder name for a program's links: its name made safe as a file name,
+// spaces kept
+fn program_dir_name(naming: &LinkNaming, name: &str) -> String {
+    sanitize_file_name(name, naming.replacement())
+}
+
+fn set_program_dirs(db: &mut Database, enabled: bool) -> Result<(), Box<dyn std::error::Error>> {
+    db.link_naming.program_dirs = enabled;
+    db.mark_set("/link_naming/program_dirs");
+    if enabled {
+        println!("New symlinks go in a folder per program; 'queuecast relink' moves the ones on air");
+    } else {
+        println!("New symlinks go straight into the symlink directory; 'queuecast relink' moves the ones on air");
+    }
+    Ok(())
+}
+
+// Each file of the episode paired with the link it airs under. Parts of a
+// multi-part episode get a "_partN" suffix.
+fn episode_links<'a>(symlink_dir: &Path, naming: &LinkNaming, program: &Program, episode: &'a Episode) -> Vec<(&'a Path, PathBuf)> {
+    let mut symlink_dir = match &program.link_dir {
+        Some(link_dir) => symlink_dir.join(link_dir),
+        None => symlink_dir.to_path_buf(),
+    };
+    if naming.program_dirs {
+        symlink_dir.push(program_dir_name(naming, &program.name));
+    }
+    let symlink_dir = symlink_dir.as_path();
+    let stem = link_stem(naming, program, episode);
+    if episode.extra_parts.is_empty() {
+        return vec![(episode.path.as_path(), symlink_dir.join(link_file_name(&stem, &episode.path, episode.folder)))];
+    }
+
+    episode.parts().enumerate()
+        .map(|(i, part)| {
+            let part_stem = format!("{}_part{}", stem, i + 1);
+            (part.as_path(), symlink_dir.join(link_file_name(&part_stem, part, false)))
+        })
+        .collect()
+}
+
+// History entries of the program's most recent rollover, in airing order.
+// Imported airings happened on another machine and say nothing about the
+// links here.
+fn last_aired_batch<'a>(history: &'a [HistoryEntry], program_hash: &str) -> Vec<&'a HistoryEntry> {
+    let Some(latest) = history.iter()
+        .filter(|e| e.program_hash == program_hash && !e.imported)
+        .map(|e| e.aired_at)
+        .max() else {
+        return Vec::new();
+    };
+    history.iter()
+        .filter(|e| e.program_hash == program_hash && !e.imported && e.aired_at == latest)
+        .collect()
+}
+
+// Source file of the first episode in the program's most recent rollover.
+fn last_aired_path<'a>(history: &'a [HistoryEntry], program_hash: &str) -> Option<&'a Path> {
+    last_aired_batch(history, program_hash).first().map(|e| e.path.as_path())
+}
+
+// Remove the program's next-up links, except `keep` if given.
+fn remove_nextup_links(nextup_dir: &Path, naming: &LinkNaming, program: &Program, keep: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = match fs::read_dir(nextup_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let stem = symlink_stem(&program.name, naming.replacement());
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+        let is_programs = file_name.strip_prefix(stem.as_str())
+            .is_some_and(|rest| rest.is_empty() || (rest.starts_with('.') && !rest[1..].contains('.')));
+        if is_symlink && is_programs && Some(path.as_path()) != keep {
+            remove_symlink(&path)?;
+        }
+    }
+    Ok(())
+}
+
+// Point the program's fixed-name next-up link at the episode currently on air,
+// or remove it once the program has finished or been stopped. Missing links are
+// recreated, so running this on every update also heals them.
+fn sync_nextup_link(db: &Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (Some(nextup_dir), Some(program)) = (&db.nextup_dir, db.programs.get(program_hash)) else {
+        return Ok(());
+    };
+
+    let on_air = !matches!(program.status, ProgramStatus::Finished | ProgramStatus::Stopped);
+    let Some(target) = last_aired_path(&db.history, program_hash).filter(|_| on_air) else {
+        return remove_nextup_links(nextup_dir, &db.link_naming, program, None);
+    };
+
+    let link_name = link_file_name(&symlink_stem(&program.name, db.link_naming.replacement()), target, target.is_dir());
+    let link = nextup_dir.join(&link_name);
+
+    if fs::read_link(&link).is_ok_and(|current| current == target) {
+        return Ok(());
+    }
+
+    create_link_folder(nextup_dir)?;
+    replace_symlink(target, &link)?;
+
+    // An episode with a different extension leaves the old link behind
+    remove_nextup_links(nextup_dir, &db.link_naming, program, Some(&link))?;
+    Ok(())
+}
+
+// What an update would do to a program, decided without changing anything.
+// update_program_symlink carries it out and diff prints it, so the two
+// can't disagree.
+struct RolloverPlan {
+    // Ready programs start on their first update
+    start: bool,
+    // A forced update confirms the season it airs into
+    confirm_season: Option<Option<u32>>,
+    // Where the program resumes once skipped episodes are passed over
+    resume_at: usize,
+    outcome: RolloverOutcome,
+    // Most episodes the rollover may air, what's left of the weekly budget
+    limit: Option<usize>,
+}
+
+enum RolloverOutcome {
+    // Not running, or not due yet
+    Idle,
+    // Nothing left to air
+    Finish,
+    // Paused before this season until 'continue'
+    AwaitSeason(u32),
+    // The batch starting at resume_at airs
+    Air,
+    // Would air, but is held back for now
+    Held(Hold),
+}
+
+enum Hold {
+    Pinned,
+    // Forced again this many seconds after its rollover, within the grace
+    Repeated(i64),
+    WeeklyBudget,
+}
+
+impl Hold {
+    fn describe(&self, program: &Program) -> String {
+        match self {
+            Hold::Pinned => format!("{} is pinned, leaving episode {} on air", program.name, program.current_episode),
+            Hold::Repeated(seconds) => format!("{} rolled over {} second(s) ago, leaving it (--really-force to advance again)", program.name, seconds),
+            Hold::WeeklyBudget => format!("{} is due, but the weekly budget is used up until next week", program.name),
+        }
+    }
+}
+
+// Everything an update decides before touching a link, which 'diff' plans
+// with too so the two never disagree. Forcing a pinned program or one in
+// its cooldown is refused here, and a forced update repeated within the
+// grace held, for both. A scheduled rollover airs no more than
+// `budget_left`, and waits when that's nothing.
+fn plan_rollover(db: &Database, program: &Program, force: Force, budget_left: Option<usize>) -> Result<RolloverPlan, Box<dyn std::error::Error>> {
+    let held = |hold| RolloverPlan {
+        start: false,
+        confirm_season: None,
+        resume_at: program.current_episode,
+        outcome: RolloverOutcome::Held(hold),
+        limit: None,
+    };
+    if program.pinned {
+        if force != Force::No {
+            return Err(format!("'{}' is pinned, unpin it first (update --unpin-first)", program.name).into());
+        }
+        return Ok(held(Hold::Pinned));
+    }
+    if force == Force::Yes {
+        if let Some(hold) = force_cooldown(db, program)? {
+            return Ok(held(hold));
+        }
+    }
+    let forced = force != Force::No;
+    let start = program.status == ProgramStatus::Ready;
+    let confirm_season = (forced && program.status == ProgramStatus::AwaitingConfirmation)
+        .then(|| program.episodes.get(program.current_episode).and_then(|e| e.season));
+    let running = program.custom_status.is_none() && (start || confirm_season.is_some() || program.status == ProgramStatus::Running);
+
+    let (resume_at, outcome) = if running && (forced || should_rollover(program, &db.blackouts)) {
+        next_airing(program, forced)
+    } else {
+        (program.current_episode, RolloverOutcome::Idle)
+    };
+    // Forcing a rollover goes past the budget, update warns about it
+    let limit = budget_left.filter(|_| !forced);
+    if matches!(outcome, RolloverOutcome::Air) && limit == Some(0) {
+        return Ok(held(Hold::WeeklyBudget));
+    }
+    Ok(RolloverPlan { start, confirm_season, resume_at, outcome, limit })
+}
+
+// A batch cut short after `limit` episodes that air; skipped ones don't
+// count against it
+fn limit_batch(program: &Program, batch: std::ops::Range<usize>, limit: Option<usize>) -> std::ops::Range<usize> {
+    let Some(limit) = limit else {
+        return batch;
+    };
+    let mut aired = 0;
+    for index in batch.clone() {
+        if program.episodes[index].skipped.is_none() {
+            if aired == limit {
+                return batch.start..index;
+            }
+            aired += 1;
+        }
+    }
+    batch
+}
+
+// Once a rollover is due: where the program resumes past skipped episodes,
+// and whether it airs from there.
+fn next_airing(program: &Program, force: bool) -> (usize, RolloverOutcome) {
+    let mut resume_at = program.current_episode;
+    while program.episodes.get(resume_at).is_some_and(|e| e.skipped.is_some()) {
+        resume_at += 1;
+    }
+
+    if resume_at >= program.episodes.len() {
+        return (resume_at, RolloverOutcome::Finish);
+    }
+    if program.pause_between_seasons && !force {
+        if let Some(next_season) = upcoming_season_boundary(program, resume_at) {
+            if program.confirmed_season != Some(next_season) {
+                return (resume_at, RolloverOutcome::AwaitSeason(next_season));
+            }
+        }
+    }
+    (resume_at, RolloverOutcome::Air)
+}
+
+// One line of 'queuecast diff'
+#[derive(Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+enum PlannedChange {
+    Status { from: ProgramStatus, to: ProgramStatus },
+    Skip { episode: usize, reason: String },
+    Unlink { path: PathBuf },
+    Link { path: PathBuf, target: PathBuf },
+    CurrentEpisode { from: usize, to: usize },
+}
+
+impl PlannedChange {
+    // Link paths are shown relative to the symlink directory
+    fn render(&self, symlink_dir: &Path) -> String {
+        let short = |path: &Path| path.strip_prefix(symlink_dir).unwrap_or(path).display().to_string();
+        match self {
+            PlannedChange::Status { from, to } => format!("~ status {:?} -> {:?}", from, to),
+            PlannedChange::Skip { episode, reason } => format!("~ skip episode {} ({})", episode, reason),
+            PlannedChange::Unlink { path } => format!("- unlink {}", short(path)),
+            PlannedChange::Link { path, target } => format!("+ link {} -> {}", short(path), target.display()),
+            PlannedChange::CurrentEpisode { from, to } => format!("~ current_episode {} -> {}", from, to),
+        }
+    }
+}
+
+// The changes the next update would make to a program, in the order update
+// makes them. Works on a copy, so only ffprobe results are thrown away.
+// Episodes it would air are taken off `budget_left`, for the next program.
+fn planned_changes(db: &Database, symlink_dir: &Path, program: &Program, force: Force, budget_left: &mut Option<usize>) -> Result<Vec<PlannedChange>, Box<dyn std::error::Error>> {
+    check_position(program)?;
+    let plan = plan_rollover(db, program, force, *budget_left)?;
+    let mut program = program.clone();
+    let mut changes = Vec::new();
+
+    let status = match plan.outcome {
+        RolloverOutcome::Finish => ProgramStatus::Finished,
+        RolloverOutcome::AwaitSeason(_) => ProgramStatus::AwaitingConfirmation,
+        _ if plan.start || plan.confirm_season.is_some() => ProgramStatus::Running,
+        _ => program.status.clone(),
+    };
+    if status != program.status {
+        changes.push(PlannedChange::Status { from: program.status.clone(), to: status });
+    }
+
+    let skip = |episode: &Episode| PlannedChange::Skip {
+        episode: episode.episode_number,
+        reason: episode.skipped.unwrap_or(SkipReason::Manual).to_string(),
+    };
+    changes.extend(program.episodes.get(program.current_episode..plan.resume_at).unwrap_or_default().iter().map(skip));
+    let from = program.current_episode;
+    program.current_episode = plan.resume_at;
+
+    if let (RolloverOutcome::Finish, OnFinishSymlink::Remove) = (&plan.outcome, db.on_finish_symlink) {
+        changes.extend(finished_links(&program).into_iter().map(|path| PlannedChange::Unlink { path }));
+    }
+    if let RolloverOutcome::Air = plan.outcome {
+        check_link_names(&db.link_naming, &program)?;
+        let batch = rollover_batch(&mut program);
+        let batch = limit_batch(&program, batch, plan.limit);
+        let aired = program.episodes[batch.clone()].iter().filter(|e| e.skipped.is_none()).count();
+        if let Some(left) = budget_left.as_mut() {
+            *left = left.saturating_sub(aired);
+        }
+        for episode in &program.episodes[batch.clone()] {
+            if episode.skipped.is_some() {
+                changes.push(skip(episode));
+                continue;
+            }
+            for (target, path) in episode_links(symlink_dir, &db.link_naming, &program, episode) {
+                match fs::symlink_metadata(&path) {
+                    Ok(metadata) if !metadata.file_type().is_symlink() => {
+                        return Err(format!("{} is not a symlink, refusing to replace it", path.display()).into());
+                    }
+                    Ok(_) => changes.push(PlannedChange::Unlink { path: path.clone() }),
+                    Err(_) => {}
+                }
+                changes.push(PlannedChange::Link { path, target: target.to_path_buf() });
+            }
+        }
+        // The new rollover takes one place in the window
+        if let Some(keep) = program.keep_window {
+            let relinked: Vec<PathBuf> = program.episodes[batch.clone()].iter()
+                .flat_map(|episode| episode_links(symlink_dir, &db.link_naming, &program, episode))
+                .map(|(_, path)| path)
+                .collect();
+            let remaining: Vec<ActiveLink> = program.active_links.iter()
+                .filter(|link| !relinked.contains(&link.path))
+                .cloned()
+                .collect();
+            changes.extend(links_outside_window(&remaining, keep.saturating_sub(1)).into_iter()
+                .map(|link| PlannedChange::Unlink { path: link.path.clone() }));
+        }
+        program.current_episode = batch.end;
+    }
+
+    if program.current_episode != from {
+        changes.push(PlannedChange::CurrentEpisode { from, to: program.current_episode });
+    }
+    Ok(changes)
+}
+
+type ProgramChanges<'a> = (&'a Program, Result<Vec<PlannedChange>, Box<dyn std::error::Error>>);
+
+// The changes of every program update (or 'update <programs>') would go
+// through, planned in the order it goes through them so the weekly budget
+// runs out on the same programs
+fn planned_update<'a>(db: &'a Database, symlink_dir: &Path, programs: Option<(&[String], Force)>) -> Vec<ProgramChanges<'a>> {
+    let (hashes, force) = match programs {
+        Some((hashes, force)) => (hashes.to_vec(), force),
+        None => {
+            let waiting = waiting_for_slot(db);
+            (scheduled_order(db).into_iter().filter(|hash| !waiting.contains(hash)).collect(), Force::No)
+        }
+    };
+    let mut budget = budget_left(db);
+    hashes.iter()
+        .map(|hash| &db.programs[hash])
+        .map(|program| (program, planned_changes(db, symlink_dir, program, force, &mut budget)))
+        .collect()
+}
+
+// Print what update (or update <program>) would change. Returns the exit
+// code: 0 when nothing would change, 1 when changes are pending and 2 when
+// a program couldn't be planned.
+fn diff_update(db: &Database, program: Option<&str>, force: Force, json: bool) -> Result<i32, Box<dyn std::error::Error>> {
+    let symlink_dir = db.symlink_dir.as_ref()
+        .ok_or("Symlink directory not configured. Use 'queuecast config symlink-dir <path>' to set it.")?;
+
+    let hashes = match program {
+        Some(program) => resolve_programs(db, program)?,
+        None => Vec::new(),
+    };
+    let mut plans = planned_update(db, symlink_dir, program.map(|_| (hashes.as_slice(), force)));
+    plans.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name).then_with(|| a.hash.cmp(&b.hash)));
+
+    let mut code = 0;
+    let mut report = Vec::new();
+    for (program, changes) in plans {
+        match changes {
+            Ok(changes) if changes.is_empty() => {}
+            Ok(changes) => {
+                code = code.max(1);
+                if json {
+                    report.push(serde_json::json!({ "hash": program.hash, "name": program.name, "changes": changes }));
+                } else {
+                    println!("{} [{}]", program.name, program.hash);
+                    for change in &changes {
+                        println!("  {}", change.render(symlink_dir));
+                    }
+                }
+            }
+            Err(e) => {
+                code = 2;
+                if json {
+                    report.push(serde_json::json!({ "hash": program.hash, "name": program.name, "error": e.to_string() }));
+                } else {
+                    println!("{} [{}]", program.name, program.hash);
+                    println!("  ! {}", e);
+                }
+            }
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    Ok(code)
+}
+
+// Pick the episodes to air in this rollover. Without a duration target this is
+// just the current episode; with one, consecutive episodes are taken until their
+// cumulative runtime reaches the target. Episodes ffprobe couldn't measure count
+// as the program's median duration.
+fn rollover_batch(program: &mut Program) -> std::ops::Range<usize> {
+    let start = program.current_episode;
+    if let Some(season) = season_batch(program) {
+        return season;
+    }
+    if program.minutes_per_rollover.is_none() {
+        return fixed_batch(program);
+    }
+
+    for episode in program.episodes.get_mut(start..).unwrap_or_default() {
+        // One stat per part instead of ffprobe reading the file
+        let stamp = FileStamp::of(episode.parts());
+        if episode.duration_secs.is_none() || !metadata_unchanged(episode.probed.as_ref(), stamp.as_ref()) {
+            episode.duration_secs = probe_episode_duration(episode);
+        }
+        episode.probed = stamp;
+    }
+    batch_range(program)
+}
+
+// The next batch_size episodes, not running into a season the program
+// pauses before
+fn fixed_batch(program: &Program) -> std::ops::Range<usize> {
+    let start = program.current_episode;
+    let size = program.batch_size.unwrap_or(1).max(1) as usize;
+    let mut end = (start + size).min(program.episodes.len()).max(start + 1);
+    if program.pause_between_seasons {
+        if let Some(season) = program.episodes.get(start).map(|e| e.season) {
+            end = program.episodes[start..end].iter()
+                .position(|e| e.season != season)
+                .map_or(end, |offset| start + offset);
+        }
+    }
+    start..end
+}
+
+// Links of all but the latest `keep` rollovers
+fn links_outside_window(links: &[ActiveLink], keep: u32) -> Vec<&ActiveLink> {
+    let mut rollovers: Vec<DateTime<Utc>> = links.iter().map(|link| link.created).collect();
+    rollovers.sort_unstable();
+    rollovers.dedup();
+    let dropped = rollovers.len().saturating_sub(keep as usize);
+    let Some(newest_dropped) = dropped.checked_sub(1).map(|i| rollovers[i]) else {
+        return Vec::new();
+    };
+    links.iter().filter(|link| link.created <= newest_dropped).collect()
+}
+
+// With WholeSeason, a rollover reaching the first episode of a season airs
+// the whole season. Episodes without a season number air one at a time.
+fn season_batch(program: &Program) -> Option<std::ops::Range<usize>> {
+    if program.season_release != SeasonRelease::WholeSeason {
+        return None;
+    }
+    let start = program.current_episode;
+    let season = program.episodes.get(start)?.season?;
+    let previous = start.checked_sub(1).and_then(|i| program.episodes.get(i));
+    if previous.is_some_and(|e| e.season == Some(season)) {
+        return None;
+    }
+    let end = program.episodes[start..].iter()
+        .position(|e| e.season != Some(season))
+        .map_or(program.episodes.len(), |offset| start + offset);
+    Some(start..end)
+}
+
+// How long to wait after airing `batch`: a released season holds the
+// schedule back as long as airing it weekly would have, or the cooldown.
+fn wait_after(program: &Program, batch: &std::ops::Range<usize>, whole_season: bool) -> Option<u32> {
+    if !whole_season {
+        return None;
+    }
+    let released = program.episodes[batch.clone()].iter().filter(|e| e.skipped.is_none()).count() as u32;
+    // An interval in hours is rounded up to whole days for the wait
+    let interval_days = match program.interval_secs {
+        Some(secs) => u32::try_from((u64::from(secs) * u64::from(released.max(1))).div_ceil(86400)).unwrap_or(u32::MAX),
+        None => program.interval_days.saturating_mul(released.max(1)),
+    };
+    Some(program.season_cooldown_days.unwrap_or(interval_days))
+}
+
+// The batch rollover_batch would pick using only the durations already known.
+fn batch_range(program: &Program) -> std::ops::Range<usize> {
+    let start = program.current_episode;
+    if let Some(season) = season_batch(program) {
+        return season;
+    }
+    let Some(minutes) = program.minutes_per_rollover else {
+        return fixed_batch(program);
+    };
+
+    let mut known: Vec<u32> = program.episodes.iter().filter_map(|e| e.duration_secs).collect();
+    if known.is_empty() {
+        return start..start + 1;
+    }
+    known.sort_unstable();
+    let median = known[known.len() / 2];
+
+    // Don't let a batch run into the next season when the program pauses there
+    let Some(season) = program.episodes.get(start).map(|e| e.season) else {
+        return start..start;
+    };
+    let limit = if program.pause_between_seasons {
+        program.episodes[start..].iter()
+            .position(|e| e.season != season)
+            .map_or(program.episodes.len(), |offset| start + offset)
+    } else {
+        program.episodes.len()
+    };
+
+    let target = u64::from(minutes) * 60;
+    let mut total = 0;
+    let mut end = start;
+    while let Some(episode) = program.episodes.get(end).filter(|_| end < limit) {
+        if episode.skipped.is_none() {
+            total += u64::from(episode.duration_secs.unwrap_or(median));
+        }
+        end += 1;
+        if total >= target {
+            break;
+        }
+    }
+    start..end
+}
+
+// Future rollovers of a running program and the episodes each will air,
+// following the interval and airing days until the episodes run out or the
+// program pauses for a season.
+fn projected_rollovers(program: &Program, blackouts: &[Blackout]) -> Vec<(DateTime<Utc>, std::ops::Range<usize>)> {
+    let mut rollovers = Vec::new();
+    if program.status != ProgramStatus::Running {
+        return rollovers;
+    }
+
+    let mut projected = program.clone();
+    while let Some(at) = next_rollover_time(&projected, blackouts) {
+        let (resume_at, outcome) = next_airing(&projected, false);
+        if !matches!(outcome, RolloverOutcome::Air) {
+            break;
+        }
+        projected.current_episode = resume_at;
+
+        let whole_season = season_batch(&projected).is_some();
+        let batch = batch_range(&projected);
+        projected.next_wait_days = wait_after(&projected, &batch, whole_season);
+        projected.current_episode = batch.end;
+        projected.last_update = Some(at);
+        projected.schedule_slot = None;
+        rollovers.push((at, batch));
+    }
+    rollovers
+}
+
+// Escape text for an iCalendar property value
+fn ics_escape(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Fold a content line at 75 octets as RFC 5545 asks, without splitting a
+// character, and end it with CRLF.
+fn ics_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn render_calendar(db: &Database) -> String {
+    let stamp = |time: DateTime<Utc>| time.format("%Y%m%dT%H%M%SZ").to_string();
+    let now = stamp(Utc::now());
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//queuecast//schedule//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    let mut programs: Vec<&Program> = db.programs.values().collect();
+    programs.sort_by(|a, b| a.name.cmp(&b.name));
+    for program in programs {
+        for (at, batch) in projected_rollovers(program, &db.blackouts) {
+            let episodes = &program.episodes[batch];
+            let (Some(first), Some(last)) = (episodes.first(), episodes.last()) else {
+                continue;
+            };
+            let summary = if first.episode_number == last.episode_number {
+                format!("{} - Episode {}", program.name, first.episode_number)
+            } else {
+                format!("{} - Episodes {}-{}", program.name, first.episode_number, last.episode_number)
+            };
+            let files: Vec<String> = episodes.iter()
+                .map(|e| e.path.file_name().unwrap_or_default().to_string_lossy().into_owned())
+                .collect();
+
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:{}-{}@queuecast", program.hash, first.episode_number));
+            lines.push(format!("DTSTAMP:{}", now));
+            lines.push(format!("DTSTART:{}", stamp(at)));
+            lines.push(format!("SUMMARY:{}", ics_escape(&summary)));
+            lines.push(format!("DESCRIPTION:{}", ics_escape(&files.join("\n"))));
+            lines.push("END:VEVENT".to_string());
+        }
+    }
+    for blackout in &db.blackouts {
+        let day = |date: NaiveDate| date.format("%Y%m%d").to_string();
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:blackout-{}-{}@queuecast", day(blackout.start), day(blackout.end)));
+        lines.push(format!("DTSTAMP:{}", now));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", day(blackout.start)));
+        // All-day events end on the day after, exclusively
+        lines.push(format!("DTEND;VALUE=DATE:{}", day(blackout.end.succ_opt().unwrap_or(blackout.end))));
+        lines.push("SUMMARY:queuecast blackout".to_string());
+        lines.push("TRANSP:TRANSPARENT".to_string());
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| ics_line(line)).collect()
+}
+
+fn write_calendar(db: &Database, output: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let calendar = render_calendar(db);
+    if output == "-" {
+        print!("{}", calendar);
+    } else {
+        fs::write(output, calendar)?;
+        println!("Wrote calendar to: {}", output);
+    }
+    Ok(())
+}
+
+// Season of the episode at `index` when it differs from the season of the
+// one before it.
+fn upcoming_season_boundary(program: &Program, index: usize) -> Option<u32> {
+    let previous = program.episodes.get(index.checked_sub(1)?)?.season?;
+    let next = program.episodes.get(index)?.season?;
+    (next != previous).then_some(next)
+}
+
+// Roll a program over as often as it was due since its last rollover, up
+// to `limit` times. Each catch-up rollover counts from when it was due
+// rather than from now, so whatever is left over stays due for the next run.
+fn catch_up_program(db: &mut Database, program_hash: &str, limit: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let mut aired = 0;
+    while aired < limit {
+        let program = db.programs.get(program_hash)
+            .ok_or("Program not found")?;
+        let before = program.last_update;
+        let due = schedule_base(program).map(|last| last + wait(program));
+
+        update_program_symlink(db, program_hash, Force::No)?;
+        let program = db.programs.get_mut(program_hash)
+            .ok_or("Program not found")?;
+        if program.last_update == before {
+            break;
+        }
+        aired += 1;
+        if let Some(due) = due {
+            program.last_update = Some(due);
+        }
+    }
+
+    let program = &db.programs[program_hash];
+    if aired > 1 {
+        println!("Caught up {} missed rollover(s) of {}", aired - 1, program.name);
+    }
+    if aired == limit && should_rollover(program, &db.blackouts) {
+        println!("{} is still behind, the next update catches up further", program.name);
+    }
+    Ok(())
+}
+
+// Ready programs in the order they start
+fn ready_queue(db: &Database) -> Vec<String> {
+    let mut ready: Vec<&Program> = db.programs.values()
+        .filter(|p| p.status == ProgramStatus::Ready)
+        .collect();
+    ready.sort_by(|a, b| a.queue_position.cmp(&b.queue_position)
+        .then_with(|| a.name.cmp(&b.name))
+        .then_with(|| a.hash.cmp(&b.hash)));
+    ready.into_iter().map(|p| p.hash.clone()).collect()
+}
+
+// Store `order` as explicit positions, so later ties can't reorder it
+fn set_queue_order(db: &mut Database, order: &[String]) {
+    for (position, hash) in order.iter().enumerate() {
+        if let Some(program) = db.programs.get_mut(hash) {
+            program.queue_position = position as u32 + 1;
+        }
+    }
+}
+
+// Put a newly Ready program at the tail of the queue, or the head
+fn enqueue(db: &mut Database, program_hash: &str, top: bool) {
+    let mut order: Vec<String> = ready_queue(db).into_iter().filter(|hash| hash != program_hash).collect();
+    if top {
+        order.insert(0, program_hash.to_string());
+    } else {
+        order.push(program_hash.to_string());
+    }
+    set_queue_order(db, &order);
+}
+
+// How many more programs may start, None without a max-running limit.
+// Programs labelled with set-status don't take a slot.
+fn free_slots(db: &Database) -> Option<usize> {
+    let max = db.max_running? as usize;
+    let running = db.programs.values()
+        .filter(|p| matches!(p.status, ProgramStatus::Running | ProgramStatus::AwaitingConfirmation) && p.custom_status.is_none())
+        .count();
+    Some(max.saturating_sub(running))
+}
+
+// Ready programs beyond the free slots, which the next update won't start
+fn waiting_for_slot(db: &Database) -> Vec<String> {
+    let queue = ready_queue(db);
+    let startable = queue.iter().filter(|hash| db.programs[*hash].custom_status.is_none());
+    match free_slots(db) {
+        Some(free) => startable.skip(free).cloned().collect(),
+        None => Vec::new(),
+    }
+}
+
+fn show_queue(db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+    let queue = ready_queue(db);
+    if queue.is_empty() {
+        println!("No programs are waiting to start");
+        return Ok(());
+    }
+    let waiting = waiting_for_slot(db);
+    for (position, hash) in queue.iter().enumerate() {
+        let program = &db.programs[hash];
+        let note = if program.custom_status.is_some() {
+            format!(" ({})", status_label(program))
+        } else if waiting.contains(hash) {
+            " (waits for a slot)".to_string()
+        } else {
+            String::new()
+        };
+        println!("{:>3}. {} [{}]{}", position + 1, program.name, program.hash, note);
+    }
+    match (db.max_running, free_slots(db)) {
+        (Some(max), Some(free)) => println!("{} of {} slot(s) free", free, max),
+        _ => println!("No max-running limit, every Ready program starts at the next update"),
+    }
+    Ok(())
+}
+
+// Move a Ready program to `position` (1 for the head, past the end for the tail)
+fn move_in_queue(db: &mut Database, program_hash: &str, position: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get(program_hash)
+        .ok_or("Program not found")?;
+    if program.status != ProgramStatus::Ready {
+        return Err(format!("'{}' is {:?}, only Ready programs are queued", program.name, program.status).into());
+    }
+    let name = program.name.clone();
+
+    let mut order: Vec<String> = ready_queue(db).into_iter().filter(|hash| hash != program_hash).collect();
+    let index = position.saturating_sub(1).min(order.len());
+    order.insert(index, program_hash.to_string());
+    set_queue_order(db, &order);
+    println!("'{}' is now number {} of {} in the queue", name, index + 1, order.len());
+    Ok(())
+}
+
+// Returns the per-program errors of a scheduled update, which are reported
+// but don't stop the other programs from updating. With `catch_up_limit`,
+// the scheduled pass also airs rollovers missed while no update ran, at
+// most that many per program.
+// The order a scheduled update goes through the programs. Ready programs
+// go last, in queue order, so they start in the slots running ones free up.
+// The others go longest due first, which is who gets the rest of a weekly
+// budget.
+fn scheduled_order(db: &Database) -> Vec<String> {
+    let queue = ready_queue(db);
+    let mut program_hashes: Vec<String> = db.programs.keys()
+        .filter(|hash| !queue.contains(hash))
+        .cloned()
+        .collect();
+    program_hashes.sort_by_cached_key(|hash| {
+        let program = &db.programs[hash];
+        (next_rollover_time(program, &db.blackouts).is_none(), next_rollover_time(program, &db.blackouts), program.name.clone())
+    });
+    program_hashes.extend(queue);
+    program_hashes
+}
+
+fn update_symlinks(db: &mut Database, program: Option<(&str, Force)>, catch_up_limit: Option<u32>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut errors = Vec::new();
+    match program {
+        Some((hash, force)) => {
+            // Update specific program, force rollover
+            if let Some((budget, aired)) = budget_reached(db) {
+                eprintln!("Warning: {} episode(s) aired this week, the weekly budget is {}; forcing a rollover anyway", aired, budget);
+            }
+            update_program_symlink(db, hash, force)?;
+            sync_nextup_link(db, hash)?;
+        }
+        None => {
+            // Update all running programs, respect weekly schedule
+            let mut deferred = Vec::new();
+            let mut downloading = Vec::new();
+            for hash in scheduled_order(db) {
+                if db.programs[&hash].status == ProgramStatus::Ready && free_slots(db) == Some(0) {
+                    continue;
+                }
+                let plan = plan_rollover(db, &db.programs[&hash], Force::No, budget_left(db));
+                if let Ok(RolloverPlan { outcome: RolloverOutcome::Held(Hold::WeeklyBudget), .. }) = plan {
+                    deferred.push(db.programs[&hash].name.clone());
+                    continue;
+                }
+                let updated = match catch_up_limit {
+                    Some(limit) => catch_up_program(db, &hash, limit),
+                    None => update_program_symlink(db, &hash, Force::No),
+                };
+                if let Err(e) = updated.and_then(|_| sync_nextup_link(db, &hash)) {
+                    eprintln!("Error updating program {}: {}", hash, e);
+                    errors.push(format!("{}: {}", hash, e));
+                }
+                if db.programs.get(&hash).and_then(|program| program.stall.as_ref()).is_some_and(|stall| stall.reason == StallReason::StillDownloading) {
+                    downloading.push(db.programs[&hash].name.clone());
+                }
+            }
+            if !downloading.is_empty() {
+                println!("Still downloading, trying again next update: {}", downloading.join(", "));
+            }
+            if let (false, Some(budget)) = (deferred.is_empty(), db.weekly_budget) {
+                println!("Weekly budget of {} episode(s) reached, deferred until next week: {}", budget, deferred.join(", "));
+            }
+            if errors.is_empty() {
+                db.last_successful_update = Some(Utc::now());
+            }
+        }
+    }
+    // The links are in place either way, so a dashboard failure only warns
+    if let Err(e) = write_dashboard(db) {
+        eprintln!("WARNING: Could not write dashboard file: {}", e);
+    }
+    Ok(errors)
+}
+
+const DASHBOARD_FORMAT: &str = "queuecast-dashboard";
+const DASHBOARD_VERSION: u32 = 1;
+
+// The dashboard file's contract: fields are only ever added within a version
+#[derive(Serialize)]
+struct Dashboard<'a> {
+    format: &'static str,
+    version: u32,
+    generated_at: DateTime<Utc>,
+    programs: Vec<DashboardProgram<'a>>,
+}
+
+#[derive(Serialize)]
+struct DashboardProgram<'a> {
+    hash: &'a str,
+    name: &'a str,
+    // The episode on air, None before the first rollover
+    episode: Option<DashboardEpisode<'a>>,
+    aired: usize,
+    total: usize,
+    next_air: Option<DateTime<Utc>>,
+    // None for programs added before this was recorded
+    added_at: Option<DateTime<Utc>>,
+    // Set once the program counts as stalled
+    stalled: Option<&'a Stall>,
+}
+
+#[derive(Serialize)]
+struct DashboardEpisode<'a> {
+    number: usize,
+    season: Option<u32>,
+    file: &'a str,
+    aired_at: DateTime<Utc>,
+}
+
+// Running programs by name, with the episode each has on air
+fn dashboard(db: &Database) -> Dashboard<'_> {
+    let mut programs: Vec<&Program> = db.programs.values()
+        .filter(|p| p.status == ProgramStatus::Running)
+        .collect();
+    programs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let programs = programs.into_iter().map(|program| {
+        let episode = last_aired_batch(&db.history, &program.hash).last().map(|aired| DashboardEpisode {
+            number: aired.episode_number,
+            season: program.episodes.iter()
+                .find(|e| e.episode_number == aired.episode_number)
+                .and_then(|e| e.season),
+            file: aired.path.file_name().and_then(|name| name.to_str()).unwrap_or_default(),
+            aired_at: aired.aired_at,
+        });
+        DashboardProgram {
+            hash: &program.hash,
+            name: &program.name,
+            episode,
+            aired: program.current_episode,
+            total: program.episodes.len(),
+            next_air: next_rollover_time(program, &db.blackouts),
+            added_at: program.added_at,
+            stalled: program.stall.as_ref().filter(|_| is_stalled(program)),
+        }
+    }).collect();
+
+    Dashboard {
+        format: DASHBOARD_FORMAT,
+        version: DASHBOARD_VERSION,
+        generated_at: Utc::now(),
+        programs,
+    }
+}
+
+// Written under a temporary name and renamed over, so a dashboard polling
+// the file never reads it half written
+fn write_dashboard(db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = &db.dashboard_file else {
+        return Ok(());
+    };
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(path.file_name().ok_or("Dashboard file has no file name")?);
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    fs::write(&tmp, serde_json::to_string_pretty(&dashboard(db))?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn set_dashboard_file(db: &mut Database, path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    match path {
+        Some(path) => {
+            db.dashboard_file = Some(std::path::absolute(path)?);
+            db.mark_set("/dashboard_file");
+            write_dashboard(db)?;
+            println!("Writing the on-air summary to {} on every update", path);
+        }
+        None => {
+            db.dashboard_file = None;
+            db.mark_set("/dashboard_file");
+            println!("Stopped writing the dashboard file");
+        }
+    }
+    Ok(())
+}
+
+fn database_mtime(config_path: &Path) -> Option<SystemTime> {
+    fs::metadata(config_path).and_then(|m| m.modified()).ok()
+}
+
+fn daemon_socket_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if PORTABLE.get().is_some() {
+        return Ok(state_dir()?.join("daemon.sock"));
+    }
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => Ok(Path::new(&dir).join("queuecast.sock")),
+        _ => Ok(state_dir()?.join("daemon.sock")),
+    }
+}
+
+// One JSON object per line in each direction
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum DaemonRequest {
+    Status,
+    Trigger {
+        program: Option<String>,
+        #[serde(default)]
+        really_force: bool,
+    },
+}
+
+type TriggerReply = mpsc::Sender<Result<String, String>>;
+
+struct DaemonState {
+    started: DateTime<Utc>,
+    last_pass: Option<DateTime<Utc>>,
+    next_pass: Option<DateTime<Utc>>,
+    last_errors: Vec<String>,
+    missed_update: Option<String>,
+    // Passes requested over the socket, run by the main loop
+    triggers: Vec<(Option<String>, bool, TriggerReply)>,
+}
+
+// Removes the socket or pid file when the daemon exits, however it exits
+struct RemoveOnDrop(PathBuf);
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(unix)]
+fn bind_daemon_socket(state: Arc<Mutex<DaemonState>>) -> Result<RemoveOnDrop, Box<dyn std::error::Error>> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let socket_path = daemon_socket_path()?;
+    if socket_path.exists() {
+        if UnixStream::connect(&socket_path).is_ok() {
+            return Err(format!("Another queuecast daemon is already listening on {}", socket_path.display()).into());
+        }
+        // Left behind by a daemon that didn't exit cleanly
+        fs::remove_file(&socket_path)?;
+    }
+
+    let listener = UnixListener::bind(&socket_path)?;
+    std::thread::spawn(move || {
+        // A thread per client, so a trigger waiting for its update doesn't
+        // hold up a status request behind it
+        for stream in listener.incoming().flatten() {
+            let state = Arc::clone(&state);
+            std::thread::spawn(move || {
+                if let Err(e) = handle_daemon_client(&stream, &state) {
+                    eprintln!("Daemon client error: {}", e);
+                }
+            });
+        }
+    });
+    Ok(RemoveOnDrop(socket_path))
+}
+
+#[cfg(not(unix))]
+fn bind_daemon_socket(_state: Arc<Mutex<DaemonState>>) -> Result<RemoveOnDrop, Box<dyn std::error::Error>> {
+    eprintln!("Daemon control is only supported on Unix, trigger and daemon-status won't reach this process");
+    Ok(RemoveOnDrop(daemon_socket_path()?))
+}
+
+#[cfg(unix)]
+fn handle_daemon_client(stream: &std::os::unix::net::UnixStream, state: &Mutex<DaemonState>) -> Result<(), Box<dyn std::error::Error>> {
+    // A client that connects and says nothing doesn't keep its thread forever
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut line = String::new();
+    if BufReader::new(stream).read_line(&mut line)? == 0 {
+        return Ok(()); // Just probing whether a daemon is running
+    }
+
+    let response = match serde_json::from_str::<DaemonRequest>(&line) {
+        Ok(DaemonRequest::Status) => {
+            let state = state.lock().unwrap();
+            serde_json::json!({
+                "ok": true,
+                "started": state.started,
+                "uptime_secs": Utc::now().signed_duration_since(state.started).num_seconds(),
+                "last_pass": state.last_pass,
+                "next_pass": state.next_pass,
+                "last_errors": state.last_errors,
+                "missed_update": state.missed_update,
+            })
+        }
+        Ok(DaemonRequest::Trigger { program, really_force }) => {
+            let (reply, result) = mpsc::channel();
+            state.lock().unwrap().triggers.push((program, really_force, reply));
+            match result.recv_timeout(Duration::from_secs(300)) {
+                Ok(Ok(message)) => serde_json::json!({ "ok": true, "message": message }),
+                Ok(Err(error)) => serde_json::json!({ "ok": false, "error": error }),
+                Err(_) => serde_json::json!({ "ok": false, "error": "Timed out waiting for the daemon" }),
+            }
+        }
+        Err(e) => serde_json::json!({ "ok": false, "error": format!("Bad request: {}", e) }),
+    };
+    let mut stream = stream;
+    writeln!(stream, "{}", response)?;
+    Ok(())
+}
+
+// Send a request to the running daemon. None if no daemon is listening.
+#[cfg(unix)]
+fn daemon_request(request: &DaemonRequest, timeout: Option<Duration>) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>> {
+    use std::os::unix::net::UnixStream;
+
+    let Ok(mut stream) = UnixStream::connect(daemon_socket_path()?) else {
+        return Ok(None);
+    };
+    stream.set_read_timeout(timeout)?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+#[cfg(not(unix))]
+fn daemon_request(_request: &DaemonRequest, _timeout: Option<Duration>) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>> {
+    Ok(None)
+}
+
+fn daemon_status() -> Result<bool, Box<dyn std::error::Error>> {
+    let Some(status) = daemon_request(&DaemonRequest::Status, None)? else {
+        println!("No daemon running");
+        return Ok(false);
+    };
+
+    let time = |key: &str| {
+        serde_json::from_value::<Option<DateTime<Utc>>>(status[key].clone()).ok().flatten()
+            .map(format_time)
+            .unwrap_or_else(|| "never".to_string())
+    };
+    println!("Daemon running since {} ({}s)", time("started"), status["uptime_secs"]);
+    println!("  Last pass: {}", time("last_pass"));
+    println!("  Next pass: {}", time("next_pass"));
+    if let Some(warning) = status["missed_update"].as_str() {
+        println!("  WARNING: {}", warning);
+    }
+    if let Some(errors) = status["last_errors"].as_array().filter(|errors| !errors.is_empty()) {
+        println!("  Last errors:");
+        for error in errors {
+            println!("    {}", error.as_str().unwrap_or_default());
+        }
+    }
+    Ok(true)
+}
+
+// Ask the daemon for an immediate pass. Returns false if no daemon is
+// running, in which case the caller updates in-process instead.
+fn trigger_daemon(program: Option<&str>, really_force: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let request = DaemonRequest::Trigger { program: program.map(str::to_string), really_force };
+    let Some(response) = daemon_request(&request, None)? else {
+        return Ok(false);
+    };
+    if response["ok"].as_bool() != Some(true) {
+        return Err(response["error"].as_str().unwrap_or("Daemon rejected the request").to_string().into());
+    }
+    println!("{}", response["message"].as_str().unwrap_or_default());
+    Ok(true)
+}
+
+// SIGINT and SIGTERM set `shutdown`, SIGHUP sets `reload`. A second SIGINT
+// or SIGTERM exits straight away.
+#[cfg(unix)]
+fn install_signal_handlers(shutdown: &Arc<AtomicBool>, reload: &Arc<AtomicBool>) -> Result<(), Box<dyn std::error::Error>> {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(shutdown))?;
+        signal_hook::flag::register(signal, Arc::clone(shutdown))?;
+    }
+    signal_hook::flag::register(SIGHUP, Arc::clone(reload))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn install_signal_handlers(shutdown: &Arc<AtomicBool>, _reload: &Arc<AtomicBool>) -> Result<(), Box<dyn std::error::Error>> {
+    let handler_flag = Arc::clone(shutdown);
+    ctrlc::set_handler(move || {
+        if handler_flag.swap(true, AtomicOrdering::SeqCst) {
+            process::exit(1);
+        }
+    })?;
+    Ok(())
+}
+
+fn write_pid_file(path: &Path) -> Result<RemoveOnDrop, Box<dyn std::error::Error>> {
+    fs::write(path, format!("{}\n", process::id()))
+        .map_err(|e| format!("Could not write pid file {}: {}", path.display(), e))?;
+    Ok(RemoveOnDrop(path.to_path_buf()))
+}
+
+// Load, update and save, recording the outcome for daemon-status
+fn daemon_pass(state: &Mutex<DaemonState>, program: Option<&str>, really_force: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let mut db = Database::load()?;
+    let errors = match program {
+        // Fails as a whole, like 'queuecast update <program>'
+        Some(program) => {
+            let force = if really_force { Force::Really } else { Force::Yes };
+            for hash in resolve_programs(&db, program)? {
+                update_symlinks(&mut db, Some((&hash, force)), None)?;
+            }
+            Vec::new()
+        }
+        None => update_symlinks(&mut db, None, None)?,
+    };
+    db.save()?;
+
+    let message = if errors.is_empty() {
+        "Update complete".to_string()
+    } else {
+        format!("Update finished with errors:\n{}", errors.join("\n"))
+    };
+    let mut state = state.lock().unwrap();
+    state.last_pass = Some(Utc::now());
+    state.last_errors = errors;
+    state.missed_update = missed_update_warning(&db);
+    Ok(message)
+}
+
+// A scheduled pass that failed as a whole, e.g. on a database another
+// command was writing just then, is logged and shown by daemon-status; the
+// daemon carries on and the next pass retries
+fn record_daemon_error(state: &Mutex<DaemonState>, e: &dyn std::error::Error) {
+    eprintln!("Error: {}", e);
+    let mut state = state.lock().unwrap();
+    state.last_pass = Some(Utc::now());
+    state.last_errors = vec![e.to_string()];
+}
+
+fn scheduled_daemon_pass(state: &Mutex<DaemonState>) {
+    if let Err(e) = daemon_pass(state, None, false) {
+        record_daemon_error(state, e.as_ref());
+    }
+}
+
+// Run scheduled updates forever, sleeping until the next program is due.
+// The database is reloaded before every update, so edits made by other
+// queuecast invocations while we sleep are picked up; SIGHUP forces a reload.
+// On SIGINT or SIGTERM the update in progress finishes before we exit.
+fn watch(min_sleep_secs: u64, pid_file: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let reload = Arc::new(AtomicBool::new(false));
+    install_signal_handlers(&shutdown, &reload)?;
+
+    let state = Arc::new(Mutex::new(DaemonState {
+        started: Utc::now(),
+        last_pass: None,
+        next_pass: None,
+        last_errors: Vec::new(),
+        missed_update: None,
+        triggers: Vec::new(),
+    }));
+    let _socket = bind_daemon_socket(Arc::clone(&state))?;
+    let _pid_file = pid_file.map(write_pid_file).transpose()?;
+
+    let config_path = Database::get_database_path()?;
+    let min_sleep = chrono::Duration::seconds(min_sleep_secs as i64);
+
+    // Only a first pass that fails stops the daemon, before it settles in
+    daemon_pass(&state, None, false)?;
+    while !shutdown.load(AtomicOrdering::SeqCst) {
+        let saved_mtime = database_mtime(&config_path);
+
+        // Programs that failed to update stay due, so never spin faster than min_sleep
+        let next = match Database::load() {
+            Ok(db) => db.programs.values().filter_map(|p| next_rollover_time(p, &db.blackouts)).min()
+                .map(|next| next.max(Utc::now() + min_sleep)),
+            Err(e) => {
+                record_daemon_error(&state, e.as_ref());
+                Some(Utc::now() + min_sleep)
+            }
+        };
+        state.lock().unwrap().next_pass = next;
+        match next {
+            Some(next) => println!("Next rollover at {}", format_time(next)),
+            None => println!("Nothing scheduled, waiting for the database to change"),
+        }
+
+        while !shutdown.load(AtomicOrdering::SeqCst) {
+            let triggers = std::mem::take(&mut state.lock().unwrap().triggers);
+            if !triggers.is_empty() {
+                for (program, really_force, reply) in triggers {
+                    let result = daemon_pass(&state, program.as_deref(), really_force).map_err(|e| e.to_string());
+                    let _ = reply.send(result);
+                }
+                break;
+            }
+            if next.is_some_and(|next| Utc::now() >= next) {
+                scheduled_daemon_pass(&state);
+                break;
+            }
+            if database_mtime(&config_path) != saved_mtime {
+                println!("Database changed on disk, reloading");
+                scheduled_daemon_pass(&state);
+                break;
+            }
+            if reload.swap(false, AtomicOrdering::SeqCst) {
+                println!("Received SIGHUP, reloading");
+                scheduled_daemon_pass(&state);
+                break;
+            }
+            std::thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    // Every update is saved as soon as it finishes, so there is nothing left to flush
+    println!("Stopping");
+    Ok(())
+}
+
+fn remove_program(db: &mut Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get(program_hash)
+        .ok_or("Program not found")?
+        .clone();
+    if let Some(nextup_dir) = &db.nextup_dir {
+        remove_nextup_links(nextup_dir, &db.link_naming, &program, None)?;
+    }
+    // Its links on air go first, and the .nfo and .ready files beside them,
+    // so none is left pointing into a cache removed below. If one can't be
+    // removed, neither is the program. Anything put in a link's place that
+    // isn't a link stays.
+    for link in &program.active_links {
+        if !fs::symlink_metadata(&link.path).is_ok_and(|m| m.file_type().is_symlink()) {
+            continue;
+        }
+        remove_ready_marker(&link.path)
+            .and_then(|_| remove_sidecar(&link.path))
+            .and_then(|_| remove_symlink(&link.path))
+            .map_err(|e| format!("Could not remove {}: {}; '{}' was not removed", link.path.display(), e, program.name))?;
+    }
+
+    db.programs.remove(program_hash);
+    for members in db.collections.values_mut() {
+        members.remove(program_hash);
+    }
+    for weights in db.collection_weights.values_mut() {
+        weights.remove(program_hash);
+    }
+    // Program and link-dir folders left empty go too
+    if let Some(symlink_dir) = &db.symlink_dir {
+        let folders: BTreeSet<&Path> = program.active_links.iter()
+            .filter_map(|link| link.path.parent())
+            .collect();
+        for folder in folders {
+            remove_empty_link_folders(folder, symlink_dir);
+        }
+    }
+
+    // Extracted or downloaded episodes go too, unless a rerun airs them
+    let in_use = |dir: &Path| db.programs.values()
+        .any(|other| other.episodes.iter().flat_map(|e| e.parts()).any(|part| part.starts_with(dir)));
+    let mut caches = Vec::new();
+    if program.archive {
+        caches.push((archive_cache_dir(&program.hash)?, "extracted"));
+    }
+    if program.remote.is_some() {
+        caches.push((program.directory.clone(), "downloaded"));
+    }
+    for (cache, kind) in caches.iter().filter(|_| !simulating()) {
+        if in_use(cache) {
+            println!("Keeping the {} episodes in {}, a rerun of '{}' still airs them", kind, cache.display(), program.name);
+            continue;
+        }
+        match fs::remove_dir_all(cache) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                eprintln!("Could not clean up {} episodes: {}", kind, e);
+            }
+            _ => {}
+        }
+    }
+    println!("Removed program '{}'", program.name);
+    Ok(())
+}
+
+fn set_episode_title(db: &mut Database, program_hash: &str, episode_number: usize, title: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    let name = program.name.clone();
+    let episode = program.episodes.iter_mut()
+        .find(|e| e.episode_number == episode_number)
+        .ok_or_else(|| format!("'{}' has no episode {}", name, episode_number))?;
+
+    episode.title = title.map(str::to_string);
+    match title {
+        Some(title) => println!("Episode {} of '{}' is now titled \"{}\"", episode_number, name, title),
+        None => println!("Episode {} of '{}' is titled from its file name again", episode_number, name),
+    }
+    Ok(())
+}
+
+// Set titles from lines of "<number><TAB><title>"; blank lines and lines
+// starting with # are skipped. Every line is checked before any is applied.
+fn import_episode_titles(db: &mut Database, program_hash: &str, source: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let content = if source == "-" {
+        std::io::read_to_string(std::io::stdin())?
+    } else {
+        fs::read_to_string(source)?
+    };
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    let mut titles = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = line.split_once('\t')
+            .and_then(|(number, title)| Some((number.trim().parse::<usize>().ok()?, title.trim())))
+            .filter(|(_, title)| !title.is_empty());
+        let Some((number, title)) = parsed else {
+            return Err(format!("{} line {}: expected <episode number><TAB><title>", source, line_number + 1).into());
+        };
+        if !program.episodes.iter().any(|e| e.episode_number == number) {
+            return Err(format!("{} line {}: '{}' has no episode {}", source, line_number + 1, program.name, number).into());
+        }
+        titles.push((number, title.to_string()));
+    }
+
+    for (number, title) in &titles {
+        if let Some(episode) = program.episodes.iter_mut().find(|e| e.episode_number == *number) {
+            episode.title = Some(title.clone());
+        }
+    }
+    println!("Set {} episode title(s) of '{}'", titles.len(), program.name);
+    Ok(())
+}
+
+fn set_custom_status(db: &mut Database, program_hash: &str, label: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    match label.map(str::trim) {
+        Some("") => return Err("The status label is empty".into()),
+        Some(label) => {
+            program.custom_status = Some(label.to_string());
+            println!("'{}' is now '{}', updates leave it alone until 'set-status --clear'", program.name, label);
+        }
+        None => {
+            program.custom_status = None;
+            println!("'{}' is back to {:?}", program.name, program.status);
+        }
+    }
+    Ok(())
+}
+
+// The custom label if there is one, with the status scheduling goes by
+fn status_label(program: &Program) -> String {
+    match &program.custom_status {
+        Some(label) => format!("{} ({:?})", label, program.status),
+        None => format!("{:?}", program.status),
+    }
+}
+
+fn stop_program(db: &mut Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    
+    program.status = ProgramStatus::Stopped;
+    println!("Stopped program '{}'", program.name);
+    sync_nextup_link(db, program_hash)?;
+    Ok(())
+}
+
+// Move every program with status `from` to `to`. Only stopping and resuming
+// make sense in bulk: Finished and AwaitingConfirmation are reached by
+// airing, and a Ready program starts by rolling over. A stopped program
+// resumes as Running if it has aired, or joins the queue as Ready if not, so
+// each target only takes the programs it fits.
+fn transition_programs(db: &mut Database, from: ProgramStatus, to: ProgramStatus, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let resuming = match (&from, &to) {
+        _ if from == to => return Err(format!("Programs that are {:?} already are", from).into()),
+        (ProgramStatus::Finished, _) => return Err("Finished programs have no episodes left to air; there is no going back from Finished".into()),
+        (_, ProgramStatus::Finished) => return Err("Programs finish by airing their last episode, not by a status change".into()),
+        (ProgramStatus::AwaitingConfirmation, _) => return Err("Programs awaiting confirmation go on with 'queuecast continue <program>'".into()),
+        (_, ProgramStatus::AwaitingConfirmation) => return Err("Programs only await confirmation at a season break".into()),
+        (ProgramStatus::Ready, ProgramStatus::Running) => return Err("Ready programs start at their first rollover, use 'queuecast update <program>' to start one now".into()),
+        (ProgramStatus::Running, ProgramStatus::Ready) => return Err("Running programs can't start over as Ready".into()),
+        (_, ProgramStatus::Stopped) => false,
+        (ProgramStatus::Stopped, _) => true,
+        _ => unreachable!(),
+    };
+
+    let has_aired = |program: &Program| program.last_update.is_some();
+    let matching: Vec<&Program> = db.programs.values().filter(|p| p.status == from).collect();
+    let hashes: Vec<String> = matching.iter()
+        .filter(|p| !resuming || has_aired(p) == (to == ProgramStatus::Running))
+        .map(|p| p.hash.clone())
+        .collect();
+    let left = matching.len() - hashes.len();
+    if !confirm_programs(db, if resuming { "resume" } else { "stop" }, &hashes, yes, std::io::stdin().is_terminal())? {
+        println!("Nothing changed");
+        return Ok(());
+    }
+
+    for hash in &hashes {
+        if to == ProgramStatus::Stopped {
+            stop_program(db, hash)?;
+            continue;
+        }
+        let program = db.programs.get_mut(hash)
+            .ok_or("Program not found")?;
+        program.status = to.clone();
+        println!("Resumed program '{}' as {:?}", program.name, to);
+        if to == ProgramStatus::Ready {
+            enqueue(db, hash, false);
+        }
+        sync_nextup_link(db, hash)?;
+    }
+    println!("Changed {} program(s) from {:?} to {:?}", hashes.len(), from, to);
+    if left > 0 {
+        let (other, reason) = match to {
+            ProgramStatus::Running => ("ready", "have not aired yet"),
+            _ => ("running", "have already aired"),
+        };
+        println!("{} stopped program(s) {} and stay stopped; use --to {} for them", left, reason, other);
+    }
+    if to == ProgramStatus::Running && free_slots(db) == Some(0) && db.programs.values().any(|p| p.status == ProgramStatus::Ready) {
+        eprintln!("Warning: max-running is reached, Ready programs keep waiting");
+    }
+    Ok(())
+}
+
+fn set_batch_size(db: &mut Database, program_hash: &str, size: u32, keep_window: Option<u32>) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    program.batch_size = (size > 1).then_some(size);
+    program.keep_window = keep_window;
+    match keep_window {
+        Some(keep) => println!("'{}' links {} episode(s) per rollover and keeps the last {} rollover(s) on air", program.name, size, keep),
+        None => println!("'{}' links {} episode(s) per rollover", program.name, size),
+    }
+    if program.minutes_per_rollover.is_some() {
+        eprintln!("Warning: '{}' has minutes per rollover set, which picks its batches instead", program.name);
+    }
+    Ok(())
+}
+
+fn set_season_release(db: &mut Database, program_hash: &str, release: SeasonRelease, cooldown_days: Option<u32>) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    program.season_release = release;
+    program.season_cooldown_days = cooldown_days.filter(|_| release == SeasonRelease::WholeSeason);
+    match (release, cooldown_days) {
+        (SeasonRelease::PerEpisode, _) => println!("'{}' airs one episode per rollover", program.name),
+        (SeasonRelease::WholeSeason, Some(days)) => println!("'{}' releases each new season whole, then waits {} day(s)", program.name, days),
+        (SeasonRelease::WholeSeason, None) => println!("'{}' releases each new season whole, then waits an interval per episode", program.name),
+    }
+    if program.episodes.iter().all(|e| e.season.is_none()) {
+        eprintln!("Warning: no episode of '{}' has a season number (like S02E01), so they still air one at a time", program.name);
+    }
+    Ok(())
+}
+
+fn set_airing_days(db: &mut Database, program_hash: &str, airing_days: Vec<Weekday>) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    if airing_days.is_empty() {
+        println!("Program '{}' may now air on any day", program.name);
+    } else {
+        let days: Vec<String> = airing_days.iter().map(|d| d.to_string()).collect();
+        println!("Program '{}' now airs only on {}", program.name, days.join(", "));
+    }
+    program.airing_days = airing_days;
+    Ok(())
+}
+
+// Give every episode its position as its number again after regrouping.
+fn renumber_episodes(program: &mut Program) {
+    for (i, episode) in program.episodes.iter_mut().enumerate() {
+        episode.episode_number = i + 1;
+    }
+}
+
+fn split_parts(db: &mut Database, program_hash: &str, episode_number: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    let index = unaired_episode_index(program, episode_number)?;
+
+    let episode = &mut program.episodes[index];
+    if episode.extra_parts.is_empty() {
+        return Err(format!("Episode {} has only one part", episode_number).into());
+    }
+    episode.duration_secs = None;
+    episode.probed = None;
+    let extra_parts = std::mem::take(&mut episode.extra_parts);
+
+    let split: Vec<Episode> = extra_parts.into_iter()
+        .map(|part| Episode::new(part, 0))
+        .collect();
+    let count = split.len() + 1;
+    program.episodes.splice(index + 1..index + 1, split);
+    renumber_episodes(program);
+
+    println!("Split episode {} of '{}' into {} episodes", episode_number, program.name, count);
+    Ok(())
+}
+
+fn join_parts(db: &mut Database, program_hash: &str, episode_number: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    let index = unaired_episode_index(program, episode_number)?;
+    if index + 1 >= program.episodes.len() {
+        return Err(format!("Episode {} is the last episode, there is nothing to join it with", episode_number).into());
+    }
+
+    let next = program.episodes.remove(index + 1);
+    let episode = &mut program.episodes[index];
+    episode.extra_parts.extend(next.parts().cloned());
+    episode.duration_secs = None;
+    episode.probed = None;
+    let parts = episode.extra_parts.len() + 1;
+    renumber_episodes(program);
+
+    println!("Joined episodes {} and {} of '{}' into one {}-part episode", episode_number, episode_number + 1, program.name, parts);
+    Ok(())
+}
+
+// Record that the viewer has watched up to and including episode_number,
+// 0 meaning nothing. May be ahead of what has aired.
+fn mark_watched(db: &mut Database, program_hash: &str, episode_number: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    if episode_number == 0 {
+        program.watched_episode = 0;
+        println!("Marked nothing of '{}' as watched", program.name);
+        return Ok(());
+    }
+    let index = program.episodes.iter().position(|e| e.episode_number == episode_number)
+        .ok_or_else(|| format!("Episode {} not found in '{}'", episode_number, program.name))?;
+    program.watched_episode = index + 1;
+    println!("Watched '{}' up to episode {} ({}/{} episodes)", program.name, episode_number, program.watched_episode, program.episodes.len());
+    Ok(())
+}
+
+// Restart the program's interval from now, leaving the episode and its
+// symlink alone.
+fn set_pinned(db: &mut Database, program_hash: &str, pinned: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    program.pinned = pinned;
+    if pinned {
+        println!("Pinned '{}' on episode {}, updates leave it alone until 'queuecast unpin'", program.name, program.current_episode);
+    } else {
+        println!("Unpinned '{}'", program.name);
+    }
+    Ok(())
+}
+
+fn ensure_unpinned(program: &Program) -> Result<(), Box<dyn std::error::Error>> {
+    if program.pinned {
+        return Err(format!("'{}' is pinned, run 'queuecast unpin {}' first", program.name, program.hash).into());
+    }
+    Ok(())
+}
+
+// What the program's latest rollover aired, i.e. what is linked now
+fn show_last_aired(db: &Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get(program_hash)
+        .ok_or("Program not found")?;
+    let zone = Zone::of(program);
+    let file_name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+    let batch = last_aired_batch(&db.history, program_hash);
+    for aired in &batch {
+        println!("{} episode {}: {} (aired {})", program.name, aired.episode_number, file_name(&aired.path), format_time_in(aired.aired_at, zone));
+    }
+    if !batch.is_empty() {
+        return Ok(());
+    }
+
+    // Without history, the episode before the pointer is the best guess
+    let Some(episode) = program.current_episode.checked_sub(1).and_then(|i| program.episodes.get(i)) else {
+        println!("Nothing of '{}' has aired yet", program.name);
+        return Ok(());
+    };
+    match program.last_update {
+        Some(at) => println!("{} episode {}: {} (aired {})", program.name, episode.episode_number, file_name(&episode.path), format_time_in(at, zone)),
+        None => println!("{} episode {}: {}", program.name, episode.episode_number, file_name(&episode.path)),
+    }
+    Ok(())
+}
+
+fn touch_program(db: &mut Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    program.last_update = Some(Utc::now());
+    program.schedule_slot = None;
+    match next_rollover_time(program, &db.blackouts) {
+        Some(at) => println!("Reset the clock for '{}', next rollover {}", program.name, format_time(at)),
+        None => println!("Reset the clock for '{}'", program.name),
+    }
+    Ok(())
+}
+
+// Parse "pdf,.CBZ" into bare, lowercase extensions
+fn parse_extensions(list: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let extensions: Vec<String> = list.split(',')
+        .map(|ext| ext.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect();
+    if extensions.is_empty() {
+        return Err(format!("No extensions in '{}'", list).into());
+    }
+    Ok(extensions)
+}
+
+// Scan the program's source again, keeping per-episode settings of files
+// that are still there and resuming after the last one that already aired.
+// A scan that finds nothing, or nothing left to air where episodes were
+// waiting, looks like a drive that dropped out or an extension setting that
+// no longer matches rather than a finished program; unless `accept_empty`,
+// the episode list is kept, the program counts a stall instead and this
+// returns false.
+fn rescan_program(db: &mut Database, program_hash: &str, accept_empty: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let part_markers = part_markers(db);
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    if program.curated {
+        return Err(format!("'{}' was assembled with add-file and has no directory to rescan", program.name).into());
+    }
+
+    let episodes = if let Some(url) = &program.remote {
+        scan_remote(&SftpSource::parse(url)?, program.sort, &program.directory, &program.extensions, &db.ignore_patterns)?
+    } else if program.archive {
+        scan_archive(&program.directory, program.sort, &archive_cache_dir(&program.hash)?, &program.extensions, &db.ignore_patterns)?
+    } else {
+        scan_episodes(&program.directory, program.sort, program.folder_episodes, &program.extensions, &db.ignore_patterns, &part_markers)?
+    };
+    if rescan_shrinks(program, &episodes) && !accept_empty {
+        record_stall(program, StallReason::SourceUnavailable);
+        let found = if episodes.is_empty() {
+            "no episodes".to_string()
+        } else {
+            format!("only {} episode(s), none of them left to air", episodes.len())
+        };
+        eprintln!("WARNING: Rescanning '{}' ({}) found {}; keeping its {} episodes and its place ({} aired). \
+            Check the folder and its extensions, or run 'queuecast rescan {} --accept-empty' if the episodes really are gone",
+            program.name, program.directory.display(), found, program.episodes.len(), program.current_episode, program.hash);
+        return Ok(false);
+    }
+    let position = apply_rescan(program, episodes.clone(), &mut db.history);
+    println!("Rescanned '{}': {} episodes, {} already aired", program.name, program.episodes.len(), position);
+
+    let followers = db.programs.values_mut()
+        .filter(|p| p.follow_rescans && p.duplicated_from.as_deref() == Some(program_hash));
+    for follower in followers {
+        let position = apply_rescan(follower, episodes.clone(), &mut db.history);
+        println!("Rescanned rerun '{}': {} episodes, {} already aired", follower.name, follower.episodes.len(), position);
+    }
+    Ok(true)
+}
+
+// Whether a fresh scan would leave an unpinned program with nothing, or
+// with nothing left to air where episodes were still waiting
+fn rescan_shrinks(program: &Program, episodes: &[Episode]) -> bool {
+    !program.pinned && !program.episodes.is_empty()
+        && (episodes.is_empty() || (program.current_episode < program.episodes.len() && resume_position(program, episodes) >= episodes.len()))
+}
+
+// Swap in a fresh scan, keeping per-episode settings of files that are still
+// there. Returns where airing resumes: after the last file that already aired.
+// A pinned program only gains the new files, after its last episode.
+fn apply_rescan(program: &mut Program, mut episodes: Vec<Episode>, history: &mut [HistoryEntry]) -> usize {
+    if program.pinned {
+        let known: HashSet<&PathBuf> = program.episodes.iter().flat_map(|e| e.parts()).collect();
+        episodes.retain(|e| !e.parts().any(|part| known.contains(part)));
+        let first_number = program.episodes.len() + 1;
+        for (i, episode) in episodes.iter_mut().enumerate() {
+            episode.episode_number = first_number + i;
+        }
+        program.episodes.extend(episodes);
+        if program.status == ProgramStatus::Finished && program.current_episode < program.episodes.len() {
+            program.status = ProgramStatus::Running;
+        }
+        return program.current_episode;
+    }
+    for episode in &mut episodes {
+        if let Some(old) = program.episodes.iter().find(|old| old.path == episode.path) {
+            episode.duration_secs = old.duration_secs;
+            episode.probed = old.probed.clone();
+            episode.skipped = old.skipped;
+            episode.title = old.title.clone();
+        }
+    }
+
+    let position = resume_position(program, &episodes);
+    program.episodes = episodes;
+    program.current_episode = position;
+
+    // History refers to episodes by number, which the rescan may have shifted
+    for entry in history.iter_mut().filter(|entry| entry.program_hash == program.hash) {
+        if let Some(episode) = program.episodes.iter().find(|e| e.path == entry.path) {
+            entry.episode_number = episode.episode_number;
+        }
+    }
+    if program.status == ProgramStatus::Finished && position < program.episodes.len() {
+        program.status = ProgramStatus::Running;
+    }
+    position
+}
+
+// Where airing resumes in a fresh scan: after the last file that already aired
+fn resume_position(program: &Program, episodes: &[Episode]) -> usize {
+    let aired: Vec<&PathBuf> = program.episodes[..program.current_episode.min(program.episodes.len())].iter()
+        .flat_map(|e| e.parts())
+        .collect();
+    episodes.iter()
+        .rposition(|e| aired.contains(&&e.path))
+        .map_or(0, |i| i + 1)
+}
+
+// Append files that appeared since the program was last scanned, judged by
+// file name alone. Unless something is new, nothing is stat'ed or re-sorted,
+// so it is cheap enough to run often on slow network folders. Archives are
+// cheap to list anyway and get a full rescan.
+fn rescan_new_only(db: &mut Database, program_hash: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let program = db.programs.get(program_hash)
+        .ok_or("Program not found")?;
+    if program.curated {
+        return Err(format!("'{}' was assembled with add-file and has no directory to rescan", program.name).into());
+    }
+    if program.archive {
+        return rescan_program(db, program_hash, false);
+    }
+
+    let known: HashSet<&std::ffi::OsStr> = program.episodes.iter()
+        .flat_map(|e| e.parts())
+        .filter_map(|part| part.file_name())
+        .collect();
+    let first_number = program.episodes.len() + 1;
+    let mut new_episodes = match &program.remote {
+        Some(url) => {
+            let mut episodes = scan_remote(&SftpSource::parse(url)?, program.sort, &program.directory, &program.extensions, &db.ignore_patterns)?;
+            episodes.retain(|e| !e.path.file_name().is_some_and(|name| known.contains(name)));
+            episodes
+        }
+        None => {
+            let mut entries: Vec<fs::DirEntry> = fs::read_dir(&program.directory)?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| !known.contains(entry.file_name().as_os_str()))
+                .filter(|entry| {
+                    let path = entry.path();
+                    !is_ignored(&path, &db.ignore_patterns)
+                        && ((path.is_file() && has_episode_extension(&path, &program.extensions))
+                            || (program.folder_episodes && is_disc_folder(&path)))
+                })
+                .collect();
+            sort_dir_entries(&mut entries, program.sort);
+            let paths: Vec<PathBuf> = entries.iter().map(fs::DirEntry::path).collect();
+            group_episodes(&paths, first_number, &part_markers(db))
+        }
+    };
+    if new_episodes.is_empty() {
+        println!("No changes to '{}'", program.name);
+        return Ok(true);
+    }
+    for (i, episode) in new_episodes.iter_mut().enumerate() {
+        episode.episode_number = first_number + i;
+    }
+
+    let followers: Vec<String> = db.programs.values()
+        .filter(|p| p.follow_rescans && p.duplicated_from.as_deref() == Some(program_hash))
+        .map(|p| p.hash.clone())
+        .collect();
+    for hash in std::iter::once(program_hash.to_string()).chain(followers) {
+        let Some(program) = db.programs.get_mut(&hash) else {
+            continue;
+        };
+        let first_number = program.episodes.len() + 1;
+        program.episodes.extend(new_episodes.iter().cloned().enumerate().map(|(i, mut episode)| {
+            episode.episode_number = first_number + i;
+            episode
+        }));
+        if program.status == ProgramStatus::Finished {
+            program.status = ProgramStatus::Running;
+        }
+        println!("Added {} new episode(s) to '{}', {} in all", new_episodes.len(), program.name, program.episodes.len());
+    }
+    Ok(true)
+}
+
+struct DuplicateOptions {
+    name: Option<String>,
+    link_dir: Option<PathBuf>,
+    offset_episodes: usize,
+    follow_rescans: bool,
+}
+
+// Copy a program into a rerun that starts over from the beginning, or from
+// offset_episodes in, while the original carries on. Its links must not
+// land on any other program's.
+fn duplicate_program(db: &mut Database, program_hash: &str, options: &DuplicateOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let original = db.programs.get(program_hash)
+        .ok_or("Program not found")?;
+    let name = options.name.clone().unwrap_or_else(|| format!("{} (rerun)", original.name));
+    let hash = new_program_hash(db, &name)?;
+
+    let mut rerun = original.clone();
+    rerun.name = name;
+    rerun.hash = hash.clone();
+    rerun.link_dir = options.link_dir.clone().or(rerun.link_dir);
+    rerun.duplicated_from = Some(original.hash.clone());
+    rerun.follow_rescans = options.follow_rescans;
+    rerun.pinned = false;
+    rerun.custom_status = None;
+    rerun.stall = None;
+    rerun.next_wait_days = None;
+    rerun.milestones_reached = Vec::new();
+    rerun.current_episode = options.offset_episodes.min(rerun.episodes.len());
+    rerun.watched_episode = 0;
+    rerun.status = ProgramStatus::Ready;
+    rerun.start_date = None;
+    rerun.added_at = Some(Utc::now());
+    rerun.last_update = None;
+    rerun.schedule_slot = None;
+    rerun.confirmed_season = None;
+    rerun.last_symlinks = Vec::new();
+    rerun.active_links = Vec::new();
+
+    if let Some(symlink_dir) = &db.symlink_dir {
+        let taken: HashMap<PathBuf, &str> = db.programs.values()
+            .flat_map(|p| p.episodes.iter()
+                .flat_map(|e| episode_links(symlink_dir, &db.link_naming, p, e))
+                .map(|(_, link)| (link, p.name.as_str())))
+            .collect();
+        let clash = rerun.episodes.iter()
+            .flat_map(|e| episode_links(symlink_dir, &db.link_naming, &rerun, e))
+            .find_map(|(_, link)| taken.get(&link).map(|owner| (link, *owner)));
+        if let Some((link, owner)) = clash {
+            return Err(format!(
+                "The rerun's link {} would also be one of '{}', pass a --name that appears in the link template or a --link-dir",
+                link.display(), owner
+            ).into());
+        }
+    }
+
+    println!("Duplicated '{}' as '{}' [{}], starting at episode {} of {}",
+        original.name, rerun.name, hash, rerun.current_episode + 1, rerun.episodes.len());
+    db.programs.insert(hash.clone(), rerun);
+    enqueue(db, &hash, false);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ComparedProgram<'a> {
+    hash: &'a str,
+    name: &'a str,
+    status: &'a ProgramStatus,
+    aired: usize,
+    watched: usize,
+    total: usize,
+}
+
+impl<'a> ComparedProgram<'a> {
+    fn new(program: &'a Program) -> Self {
+        ComparedProgram {
+            hash: &program.hash,
+            name: &program.name,
+            status: &program.status,
+            aired: program.current_episode,
+            watched: program.watched_episode.min(program.episodes.len()),
+            total: program.episodes.len(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ComparedEpisode<'a> {
+    file: &'a str,
+    episode: usize,
+}
+
+#[derive(Serialize)]
+struct MovedEpisode<'a> {
+    file: &'a str,
+    from: usize,
+    to: usize,
+}
+
+// Differences between two programs' episode lists, matched by file name so
+// a copy in another folder still counts as the same episode
+#[derive(Serialize)]
+struct Comparison<'a> {
+    a: ComparedProgram<'a>,
+    b: ComparedProgram<'a>,
+    only_in_a: Vec<ComparedEpisode<'a>>,
+    only_in_b: Vec<ComparedEpisode<'a>>,
+    moved: Vec<MovedEpisode<'a>>,
+    identical: bool,
+}
+
+fn episode_file_name(episode: &Episode) -> &str {
+    episode.path.file_name().and_then(|name| name.to_str()).unwrap_or_default()
+}
+
+#[derive(Serialize)]
+struct SearchEpisode<'a> {
+    episode: usize,
+    file: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct SearchResult<'a> {
+    hash: &'a str,
+    name: &'a str,
+    // Program-level fields that matched: "name", "status"
+    matched: Vec<&'static str>,
+    episodes: Vec<SearchEpisode<'a>>,
+}
+
+const SEARCH_FIELDS: [&str; 4] = ["name", "status", "title", "file"];
+
+// Case-insensitive substring search over program names, custom status
+// labels, episode titles and episode file names, limited to `fields`
+fn search_programs<'a>(db: &'a Database, query: &str, fields: &[&str]) -> Vec<SearchResult<'a>> {
+    let query = query.to_lowercase();
+    let hit = |text: &str| text.to_lowercase().contains(&query);
+    let mut programs: Vec<&Program> = db.programs.values().collect();
+    programs.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()).then(a.hash.cmp(&b.hash)));
+    programs.into_iter()
+        .filter_map(|program| {
+            let mut matched = Vec::new();
+            if fields.contains(&"name") && hit(&program.name) {
+                matched.push("name");
+            }
+            if fields.contains(&"status") && program.custom_status.as_deref().is_some_and(hit) {
+                matched.push("status");
+            }
+            let episodes: Vec<SearchEpisode> = program.episodes.iter()
+                .filter(|e| (fields.contains(&"title") && e.title.as_deref().is_some_and(hit))
+                    || (fields.contains(&"file") && e.parts().any(|part| part.file_name().and_then(|name| name.to_str()).is_some_and(hit))))
+                .map(|e| SearchEpisode { episode: e.episode_number, file: episode_file_name(e), title: e.title.as_deref() })
+                .collect();
+            (!matched.is_empty() || !episodes.is_empty()).then_some(SearchResult { hash: &program.hash, name: &program.name, matched, episodes })
+        })
+        .collect()
+}
+
+// Print what matches the query. Returns whether anything did.
+fn show_search(db: &Database, query: &str, fields: &[&str], json: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let results = search_programs(db, query, fields);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(!results.is_empty());
+    }
+    if results.is_empty() {
+        println!("Nothing matches '{}'", query);
+        return Ok(false);
+    }
+    for result in &results {
+        if result.matched.is_empty() {
+            println!("{} [{}]", result.hash, result.name);
+        } else {
+            println!("{} [{}] - {}", result.hash, result.name, result.matched.join(", "));
+        }
+        for episode in &result.episodes {
+            match episode.title {
+                Some(title) => println!("    Episode {}: \"{}\" ({})", episode.episode, title, episode.file),
+                None => println!("    Episode {}: {}", episode.episode, episode.file),
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn compare_programs<'a>(a: &'a Program, b: &'a Program) -> Comparison<'a> {
+    let files_a: HashSet<&str> = a.episodes.iter().map(episode_file_name).collect();
+    let files_b: HashSet<&str> = b.episodes.iter().map(episode_file_name).collect();
+    let only = |program: &'a Program, other: &HashSet<&str>| -> Vec<ComparedEpisode<'a>> {
+        program.episodes.iter()
+            .filter(|e| !other.contains(episode_file_name(e)))
+            .map(|e| ComparedEpisode { file: episode_file_name(e), episode: e.episode_number })
+            .collect()
+    };
+    let only_in_a = only(a, &files_b);
+    let only_in_b = only(b, &files_a);
+
+    // Shared episodes outside the longest run both lists have in the same
+    // order are the ones that moved
+    let common_a: Vec<&Episode> = a.episodes.iter().filter(|e| files_b.contains(episode_file_name(e))).collect();
+    let common_b: Vec<&Episode> = b.episodes.iter().filter(|e| files_a.contains(episode_file_name(e))).collect();
+    let mut lengths = vec![vec![0usize; common_b.len() + 1]; common_a.len() + 1];
+    for i in (0..common_a.len()).rev() {
+        for j in (0..common_b.len()).rev() {
+            lengths[i][j] = if episode_file_name(common_a[i]) == episode_file_name(common_b[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut in_order = HashSet::new();
+    let (mut i, mut j) = (0, 0);
+    while i < common_a.len() && j < common_b.len() {
+        if episode_file_name(common_a[i]) == episode_file_name(common_b[j]) {
+            in_order.insert(episode_file_name(common_a[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    let moved: Vec<MovedEpisode> = common_a.iter()
+        .filter(|e| !in_order.contains(episode_file_name(e)))
+        .filter_map(|e| {
+            let file = episode_file_name(e);
+            let to = common_b.iter().find(|other| episode_file_name(other) == file)?;
+            Some(MovedEpisode { file, from: e.episode_number, to: to.episode_number })
+        })
+        .collect();
+
+    let identical = only_in_a.is_empty() && only_in_b.is_empty() && moved.is_empty();
+    Comparison { a: ComparedProgram::new(a), b: ComparedProgram::new(b), only_in_a, only_in_b, moved, identical }
+}
+
+// Print how two programs differ. Returns whether their episode lists match.
+fn show_comparison(db: &Database, hash_a: &str, hash_b: &str, json: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let a = db.programs.get(hash_a).ok_or("Program not found")?;
+    let b = db.programs.get(hash_b).ok_or("Program not found")?;
+    let comparison = compare_programs(a, b);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&comparison)?);
+        return Ok(comparison.identical);
+    }
+
+    println!("{} [{}] vs {} [{}]", a.name, a.hash, b.name, b.hash);
+    println!("  Status: {:?} vs {:?}", a.status, b.status);
+    println!("  Progress: {}/{} vs {}/{} episodes", comparison.a.aired, comparison.a.total, comparison.b.aired, comparison.b.total);
+    println!("  Watched: {} vs {} episodes", comparison.a.watched, comparison.b.watched);
+    for episode in &comparison.only_in_a {
+        println!("  - {} (episode {}, only in {})", episode.file, episode.episode, a.name);
+    }
+    for episode in &comparison.only_in_b {
+        println!("  + {} (episode {}, only in {})", episode.file, episode.episode, b.name);
+    }
+    for episode in &comparison.moved {
+        println!("  ~ {} moved from episode {} to {}", episode.file, episode.from, episode.to);
+    }
+    if comparison.identical {
+        println!("  Episode lists match");
+    }
+    Ok(comparison.identical)
+}
+
+// numbers follow the disk order again. The episode on air is tracked by file
+// name across the renumbering.
+fn renumber_program(db: &mut Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get(program_hash)
+        .ok_or("Program not found")?;
+    ensure_unpinned(program)?;
+    let on_air = program.current_episode.checked_sub(1)
+        .and_then(|i| program.episodes.get(i))
+        .map(|e| (e.episode_number, e.path.clone()));
+
+    if !rescan_program(db, program_hash, false)? {
+        return Err("Not renumbered, the rescan was refused".into());
+    }
+
+    let program = &db.programs[program_hash];
+    let Some((old_number, path)) = on_air else {
+        return Ok(());
+    };
+    match program.episodes.iter().find(|e| e.path == path) {
+        Some(episode) if episode.episode_number != old_number => {
+            println!("The episode on air is now episode {} (was {})", episode.episode_number, old_number);
+        }
+        Some(_) => {}
+        None => {
+            eprintln!("Warning: the episode on air, {}, is no longer there", path.display());
+            match program.current_episode.checked_sub(1).and_then(|i| program.episodes.get(i)) {
+                Some(episode) => eprintln!("Resuming after episode {}, the last aired file still present; use 'skip' or 'trim' if that's wrong",
+                    episode.episode_number),
+                None => eprintln!("None of the aired files are left, the program starts again from episode 1"),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn set_extensions(db: &mut Database, program_hash: &str, extensions: Vec<String>, rescan: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    program.extensions = extensions;
+    let shown = if program.extensions.is_empty() { "the default video extensions".to_string() } else { program.extensions.join(", ") };
+    println!("'{}' now scans for {}", program.name, shown);
+
+    if rescan {
+        if !rescan_program(db, program_hash, false)? {
+            return Err("Extensions not changed, the rescan with them was refused".into());
+        }
+    } else {
+        println!("The episode list is unchanged until the program is rescanned");
+    }
+    Ok(())
+}
+
+// Permanently drop episodes numbered above after and below before. Leading
+// trims renumber the rest from 1; history follows the renumbering and forgets
+// the dropped episodes.
+fn trim_episodes(db: &mut Database, program_hash: &str, after: Option<usize>, before: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    let keep = |number: usize| after.is_none_or(|after| number <= after) && before.is_none_or(|before| number >= before);
+    let kept = program.episodes.iter().filter(|e| keep(e.episode_number)).count();
+    if kept == 0 {
+        return Err(format!("That would remove every episode of '{}', use 'queuecast remove' instead", program.name).into());
+    }
+    let removed = program.episodes.len() - kept;
+    if removed == 0 {
+        println!("Nothing to trim from '{}'", program.name);
+        return Ok(());
+    }
+
+    let kept_before_current = program.episodes[..program.current_episode.min(program.episodes.len())].iter()
+        .filter(|e| keep(e.episode_number))
+        .count();
+    let old_numbers: Vec<Option<usize>> = program.episodes.iter()
+        .map(|e| keep(e.episode_number).then_some(e.episode_number))
+        .collect();
+    program.episodes.retain(|e| keep(e.episode_number));
+    program.current_episode = kept_before_current;
+    if before.is_some() {
+        renumber_episodes(program);
+    }
+
+    // Old number to new number for every episode that stayed
+    let renumbered: HashMap<usize, usize> = old_numbers.into_iter().flatten()
+        .zip(program.episodes.iter().map(|e| e.episode_number))
+        .collect();
+    let history_before = db.history.len();
+    db.history.retain_mut(|entry| {
+        if entry.program_hash != program.hash {
+            return true;
+        }
+        match renumbered.get(&entry.episode_number) {
+            Some(&number) => {
+                entry.episode_number = number;
+                true
+            }
+            None => false,
+        }
+    });
+    let history_dropped = history_before - db.history.len();
+
+    if program.status == ProgramStatus::Finished && program.current_episode < program.episodes.len() {
+        program.status = ProgramStatus::Running;
+    }
+    println!("Trimmed {} episodes from '{}', {} remain", removed, program.name, program.episodes.len());
+    if history_dropped > 0 {
+        println!("Forgot {} history entries of trimmed episodes", history_dropped);
+    }
+    Ok(())
+}
+
+// Position of an episode that hasn't aired yet. Aired episodes are referenced
+// by number from history, so they can't be regrouped.
+fn unaired_episode_index(program: &Program, episode_number: usize) -> Result<usize, Box<dyn std::error::Error>> {
+    let index = program.episodes.iter().position(|e| e.episode_number == episode_number)
+        .ok_or_else(|| format!("Episode {} not found", episode_number))?;
+    if index < program.current_episode {
+        return Err(format!("Episode {} has already aired and can't be regrouped", episode_number).into());
+    }
+    Ok(index)
+}
+
+// Where weekdays are reckoned and times shown: a program's own IANA zone,
+// else the database's, else the system's. Times are stored in UTC.
+#[derive(Debug, Clone, Copy)]
+enum Zone {
+    System,
+    Named(chrono_tz::Tz),
+}
+
+impl Zone {
+    fn parse(name: &str) -> Result<Zone, String> {
+        name.parse::<chrono_tz::Tz>()
+            .map(Zone::Named)
+            .map_err(|_| format!("Unknown time zone '{}', expected an IANA name like Europe/Berlin", name))
+    }
+
+    fn database() -> Zone {
+        DEFAULT_ZONE.read().unwrap_or_else(|e| e.into_inner()).unwrap_or(Zone::System)
+    }
+
+    // Zones are checked when set, so a bad one was edited in by hand
+    fn of(program: &Program) -> Zone {
+        match program.timezone.as_deref().map(Zone::parse) {
+            Some(Ok(zone)) => zone,
+            Some(Err(e)) => {
+                eprintln!("Ignoring timezone of '{}': {}", program.name, e);
+                Zone::database()
+            }
+            None => Zone::database(),
+        }
+    }
+
+    fn name(self) -> String {
+        match self {
+            Zone::System => "system local time".to_string(),
+            Zone::Named(tz) => tz.name().to_string(),
+        }
+    }
+
+    fn date(self, time: DateTime<Utc>) -> NaiveDate {
+        match self {
+            Zone::System => time.with_timezone(&Local).date_naive(),
+            Zone::Named(tz) => time.with_timezone(&tz).date_naive(),
+        }
+    }
+
+    fn weekday(self, time: DateTime<Utc>) -> Weekday {
+        self.date(time).weekday()
+    }
+
+    // First instant of the day, which isn't always 00:00 around DST changes
+    fn start_of_day(self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        let midnight = date.and_hms_opt(0, 0, 0)?;
+        match self {
+            Zone::System => midnight.and_local_timezone(Local).earliest().map(|t| t.with_timezone(&Utc)),
+            Zone::Named(tz) => midnight.and_local_timezone(tz).earliest().map(|t| t.with_timezone(&Utc)),
+        }
+    }
+
+    fn format(self, time: DateTime<Utc>, format: &str) -> String {
+        match self {
+            Zone::System => time.with_timezone(&Local).format(format).to_string(),
+            Zone::Named(tz) => time.with_timezone(&tz).format(format).to_string(),
+        }
+    }
+}
+
+// Time in the database's zone and the configured date format. JSON output
+// uses RFC 3339 instead.
+fn format_time(time: DateTime<Utc>) -> String {
+    format_time_in(time, Zone::database())
+}
+
+fn format_time_in(time: DateTime<Utc>, zone: Zone) -> String {
+    let date_format = DATE_FORMAT.read().unwrap_or_else(|e| e.into_inner());
+    zone.format(time, date_format.as_deref().unwrap_or(DEFAULT_DATE_FORMAT))
+}
+
+fn set_timezone(db: &mut Database, zone: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    match zone {
+        Some(name) => {
+            let zone = Zone::parse(name)?;
+            db.timezone = Some(name.to_string());
+            db.mark_set("/timezone");
+            println!("Reckoning weekdays and showing times in {}, now {}", zone.name(), format_time_in(Utc::now(), zone));
+        }
+        None => {
+            db.timezone = None;
+            db.mark_set("/timezone");
+            println!("Reckoning weekdays and showing times in system local time");
+        }
+    }
+    Ok(())
+}
+
+fn set_program_timezone(db: &mut Database, program_hash: &str, zone: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let zone = zone.map(|name| Zone::parse(name).map(|zone| (name, zone))).transpose()?;
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    match zone {
+        Some((name, zone)) => {
+            program.timezone = Some(name.to_string());
+            println!("'{}' now airs by {} time, now {}", program.name, zone.name(), format_time_in(Utc::now(), zone));
+        }
+        None => {
+            program.timezone = None;
+            println!("'{}' now airs by the database's time zone", program.name);
+        }
+    }
+    Ok(())
+}
+
+// chrono panics when displaying a time with an invalid format, so formats
+// are checked before they are stored
+fn check_date_format(format: &str) -> Result<(), String> {
+    if chrono::format::StrftimeItems::new(format).any(|item| matches!(item, chrono::format::Item::Error)) {
+        return Err(format!("'{}' is not a valid strftime format", format));
+    }
+    Ok(())
+}
+
+fn set_ready_markers(db: &mut Database, enabled: bool) -> Result<(), Box<dyn std::error::Error>> {
+    db.ready_markers = enabled;
+    db.mark_set("/ready_markers");
+    if enabled {
+        println!("Writing a .ready marker after each new symlink");
+    } else {
+        println!("No longer writing .ready markers");
+    }
+    Ok(())
+}
+
+fn add_ignore_patterns(db: &mut Database, patterns: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    for pattern in patterns {
+        if db.ignore_patterns.iter().any(|existing| existing.eq_ignore_ascii_case(pattern)) {
+            println!("Already ignoring '{}'", pattern);
+        } else {
+            db.ignore_patterns.push(pattern.clone());
+            println!("Ignoring files matching '{}' in future scans", pattern);
+        }
+    }
+    Ok(())
+}
+
+fn remove_ignore_patterns(db: &mut Database, patterns: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    for pattern in patterns {
+        let before = db.ignore_patterns.len();
+        db.ignore_patterns.retain(|existing| !existing.eq_ignore_ascii_case(pattern));
+        if db.ignore_patterns.len() == before {
+            return Err(format!("'{}' is not an ignore pattern", pattern).into());
+        }
+        println!("No longer ignoring '{}'", pattern);
+    }
+    Ok(())
+}
+
+fn list_ignore_patterns(db: &Database) {
+    if db.ignore_patterns.is_empty() {
+        println!("No ignore patterns");
+    }
+    for pattern in &db.ignore_patterns {
+        println!("{}", pattern);
+    }
+}
+
+fn set_date_format(db: &mut Database, format: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        Some(format) => {
+            check_date_format(format)?;
+            db.date_format = Some(format.to_string());
+            db.mark_set("/date_format");
+            println!("Showing times as {}", Zone::database().format(Utc::now(), format));
+        }
+        None => {
+            db.date_format = None;
+            db.mark_set("/date_format");
+            println!("Showing times as {}", Zone::database().format(Utc::now(), DEFAULT_DATE_FORMAT));
+        }
+    }
+    Ok(())
+}
+
+fn show_info(db: &Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get(program_hash)
+        .ok_or("Program not found")?;
+
+    if let Some(warning) = missed_update_warning(db) {
+        eprintln!("WARNING: {}", warning);
+    }
+    println!("{} [{}]", program.name, program.hash);
+    match &program.remote {
+        Some(url) => println!("  Source: {} (downloaded to {})", url, program.directory.display()),
+        None => println!("  Directory: {}", program.directory.display()),
+    }
+    println!("  Status: {}", status_label(program));
+    println!("  Progress: {}/{} episodes", program.current_episode, program.episodes.len());
+    println!("  Watched: {}/{} episodes", program.watched_episode.min(program.episodes.len()), program.episodes.len());
+    println!("  Interval: {}", describe_interval(program));
+    if let Some(size) = program.batch_size {
+        println!("  Batch: {} episodes per rollover", size);
+    }
+    if let Some(keep) = program.keep_window {
+        println!("  Keeps on air: the last {} rollover(s)", keep);
+    }
+    if program.season_release == SeasonRelease::WholeSeason {
+        match program.season_cooldown_days {
+            Some(days) => println!("  Seasons: released whole, then {} day(s) off", days),
+            None => println!("  Seasons: released whole, then an interval per episode off"),
+        }
+    }
+    if let Some(days) = program.next_wait_days {
+        println!("  Next rollover: {} day(s) after the last", days);
+    }
+    if program.extensions.is_empty() {
+        println!("  Extensions: {} (default)", VIDEO_EXTENSIONS.join(", "));
+    } else {
+        println!("  Extensions: {}", program.extensions.join(", "));
+    }
+    if !program.airing_days.is_empty() {
+        let days: Vec<String> = program.airing_days.iter().map(|d| d.to_string()).collect();
+        println!("  Airing days: {}", days.join(", "));
+    }
+    if program.pinned {
+        println!("  Pinned: episode {} stays on air until 'queuecast unpin'", program.current_episode);
+    }
+    if let Some(stall) = &program.stall {
+        println!("  Failed rollovers: {} in a row since {} (latest: {}){}", stall.attempts, format_time(stall.since), stall.reason,
+            if is_stalled(program) { ", stalled" } else { "" });
+    }
+    if let Some(original) = &program.duplicated_from {
+        match db.programs.get(original) {
+            Some(original) => println!("  Rerun of: {} [{}]{}", original.name, original.hash,
+                if program.follow_rescans { ", follows its rescans" } else { "" }),
+            None => println!("  Rerun of: {} (removed)", original),
+        }
+    }
+    if let Some(link_dir) = &program.link_dir {
+        println!("  Link folder: {}", link_dir.display());
+    }
+    let zone = Zone::of(program);
+    if program.timezone.is_some() {
+        println!("  Time zone: {}", zone.name());
+    }
+    if let Some(minutes) = program.minutes_per_rollover {
+        println!("  Minutes per rollover: {}", minutes);
+    }
+    for (kind, hook) in [("Pre", &program.hooks.pre), ("Post", &program.hooks.post)] {
+        match hook {
+            HookOverride::Inherit => {}
+            HookOverride::Disabled => println!("  {} hook: none (global hook suppressed)", kind),
+            HookOverride::Command(command) => println!("  {} hook: {}", kind, command),
+        }
+    }
+    if let Some(added_at) = program.added_at {
+        println!("  Added: {}", format_time_in(added_at, zone));
+    }
+    if let Some(start_date) = program.start_date {
+        println!("  Started: {}", format_time_in(start_date, zone));
+    }
+    if let Some(last_update) = program.last_update {
+        println!("  Last update: {}", format_time_in(last_update, zone));
+    }
+
+    println!("  Episodes:");
+    print_episode_list(program);
+    Ok(())
+}
+
+// Numbered episode list, marking aired (x), on air next (>) and skipped (-)
+fn print_episode_list(program: &Program) {
+    for (i, episode) in program.episodes.iter().enumerate() {
+        let marker = match i.cmp(&program.current_episode) {
+            _ if episode.skipped.is_some() => "-",
+            Ordering::Less => "x",
+            Ordering::Equal => ">",
+            Ordering::Greater => " ",
+        };
+        let file_name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let mut note = episode.title.as_ref().map(|title| format!(" \"{}\"", title)).unwrap_or_default();
+        if let Some(reason) = episode.skipped {
+            note.push_str(&format!(" (skipped: {})", reason));
+        }
+
+        if episode.extra_parts.is_empty() {
+            println!("  {} {:>3} {}{}", marker, episode.episode_number, file_name(&episode.path), note);
+        } else {
+            println!("  {} {:>3} ({} parts){}", marker, episode.episode_number, episode.extra_parts.len() + 1, note);
+            for part in episode.parts() {
+                println!("          {}", file_name(part));
+            }
+        }
+    }
+}
+
+fn set_global_hooks(db: &mut Database, pre: Option<&str>, post: Option<&str>, milestone: Option<&str>, clear: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if clear {
+        db.hooks = Hooks::default();
+        db.mark_set("/hooks/pre");
+        db.mark_set("/hooks/post");
+        db.mark_set("/hooks/milestone");
+        println!("Cleared global hooks");
+        return Ok(());
+    }
+    if let Some(pre) = pre {
+        db.hooks.pre = Some(pre.to_string());
+        db.mark_set("/hooks/pre");
+        println!("Set global pre hook: {}", pre);
+    }
+    if let Some(post) = post {
+        db.hooks.post = Some(post.to_string());
+        db.mark_set("/hooks/post");
+        println!("Set global post hook: {}", post);
+    }
+    if let Some(milestone) = milestone {
+        db.hooks.milestone = Some(milestone.to_string());
+        db.mark_set("/hooks/milestone");
+        println!("Set milestone hook: {}", milestone);
+    }
+    Ok(())
+}
+
+fn set_program_hooks(db: &mut Database, program_hash: &str, pre: Option<&str>, post: Option<&str>, none: bool, inherit: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    if none {
+        program.hooks = ProgramHooks { pre: HookOverride::Disabled, post: HookOverride::Disabled };
+        println!("Hooks disabled for '{}'", program.name);
+        return Ok(());
+    }
+    if inherit {
+        program.hooks = ProgramHooks::default();
+        println!("'{}' uses the global hooks again", program.name);
+        return Ok(());
+    }
+    if let Some(pre) = pre {
+        program.hooks.pre = HookOverride::Command(pre.to_string());
+        println!("Set pre hook for '{}': {}", program.name, pre);
+    }
+    if let Some(post) = post {
+        program.hooks.post = HookOverride::Command(post.to_string());
+        println!("Set post hook for '{}': {}", program.name, post);
+    }
+    Ok(())
+}
+
+fn continue_program(db: &mut Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    if program.status != ProgramStatus::AwaitingConfirmation {
+        return Err(format!("Program '{}' is not awaiting confirmation", program.name).into());
+    }
+
+    program.status = ProgramStatus::Running;
+    program.confirmed_season = program.episodes.get(program.current_episode).and_then(|e| e.season);
+    println!("Continuing program '{}', the next season airs on the next update", program.name);
+    Ok(())
+}
+
+// `overrides` puts a chosen episode in place of what a program has on air,
+// by program hash and episode number. Those point at the source files, as the
+// episode usually has no link, and programs that aren't running are included.
+fn write_playlist(db: &Database, output: &str, overrides: &HashMap<String, usize>) -> Result<(), Box<dyn std::error::Error>> {
+    let symlink_dir = db.symlink_dir.as_ref()
+        .ok_or("Symlink directory not configured. Use 'queuecast config symlink-dir <path>' to set it.")?;
+    let symlink_dir = std::path::absolute(symlink_dir)?;
+
+    let mut programs: Vec<&Program> = db.programs.values()
+        .filter(|p| p.status == ProgramStatus::Running || overrides.contains_key(&p.hash))
+        .collect();
+    programs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut playlist = String::from("#EXTM3U\n");
+    for program in programs {
+        if let Some(&number) = overrides.get(&program.hash) {
+            let episode = program.episodes.iter().find(|e| e.episode_number == number)
+                .ok_or_else(|| format!("Episode {} not found in '{}'", number, program.name))?;
+            playlist.push_str(&episode_m3u_entries(&program.name, episode, |part| playlist_location(db, part)));
+            continue;
+        }
+        for aired in last_aired_batch(&db.history, &program.hash) {
+            let Some(episode) = program.episodes.iter().find(|e| e.episode_number == aired.episode_number) else {
+                continue;
+            };
+            let links = episode_links(&symlink_dir, &db.link_naming, program, episode);
+            if let Some((_, missing)) = links.iter().find(|(_, link)| !link.exists()) {
+                eprintln!("Skipping {} episode {}: {} is missing", program.name, episode.episode_number, missing.display());
+                continue;
+            }
+
+            let symlinks: HashMap<&Path, PathBuf> = links.into_iter().collect();
+            playlist.push_str(&episode_m3u_entries(&program.name, episode, |part| playlist_location(db, &symlinks[part])));
+        }
+    }
+
+    write_playlist_output(&playlist, output)
+}
+
+// One episode of an interleaved playlist: its M3U entries and plain paths
+struct BlockEntry {
+    m3u: String,
+    paths: Vec<PathBuf>,
+}
+
+// A "TV block" alternating between programs: the first episode of each, then
+// the second of each, and so on. Each program contributes the aired episodes
+// whose links are still in place, or with history the last N it aired,
+// pointing at the source files. Programs come round in name order, or by
+// their weights in the channel when it has any.
+fn write_interleaved_playlist(db: &Database, output: &str, channel: Option<&str>, history: Option<usize>, paths_only: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let symlink_dir = db.symlink_dir.as_ref()
+        .ok_or("Symlink directory not configured. Use 'queuecast config symlink-dir <path>' to set it.")?;
+    let symlink_dir = std::path::absolute(symlink_dir)?;
+
+    let hashes: Vec<String> = match channel {
+        Some(channel) => resolve_programs(db, &format!("@{}", channel.trim_start_matches('@')))?,
+        None => db.programs.keys().cloned().collect(),
+    };
+    let mut programs: Vec<&Program> = hashes.iter()
+        .filter_map(|hash| db.programs.get(hash))
+        .filter(|p| channel.is_some() || matches!(p.status, ProgramStatus::Running | ProgramStatus::Finished))
+        .collect();
+    programs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let weight = |program: &Program| channel.map_or(1, |channel| collection_weight(db, channel.trim_start_matches('@'), &program.hash));
+    let windows: Vec<(u32, Vec<BlockEntry>)> = programs.iter()
+        .map(|program| {
+            let aired = program.episodes[..program.current_episode.min(program.episodes.len())].iter()
+                .filter(|e| e.skipped.is_none());
+            let entries: Vec<BlockEntry> = match history {
+                // A heavier program needs a longer window to keep coming round
+                Some(count) => {
+                    let count = count.saturating_mul(weight(program) as usize);
+                    let aired: Vec<&Episode> = aired.collect();
+                    aired[aired.len().saturating_sub(count)..].iter()
+                        .map(|episode| BlockEntry {
+                            m3u: episode_m3u_entries(&program.name, episode, |part| playlist_location(db, part)),
+                            paths: episode.parts().cloned().collect(),
+                        })
+                        .collect()
+                }
+                None => aired
+                    .filter_map(|episode| {
+                        let links: HashMap<&Path, PathBuf> = episode_links(&symlink_dir, &db.link_naming, program, episode).into_iter().collect();
+                        if !links.values().all(|link| fs::symlink_metadata(link).is_ok()) {
+                            return None;
+                        }
+                        Some(BlockEntry {
+                            m3u: episode_m3u_entries(&program.name, episode, |part| playlist_location(db, &links[part])),
+                            paths: episode.parts().map(|part| links[part.as_path()].clone()).collect(),
+                        })
+                    })
+                    .collect(),
+            };
+            (weight(program), entries)
+        })
+        .filter(|(_, window)| !window.is_empty())
+        .collect();
+
+    let block: Vec<&BlockEntry> = if windows.iter().all(|(weight, _)| *weight == 1) {
+        let rounds = windows.iter().map(|(_, window)| window.len()).max().unwrap_or(0);
+        (0..rounds).flat_map(|i| windows.iter().filter_map(move |(_, window)| window.get(i))).collect()
+    } else {
+        weighted_rotation(&windows)
+    };
+    let block = block.into_iter();
+    let playlist = if paths_only {
+        block.flat_map(|entry| &entry.paths).map(|path| format!("{}\n", path.display())).collect()
+    } else {
+        std::iter::once("#EXTM3U\n".to_string()).chain(block.map(|entry| entry.m3u.clone())).collect::<String>()
+    };
+    write_playlist_output(&playlist, output)
+}
+
+// Smooth weighted round robin: each turn every window with entries left gains
+// its weight in credit, and the one with the most takes the turn and pays
+// back the total. That spreads turns as evenly as the weights allow, so a
+// window only goes twice running when it weighs more than all the others
+// together or they have run out. Ties go to a window other than the last one,
+// then the earlier one, so the order is reproducible.
+fn weighted_rotation<T>(windows: &[(u32, Vec<T>)]) -> Vec<&T> {
+    let mut next = vec![0; windows.len()];
+    let mut credit = vec![0i64; windows.len()];
+    let mut previous = None;
+    let mut rotation = Vec::new();
+    loop {
+        let candidates: Vec<usize> = (0..windows.len()).filter(|&i| next[i] < windows[i].1.len()).collect();
+        if candidates.is_empty() {
+            return rotation;
+        }
+        let total: i64 = candidates.iter().map(|&i| i64::from(windows[i].0)).sum();
+        for &i in &candidates {
+            credit[i] += i64::from(windows[i].0);
+        }
+        let pick = candidates.iter().copied()
+            .max_by_key(|&i| (credit[i], Some(i) != previous, std::cmp::Reverse(i)))
+            .unwrap();
+        credit[pick] -= total;
+        rotation.push(&windows[pick].1[next[pick]]);
+        next[pick] += 1;
+        previous = Some(pick);
+    }
+}
+
+fn write_playlist_output(playlist: &str, output: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if output == "-" {
+        print!("{}", playlist);
+    } else {
+        fs::write(output, playlist)?;
+        println!("Wrote playlist to: {}", output);
+    }
+    Ok(())
+}
+
+// M3U entries for every part of an episode, with `location` mapping each source
+// part to the path or URL the playlist should reference.
+fn episode_m3u_entries(program_name: &str, episode: &Episode, location: impl Fn(&Path) -> String) -> String {
+    // The measured duration covers all parts together
+    let duration = episode.duration_secs.filter(|_| episode.extra_parts.is_empty());
+    episode.parts()
+        .map(|part| m3u_entry(program_name, episode.episode_number, episode.title.as_deref(), duration, &location(part)))
+        .collect()
+}
+
+fn m3u_entry(program_name: &str, episode_number: usize, title: Option<&str>, duration_secs: Option<u32>, location: &str) -> String {
+    let duration = duration_secs.map_or(-1, i64::from);
+    let title = title.map(|title| format!(" - {}", title)).unwrap_or_default();
+    format!("#EXTINF:{},{} - Episode {}{}\n{}\n", duration, program_name, episode_number, title, location)
+}
+
+// Where a playlist should point for a file: under the serve root URL when the
+// file is in the served folder, so the URL always follows the links, and
+// otherwise its path.
+fn playlist_location(db: &Database, path: &Path) -> String {
+    let served = db.serve_path.as_ref().or(db.symlink_dir.as_ref())
+        .and_then(|folder| std::path::absolute(folder).ok());
+    let relative = served.as_deref().and_then(|folder| path.strip_prefix(folder).ok());
+    match (&db.serve_url, relative) {
+        (Some(url), Some(relative)) => {
+            let encoded: Vec<String> = relative.components()
+                .map(|c| url_encode(&c.as_os_str().to_string_lossy()))
+                .collect();
+            format!("{}/{}", url.trim_end_matches('/'), encoded.join("/"))
+        }
+        _ => path.display().to_string(),
+    }
+}
+
+// Percent-encode everything but unreserved URL characters
+fn url_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn set_serve_root(db: &mut Database, url: Option<&str>, path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(url) = url else {
+        db.serve_url = None;
+        db.serve_path = None;
+        db.mark_set("/serve_url");
+        db.mark_set("/serve_path");
+        println!("Playlists point at files again");
+        return Ok(());
+    };
+    if !url.contains("://") {
+        return Err(format!("'{}' is not a URL, expected e.g. http://media.local/tv", url).into());
+    }
+
+    db.serve_url = Some(url.trim_end_matches('/').to_string());
+    db.serve_path = path.map(std::path::absolute).transpose()?;
+    db.mark_set("/serve_url");
+    db.mark_set("/serve_path");
+    match &db.serve_path {
+        Some(folder) => println!("Playlists point at {} as {}", folder.display(), url),
+        None => println!("Playlists point at the symlink directory as {}, wherever it is set", url),
+    }
+    Ok(())
+}
+
+fn append_to_playlist(path: &Path, entry: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // The airing it records is dropped
+    if simulating() {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        file.write_all(b"#EXTM3U\n")?;
+    }
+    file.write_all(entry.as_bytes())?;
+    Ok(())
+}
+
+fn export_history(db: &Database, output: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let root = db.resolved_path_root(&Database::get_database_path()?);
+    let mut events = db.history.clone();
+    if let Some(root) = &root {
+        for entry in &mut events {
+            entry.path = entry.path.strip_prefix(root).unwrap_or(&entry.path).to_path_buf();
+        }
+    }
+    let export = HistoryExport {
+        format: HISTORY_EXPORT_FORMAT.to_string(),
+        version: HISTORY_EXPORT_VERSION,
+        relative_paths: root.is_some(),
+        events,
+    };
+    let json = serde_json::to_string_pretty(&export)?;
+    if output == "-" {
+        println!("{}", json);
+    } else {
+        fs::write(output, json)?;
+        println!("Exported {} history events to: {}", export.events.len(), output);
+    }
+    Ok(())
+}
+
+// Merge another history export into ours, skipping events we already have.
+// Events keep their program name and hash even when no such program exists
+// here.
+fn import_history(db: &mut Database, input: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(input)
+        .map_err(|e| format!("Could not read {}: {}", input.display(), e))?;
+    let export: HistoryExport = serde_json::from_str(&content)
+        .map_err(|e| format!("Could not parse {}: {}", input.display(), e))?;
+    if export.format != HISTORY_EXPORT_FORMAT {
+        return Err(format!("{} is not a queuecast history export", input.display()).into());
+    }
+    if export.version > HISTORY_EXPORT_VERSION {
+        return Err(format!("{} was exported by a newer queuecast (version {}, this build supports {})",
+            input.display(), export.version, HISTORY_EXPORT_VERSION).into());
+    }
+    let root = if export.relative_paths {
+        Some(db.resolved_path_root(&Database::get_database_path()?)
+            .ok_or_else(|| format!("{} has paths relative to a path root, set one with 'queuecast config path-root' first", input.display()))?)
+    } else {
+        None
+    };
+
+    let mut known: HashSet<String> = db.history.iter().map(|entry| entry.id.clone()).collect();
+    let total = export.events.len();
+    let mut imported = 0;
+    let mut unknown_programs = BTreeSet::new();
+    for mut entry in export.events {
+        if entry.id.is_empty() {
+            entry.id = entry.stable_id();
+        }
+        if !known.insert(entry.id.clone()) {
+            continue;
+        }
+        if !db.programs.contains_key(&entry.program_hash) {
+            unknown_programs.insert(format!("{} [{}]", entry.program_name, entry.program_hash));
+        }
+        if let Some(root) = &root {
+            entry.path = root.join(&entry.path);
+        }
+        entry.imported = true;
+        db.history.push(entry);
+        imported += 1;
+    }
+    db.history.sort_by_key(|entry| entry.aired_at);
+
+    println!("Imported {} history events, skipped {} already present", imported, total - imported);
+    if !unknown_programs.is_empty() {
+        println!("Kept events for programs not in this database: {}",
+            unknown_programs.into_iter().collect::<Vec<_>>().join(", "));
+    }
+    Ok(())
+}
+
+// Build aired playlists from history in airing order, keyed by file name
+// ("aired.m3u8" plus one per program). Each episode appears once, at its first
+// airing. Entries for removed programs are kept unless `prune` is set.
+fn aired_playlists(db: &Database, prune: bool) -> Vec<(String, String)> {
+    let mut history: Vec<&HistoryEntry> = db.history.iter().collect();
+    history.sort_by_key(|e| e.aired_at);
+
+    let mut seen = HashSet::new();
+    let mut global = String::from("#EXTM3U\n");
+    let mut per_program: Vec<(String, String)> = Vec::new();
+
+    for entry in history {
+        if !seen.insert((entry.program_hash.as_str(), entry.episode_number)) {
+            continue;
+        }
+        let program = db.programs.get(&entry.program_hash);
+        if prune && program.is_none() {
+            continue;
+        }
+
+        let episode = program
+            .and_then(|p| p.episodes.iter().find(|e| e.episode_number == entry.episode_number && e.path == entry.path));
+        let line = match episode {
+            Some(episode) => episode_m3u_entries(&entry.program_name, episode, |part| part.display().to_string()),
+            None => m3u_entry(&entry.program_name, entry.episode_number, None, None, &entry.path.display().to_string()),
+        };
+        global.push_str(&line);
+
+        let file_name = format!("{}.m3u8", symlink_stem(&entry.program_name, db.link_naming.replacement()));
+        match per_program.iter_mut().find(|(name, _)| *name == file_name) {
+            Some((_, playlist)) => playlist.push_str(&line),
+            None => per_program.push((file_name, format!("#EXTM3U\n{}", line))),
+        }
+    }
+
+    let mut playlists = vec![("aired.m3u8".to_string(), global)];
+    playlists.extend(per_program);
+    playlists
+}
+
+fn write_aired_playlist(db: &Database, output: &str, rebuild: bool, prune: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let playlists = aired_playlists(db, prune);
+
+    if rebuild {
+        let archive_dir = db.archive_dir.as_ref()
+            .ok_or("Archive directory not configured. Use 'queuecast config archive-dir <path>' to set it.")?;
+        fs::create_dir_all(archive_dir)?;
+
+        let count = if db.archive_per_program { playlists.len() } else { 1 };
+        for (file_name, playlist) in &playlists[..count] {
+            fs::write(archive_dir.join(file_name), playlist)?;
+        }
+        println!("Rebuilt {} archive playlist(s) in: {}", count, archive_dir.display());
+        return Ok(());
+    }
+
+    write_playlist_output(&playlists[0].1, output)
+}
+
+fn set_archive_dir(db: &mut Database, path: Option<&str>, per_program: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match path {
+        Some(path) => {
+            let dir_path = std::path::absolute(path)?;
+            fs::create_dir_all(&dir_path)?;
+            db.archive_dir = Some(dir_path.clone());
+            db.archive_per_program = per_program;
+            db.mark_set("/archive_dir");
+            db.mark_set("/archive_per_program");
+            println!("Set archive directory to: {}", dir_path.display());
+            println!("Use 'queuecast playlist --aired --rebuild' to include episodes aired before now");
+        }
+        None => {
+            db.archive_dir = None;
+            db.archive_per_program = false;
+            db.mark_set("/archive_dir");
+            db.mark_set("/archive_per_program");
+            println!("Disabled the aired archive");
+        }
+    }
+    Ok(())
+}
+
+// Links the program's most recent rollover made, paired with the episode
+// file each should point at.
+fn expected_link_targets(program: &Program) -> Vec<(PathBuf, PathBuf)> {
+    let Some(latest) = program.active_links.iter().map(|link| link.created).max() else {
+        return Vec::new();
+    };
+    program.active_links.iter()
+        .filter(|link| link.created == latest)
+        .map(|link| (link.path.clone(), link.target.clone()))
+        .collect()
+}
+
+enum LinkTampering {
+    // Something other than a symlink sits where the link should be
+    NotSymlink,
+    // A symlink, but to somewhere else
+    Repointed(PathBuf),
+}
+
+// Missing links are not tampering, verify_links reports those separately
+fn check_link_target(link: &Path, expected: &Path) -> Option<LinkTampering> {
+    let metadata = fs::symlink_metadata(link).ok()?;
+    if !metadata.file_type().is_symlink() {
+        return Some(LinkTampering::NotSymlink);
+    }
+    let actual = fs::read_link(link).ok()?;
+    (actual != expected).then_some(LinkTampering::Repointed(actual))
+}
+
+// Links of the final rollover that are still ours to remove: symlinks that
+// point where queuecast left them
+fn finished_links(program: &Program) -> Vec<PathBuf> {
+    expected_link_targets(program).into_iter()
+        .filter(|(link, target)| fs::symlink_metadata(link).is_ok() && check_link_target(link, target).is_none())
+        .map(|(link, _)| link)
+        .collect()
+}
+
+fn has_tampered_links(program: &Program) -> bool {
+    expected_link_targets(program).iter()
+        .any(|(link, target)| check_link_target(link, target).is_some())
+}
+
+// Every symlink under `dir`, not following links into other folders
+fn collect_symlinks(dir: &Path, links: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_type = fs::symlink_metadata(&path)?.file_type();
+        if file_type.is_symlink() {
+            links.push(path);
+        } else if file_type.is_dir() {
+            collect_symlinks(&path, links)?;
+        }
+    }
+    Ok(())
+}
+
+// Remove symlinks in the symlink directory that no program claims: not one
+// of a program's links on air, and not pointing at any program's episode.
+// Links into the nextup directory's own folder are left to it.
+fn clean_orphans(db: &Database, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let symlink_dir = db.symlink_dir.as_ref()
+        .ok_or("Symlink directory not configured. Use 'queuecast config symlink-dir <path>' to set it.")?;
+
+    let claimed: HashSet<&Path> = db.programs.values()
+        .flat_map(|program| program.active_links.iter().map(|link| link.path.as_path())
+            .chain(program.last_symlinks.iter().map(|path| path.as_path())))
+        .collect();
+    let episodes: HashSet<&Path> = db.programs.values()
+        .flat_map(|program| &program.episodes)
+        .flat_map(|episode| episode.parts())
+        .map(|part| part.as_path())
+        .collect();
+
+    let mut links = Vec::new();
+    collect_symlinks(symlink_dir, &mut links)?;
+    let mut orphans: Vec<(PathBuf, PathBuf)> = links.into_iter()
+        .filter(|link| !db.nextup_dir.as_ref().is_some_and(|nextup| link.starts_with(nextup)))
+        .filter(|link| !claimed.contains(link.as_path()))
+        .filter_map(|link| {
+            let target = fs::read_link(&link).ok()?;
+            (!episodes.contains(target.as_path())).then_some((link, target))
+        })
+        .collect();
+    orphans.sort();
+
+    if orphans.is_empty() {
+        println!("No orphaned links in {}", symlink_dir.display());
+        return Ok(());
+    }
+    for (link, target) in &orphans {
+        if dry_run {
+            println!("Would remove {} -> {}", link.display(), target.display());
+            continue;
+        }
+        remove_ready_marker(link)?;
+        remove_sidecar(link)?;
+        remove_symlink(link)?;
+        println!("Removed {} -> {}", link.display(), target.display());
+        // Program folders left empty go too
+        if let Some(folder) = link.parent() {
+            remove_empty_link_folders(folder, symlink_dir);
+        }
+    }
+    if dry_run {
+        println!("{} orphaned link(s), run without --dry-run to remove them", orphans.len());
+    } else {
+        println!("Removed {} orphaned link(s)", orphans.len());
+    }
+    Ok(())
+}
+
+// References to programs that are gone, and links tracked outside the
+// symlink directory, grouped by kind. With `fix` dangling references are
+// dropped and history moves to a program of the same name that has the
+// episode file, i.e. the same show added again. Other history keeps its
+// stored name and stays, a rerun's airings are its own.
+// Returns whether anything unfixed was found.
+fn maintenance(db: &mut Database, fix: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let known: HashSet<String> = db.programs.keys().cloned().collect();
+    let mut groups: Vec<(&str, Vec<String>)> = Vec::new();
+
+    let mut members = Vec::new();
+    for (collection, hashes) in &mut db.collections {
+        for hash in hashes.iter().filter(|hash| !known.contains(*hash)) {
+            members.push(format!("@{} lists missing program {}", collection, hash));
+        }
+        if fix {
+            hashes.retain(|hash| known.contains(hash));
+        }
+    }
+    groups.push(("Collection members", members));
+
+    let mut weights = Vec::new();
+    for (collection, weighted) in &mut db.collection_weights {
+        let collection_known = db.collections.contains_key(collection);
+        for hash in weighted.keys().filter(|hash| !collection_known || !known.contains(*hash)) {
+            weights.push(format!("@{} weighs {}{}", collection, hash,
+                if collection_known { ", a missing program" } else { ", but the collection is gone" }));
+        }
+        if fix {
+            weighted.retain(|hash, _| collection_known && known.contains(hash));
+        }
+    }
+    if fix {
+        db.collection_weights.retain(|_, weighted| !weighted.is_empty());
+    }
+    groups.push(("Collection weights", weights));
+
+    let mut reruns = Vec::new();
+    for program in db.programs.values_mut() {
+        let Some(source) = program.duplicated_from.clone().filter(|source| !known.contains(source)) else {
+            continue;
+        };
+        reruns.push(format!("'{}' is a rerun of missing program {}{}", program.name, source,
+            if program.follow_rescans { " and follows its rescans" } else { "" }));
+        if fix {
+            program.duplicated_from = None;
+            program.follow_rescans = false;
+        }
+    }
+    groups.push(("Rerun sources", reruns));
+
+    let mut history = Vec::new();
+    let mut kept = 0;
+    for entry in db.history.iter_mut().filter(|entry| !known.contains(&entry.program_hash)) {
+        let owner = db.programs.values()
+            .filter(|program| program.name == entry.program_name)
+            .find_map(|program| Some((program, program.episodes.iter().find(|e| e.path == entry.path)?)));
+        let Some((program, episode)) = owner else {
+            kept += 1;
+            continue;
+        };
+        history.push(format!("{} '{}' episode {} aired {} is now '{}' episode {}", entry.program_hash, entry.program_name,
+            entry.episode_number, format_time(entry.aired_at), program.name, episode.episode_number));
+        if fix {
+            entry.program_hash = program.hash.clone();
+            entry.episode_number = episode.episode_number;
+        }
+    }
+    groups.push(("History of removed programs", history));
+
+    let mut links = Vec::new();
+    if let Some(symlink_dir) = db.symlink_dir.clone() {
+        for program in db.programs.values_mut() {
+            let outside = |path: &Path| !path.starts_with(&symlink_dir);
+            for link in program.active_links.iter().filter(|link| outside(&link.path)) {
+                links.push(format!("'{}' tracks {}", program.name, link.path.display()));
+            }
+            // The files are left alone, they aren't in a folder queuecast owns
+            if fix {
+                program.active_links.retain(|link| !outside(&link.path));
+                program.last_symlinks.retain(|path| !outside(path));
+            }
+        }
+    }
+    groups.push(("Links outside the symlink directory", links));
+
+    let mut found = 0;
+    for (kind, problems) in groups.iter().filter(|(_, problems)| !problems.is_empty()) {
+        println!("{} ({}):", kind, problems.len());
+        for problem in problems {
+            println!("  {}", problem);
+        }
+        found += problems.len();
+    }
+    if kept > 0 {
+        println!("{} airing(s) of removed programs stay in the history under their stored names", kept);
+    }
+    match (found, fix) {
+        (0, _) => println!("No dangling references"),
+        (_, true) => println!("Fixed {} dangling reference(s)", found),
+        (_, false) => println!("{} dangling reference(s), 'queuecast maintenance --fix' cleans them up", found),
+    }
+    Ok(found > 0 && !fix)
+}
+
+// Print OK/BROKEN/MISSING for every program with something on air, plus
+// a line per link that was replaced or repointed by something else. With
+// fix, repointed symlinks are restored; anything that isn't a symlink is
+// left alone. Returns whether any link was broken or tampered with.
+fn verify_links(db: &Database, fix: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut programs: Vec<&Program> = db.programs.values().collect();
+    programs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut any_broken = false;
+    for program in programs {
+        let expected = expected_link_targets(program);
+        if expected.is_empty() {
+            continue;
+        }
+
+        let mut status = "OK";
+        let mut detail = String::new();
+        for (link, _) in &expected {
+            if fs::symlink_metadata(link).is_err() {
+                status = "MISSING";
+                detail = link.display().to_string();
+            } else if fs::metadata(link).is_err() {
+                status = "BROKEN";
+                detail = link.display().to_string();
+                break;
+            }
+        }
+
+        if status == "BROKEN" {
+            any_broken = true;
+        }
+        if detail.is_empty() {
+            println!("{:<7} {} [{}]", status, program.hash, program.name);
+        } else {
+            println!("{:<7} {} [{}] {}", status, program.hash, program.name, detail);
+        }
+
+        for (link, target) in expected {
+            match check_link_target(&link, &target) {
+                None => {}
+                Some(LinkTampering::NotSymlink) => {
+                    any_broken = true;
+                    println!("  REPLACED {} is not a symlink (expected one to {}), leaving it alone",
+                        link.display(), target.display());
+                }
+                Some(LinkTampering::Repointed(actual)) => {
+                    println!("  MISMATCH {} points to {}, expected {}", link.display(), actual.display(), target.display());
+                    if fix {
+                        remove_symlink(&link)?;
+                        create_symlink(&target, &link)?;
+                        println!("  FIXED    {} -> {}", link.display(), target.display());
+                    } else {
+                        any_broken = true;
+                    }
+                }
+            }
+        }
+    }
+    Ok(any_broken)
+}
+
+// Canonical form of a path that may not exist yet: the deepest existing
+// ancestor is canonicalized and the rest appended, so "." and ".." and
+// symlinked folders can't hide an overlap.
+fn canonicalize_lenient(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut existing = absolute.as_path();
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canonical) = fs::canonicalize(existing) {
+            return rest.iter().rev().fold(canonical, |acc, part| acc.join(part));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return absolute,
+        }
+    }
+}
+
+// How the symlink directory and a program's directory overlap, if they do.
+// Link maintenance in an overlapping tree could touch source files.
+fn symlink_dir_overlap(symlink_dir: &Path, program_dir: &Path) -> Option<&'static str> {
+    let symlink_dir = canonicalize_lenient(symlink_dir);
+    let program_dir = canonicalize_lenient(program_dir);
+    if symlink_dir == program_dir {
+        Some("is the same folder as")
+    } else if symlink_dir.starts_with(&program_dir) {
+        Some("is inside")
+    } else if program_dir.starts_with(&symlink_dir) {
+        Some("contains")
+    } else {
+        None
+    }
+}
+
+fn check_symlink_dir_overlap(symlink_dir: Option<&Path>, program_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(symlink_dir) = symlink_dir else {
+        return Ok(());
+    };
+    match symlink_dir_overlap(symlink_dir, program_dir) {
+        Some("is the same folder as") => Err(format!("{} is the symlink directory, use --force if you really mean it",
+            program_dir.display()).into()),
+        Some(relation) => Err(format!("The symlink directory {} {} {}, use --force if you really mean it",
+            symlink_dir.display(), relation, program_dir.display()).into()),
+        None => Ok(()),
+    }
+}
+
+fn set_symlink_dir(db: &mut Database, path: &str, force: bool, migrate: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // Stored absolute, so only a directory under the path root is ever
+    // stored relative to it
+    let dir_path = std::path::absolute(path)?;
+    if !force {
+        for program in db.programs.values() {
+            if let Some(relation) = symlink_dir_overlap(&dir_path, &program.directory) {
+                return Err(format!("{} {} the directory of '{}' ({}), use --force if you really mean it",
+                    dir_path.display(), relation, program.name, program.directory.display()).into());
+            }
+        }
+    }
+    
+    // Create directory if it doesn't exist
+    create_link_folder(&dir_path)?;
+
+    if migrate {
+        if let Some(old_dir) = db.symlink_dir.clone().filter(|old_dir| *old_dir != dir_path) {
+            migrate_links(db, &old_dir, &dir_path)?;
+        }
+    }
+    
+    db.symlink_dir = Some(dir_path.clone());
+    db.mark_set("/symlink_dir");
+    println!("Set symlink directory to: {}", dir_path.display());
+    Ok(())
+}
+
+// Rename, or copy and delete when the move crosses filesystems
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if simulating() {
+        return Ok(());
+    }
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+// Move every link queuecast keeps under `old_dir`, with its .nfo sidecar
+// and .ready marker, to the same place under `new_dir`. A link missing on
+// disk is recreated. Nothing moves if any destination is taken by a file.
+fn migrate_links(db: &mut Database, old_dir: &Path, new_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let moves: Vec<(PathBuf, PathBuf)> = db.programs.values()
+        .flat_map(|program| &program.active_links)
+        .filter_map(|link| {
+            let relative = link.path.strip_prefix(old_dir).ok()?;
+            Some((link.path.clone(), new_dir.join(relative)))
+        })
+        .collect();
+    for (_, new_path) in &moves {
+        if fs::symlink_metadata(new_path).is_ok_and(|m| !m.file_type().is_symlink()) {
+            return Err(format!("{} is not a symlink, refusing to replace it", new_path.display()).into());
+        }
+    }
+
+    let mut moved = 0;
+    for program in db.programs.values_mut() {
+        for link in &mut program.active_links {
+            let Some((_, new_path)) = moves.iter().find(|(old_path, _)| *old_path == link.path) else {
+                continue;
+            };
+            let target = fs::read_link(&link.path).unwrap_or_else(|_| link.target.clone());
+            if let Some(parent) = new_path.parent() {
+                create_link_folder(parent)?;
+            }
+            replace_symlink(&target, new_path)?;
+            for (old_extra, new_extra) in [
+                (sidecar_path(&link.path), sidecar_path(new_path)),
+                (ready_marker_path(&link.path), ready_marker_path(new_path)),
+            ] {
+                if old_extra.exists() {
+                    move_file(&old_extra, &new_extra)?;
+                }
+            }
+            match remove_symlink(&link.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            // Program folders left empty go too
+            if let Some(folder) = link.path.parent() {
+                remove_empty_link_folders(folder, old_dir);
+            }
+
+            for recorded in &mut program.last_symlinks {
+                if *recorded == link.path {
+                    *recorded = new_path.clone();
+                }
+            }
+            link.path = new_path.clone();
+            moved += 1;
+        }
+    }
+    println!("Moved {} link(s) from {} to {}", moved, old_dir.display(), new_dir.display());
+    Ok(())
+}
+
+// A tracked link and where the current naming and layout put it
+struct Relink {
+    program_hash: String,
+    old_path: PathBuf,
+    new_path: PathBuf,
+    target: PathBuf,
+    // Gone from disk, e.g. renamed by hand, so there is nothing to remove
+    missing: bool,
+}
+
+// Give the tracked links of the programs the names and folders the current
+// link template and layout would give them, without touching schedules.
+// Links missing on disk are recreated. Nothing changes if any new name is
+// taken by a file, by another program's link or by two links at once.
+fn relink(db: &mut Database, program_hashes: &[String], dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let symlink_dir = db.symlink_dir.clone()
+        .ok_or("Symlink directory not configured. Use 'queuecast config symlink-dir <path>' to set it.")?;
+
+    let mut plan = Vec::new();
+    for hash in program_hashes {
+        let program = db.programs.get(hash)
+            .ok_or("Program not found")?;
+        for link in &program.active_links {
+            let new_path = program.episodes.iter()
+                .filter(|e| e.parts().any(|part| *part == link.target))
+                .flat_map(|episode| episode_links(&symlink_dir, &db.link_naming, program, episode))
+                .find(|(target, _)| *target == link.target)
+                .map(|(_, path)| path);
+            let Some(new_path) = new_path else {
+                println!("Keeping {}, {} no longer has its episode", link.path.display(), program.name);
+                continue;
+            };
+            let missing = !fs::symlink_metadata(&link.path).is_ok_and(|m| m.file_type().is_symlink());
+            if new_path != link.path || missing {
+                plan.push(Relink { program_hash: hash.clone(), old_path: link.path.clone(), new_path, target: link.target.clone(), missing });
+            }
+        }
+    }
+
+    let moving: HashSet<&PathBuf> = plan.iter().map(|r| &r.old_path).collect();
+    let mut claimed: HashMap<&PathBuf, &PathBuf> = HashMap::new();
+    let mut clashes = Vec::new();
+    for relink in &plan {
+        if let Some(other) = claimed.insert(&relink.new_path, &relink.old_path) {
+            clashes.push(format!("{} and {} would both become {}", other.display(), relink.old_path.display(), relink.new_path.display()));
+        } else if fs::symlink_metadata(&relink.new_path).is_ok_and(|m| !m.file_type().is_symlink()) {
+            clashes.push(format!("{} is not a symlink, refusing to replace it", relink.new_path.display()));
+        } else if let Some(owner) = db.programs.values()
+            .find(|p| p.active_links.iter().any(|l| l.path == relink.new_path && !moving.contains(&l.path))) {
+            clashes.push(format!("{} is a link of '{}'", relink.new_path.display(), owner.name));
+        }
+    }
+    if !clashes.is_empty() {
+        for clash in &clashes {
+            eprintln!("{}", clash);
+        }
+        return Err(format!("{} link name(s) clash under the current naming, nothing was relinked", clashes.len()).into());
+    }
+
+    for relink in &plan {
+        let action = if relink.missing { "recreate" } else { "rename" };
+        println!("{}{} {} -> {}", if dry_run { "Would " } else { "" }, action, relink.old_path.display(), relink.new_path.display());
+    }
+    if dry_run || plan.is_empty() {
+        println!("{} link(s) to relink", plan.len());
+        return Ok(());
+    }
+
+    // Take every old link down first, so links can swap names
+    let mut sidecars = Vec::new();
+    for relink in &plan {
+        let sidecar = fs::read(sidecar_path(&relink.old_path)).ok();
+        let ready = ready_marker_path(&relink.old_path).exists();
+        remove_sidecar(&relink.old_path)?;
+        remove_ready_marker(&relink.old_path)?;
+        if !relink.missing {
+            remove_symlink(&relink.old_path)?;
+        }
+        sidecars.push((sidecar, ready));
+    }
+
+    for (relink, (sidecar, ready)) in plan.iter().zip(sidecars) {
+        if let Some(parent) = relink.new_path.parent() {
+            create_link_folder(parent)?;
+        }
+        replace_symlink(&relink.target, &relink.new_path)?;
+        let program = db.programs.get_mut(&relink.program_hash)
+            .ok_or("Program not found")?;
+        // A sidecar is written afresh, it may name the link's episode title
+        let sidecar = match (&db.nfo_template, program.episodes.iter().find(|e| e.parts().any(|part| *part == relink.target))) {
+            (Some(template), Some(episode)) => Some(render_nfo(template, program, episode).into_bytes()),
+            _ => sidecar,
+        };
+        if let Some(sidecar) = sidecar {
+            write_sidecar(&relink.new_path, &sidecar)?;
+        }
+        if ready || db.ready_markers {
+            write_ready_marker(&relink.new_path)?;
+        }
+
+        for link in program.active_links.iter_mut().filter(|link| link.path == relink.old_path) {
+            link.path = relink.new_path.clone();
+        }
+        for recorded in program.last_symlinks.iter_mut().filter(|recorded| **recorded == relink.old_path) {
+            *recorded = relink.new_path.clone();
+        }
+    }
+
+    // Program folders left empty go too
+    for relink in &plan {
+        if let Some(folder) = relink.old_path.parent() {
+            remove_empty_link_folders(folder, &symlink_dir);
+        }
+    }
+    println!("Relinked {} link(s)", plan.len());
+    Ok(())
+}
+
+fn set_nextup_dir(db: &mut Database, path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    // Links in the old location would otherwise go stale
+    if let Some(old_dir) = db.nextup_dir.take() {
+        for program in db.programs.values() {
+            remove_nextup_links(&old_dir, &db.link_naming, program, None)?;
+        }
+    }
+    db.mark_set("/nextup_dir");
+
+    match path {
+        Some(path) => {
+            let dir_path = std::path::absolute(path)?;
+            create_link_folder(&dir_path)?;
+            db.nextup_dir = Some(dir_path.clone());
+
+            let program_hashes: Vec<String> = db.programs.keys().cloned().collect();
+            for hash in program_hashes {
+                sync_nextup_link(db, &hash)?;
+            }
+            println!("Set next-up directory to: {}", dir_path.display());
+        }
+        None => println!("Disabled next-up links"),
+    }
+    Ok(())
+}
+
+fn skip_episodes(db: &mut Database, program_hash: &str, count: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    ensure_unpinned(program)?;
+    
+    program.current_episode = (program.current_episode + count).min(program.episodes.len());
+    println!("Skipped {} episodes for program '{}'", count, program.name);
+    Ok(())
+}
+
+// Parse "3-5,7" into episode numbers, rejecting malformed or overlapping ranges
+// and ranges reaching past `highest`, the last episode number there is.
+fn parse_episode_ranges(spec: &str, highest: usize) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    let mut numbers = BTreeSet::new();
+    for part in spec.split(',').map(str::trim) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => (part, part),
+        };
+        let invalid = || format!("Invalid episode range '{}'", part);
+        let start: usize = start.parse().map_err(|_| invalid())?;
+        let end: usize = end.parse().map_err(|_| invalid())?;
+        if start == 0 || start > end {
+            return Err(invalid().into());
+        }
+        if end > highest {
+            return Err(format!("Episode range '{}' goes past the last episode, {}", part, highest).into());
+        }
+
+        for number in start..=end {
+            if !numbers.insert(number) {
+                return Err(format!("Episode {} is listed more than once", number).into());
+            }
+        }
+    }
+    Ok(numbers.into_iter().collect())
+}
+
+// The highest episode number among the programs, for parse_episode_ranges
+fn highest_episode_number(db: &Database, hashes: &[String]) -> usize {
+    hashes.iter()
+        .filter_map(|hash| db.programs.get(hash))
+        .flat_map(|program| program.episodes.iter().map(|e| e.episode_number))
+        .max()
+        .unwrap_or(0)
+}
+
+// Positions of the given upcoming episodes, after checking each one exists,
+// hasn't aired and isn't already skipped.
+fn upcoming_episode_indices(program: &Program, numbers: &[usize]) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    numbers.iter()
+        .map(|&number| {
+            let index = program.episodes.iter().position(|e| e.episode_number == number)
+                .ok_or_else(|| format!("Episode {} not found in '{}'", number, program.name))?;
+            if index < program.current_episode {
+                return Err(format!("Episode {} of '{}' has already aired", number, program.name).into());
+            }
+            if let Some(reason) = program.episodes[index].skipped {
+                return Err(format!("Episode {} of '{}' is already skipped ({})", number, program.name, reason).into());
+            }
+            Ok(index)
+        })
+        .collect()
+}
+
+fn skip_episode_numbers(db: &mut Database, program_hash: &str, numbers: &[usize], reason: SkipReason) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    // Validate everything before changing anything
+    for index in upcoming_episode_indices(program, numbers)? {
+        program.episodes[index].skipped = Some(reason);
+    }
+
+    let listed: Vec<String> = numbers.iter().map(|n| n.to_string()).collect();
+    match reason {
+        SkipReason::Manual => println!("Will skip episode(s) {} of '{}'", listed.join(", "), program.name),
+        SkipReason::Excluded => println!("Excluded episode(s) {} of '{}'", listed.join(", "), program.name),
+    }
+    Ok(())
+}
+
+fn include_episode_numbers(db: &mut Database, program_hash: &str, numbers: &[usize]) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+
+    for &number in numbers {
+        let episode = program.episodes.iter_mut().find(|e| e.episode_number == number)
+            .ok_or_else(|| format!("Episode {} not found in '{}'", number, program.name))?;
+        if episode.skipped.take().is_none() {
+            eprintln!("Episode {} of '{}' was not skipped", number, program.name);
+        }
+    }
+    println!("Episodes will air again unless already passed");
+    Ok(())
+}
+
+// Jump forward so that the given episode is the next to air.
+fn skip_to_episode(db: &mut Database, program_hash: &str, episode_number: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    ensure_unpinned(program)?;
+
+    let index = program.episodes.iter().position(|e| e.episode_number == episode_number)
+        .ok_or_else(|| format!("Episode {} not found in '{}'", episode_number, program.name))?;
+    if index < program.current_episode {
+        return Err(format!("Episode {} of '{}' has already aired", episode_number, program.name).into());
+    }
+
+    let skipped = index - program.current_episode;
+    program.current_episode = index;
+    println!("Skipped {} episodes for program '{}', episode {} airs next", skipped, program.name, episode_number);
+    Ok(())
+}
+
+fn skip_to_next_season(db: &mut Database, program_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    ensure_unpinned(program)?;
+
+    let Some(current) = program.episodes.get(program.current_episode) else {
+        println!("'{}' has no episodes left to skip", program.name);
+        return Ok(());
+    };
+    let season = current.season
+        .ok_or_else(|| format!("'{}' has no season information for episode {}", program.name, current.episode_number))?;
+
+    let index = program.episodes.iter()
+        .skip(program.current_episode)
+        .position(|e| e.season.is_some_and(|s| s > season))
+        .map_or(program.episodes.len(), |offset| program.current_episode + offset);
+    let skipped = index - program.current_episode;
+    program.current_episode = index;
+
+    match program.episodes.get(index) {
+        Some(next) => {
+            // Jumping to a season is an explicit go-ahead for it
+            program.confirmed_season = next.season;
+            if program.status == ProgramStatus::AwaitingConfirmation {
+                program.status = ProgramStatus::Running;
+            }
+            println!("Skipped {} episodes for program '{}', season {} starts next", skipped, program.name, next.season.unwrap_or_default());
+        }
+        None => println!("Skipped {} episodes for program '{}', season {} was the last", skipped, program.name, season),
+    }
+    Ok(())
+}
+
+// Expand a program argument into program hashes. "@name" refers to every member
+// of a collection, anything else is a program hash, a name, or a prefix of
+// exactly one hash.
+fn resolve_programs(db: &Database, program_arg: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    if let Some(name) = program_arg.strip_prefix('@') {
+        let members = db.collections.get(name)
+            .ok_or_else(|| format!("Collection '{}' not found", name))?;
+        return Ok(members.iter().cloned().collect());
+    }
+    if db.programs.contains_key(program_arg) {
+        return Ok(vec![program_arg.to_string()]);
+    }
+
+    let named: Vec<&Program> = db.programs.values()
+        .filter(|p| p.name.eq_ignore_ascii_case(program_arg))
+        .collect();
+    match named.as_slice() {
+        [program] => return Ok(vec![program.hash.clone()]),
+        [] => {}
+        _ => return Err(format!("Several programs are named '{}', use a hash", program_arg).into()),
+    }
+
+    let prefixed: Vec<&Program> = db.programs.values()
+        .filter(|p| !program_arg.is_empty() && p.hash.starts_with(program_arg))
+        .collect();
+    match prefixed.as_slice() {
+        [program] => Ok(vec![program.hash.clone()]),
+        [] => Err(format!("Program '{}' not found", program_arg).into()),
+        _ => {
+            let mut hashes: Vec<&str> = prefixed.iter().map(|p| p.hash.as_str()).collect();
+            hashes.sort_unstable();
+            Err(format!("'{}' is the start of several hashes ({}), type more of it", program_arg, hashes.join(", ")).into())
+        }
+    }
+}
+
+// Programs picked by identifiers and a status selector. Identifiers that
+// match nothing are kept as errors so callers can report them and still act
+// on the rest.
+struct Selection {
+    hashes: Vec<String>,
+    errors: Vec<String>,
+}
+
+fn select_programs(db: &Database, identifiers: &[&str], status: Option<&ProgramStatus>) -> Selection {
+    let mut selection = Selection { hashes: Vec::new(), errors: Vec::new() };
+    let mut add = |hash: String| {
+        if !selection.hashes.contains(&hash) {
+            selection.hashes.push(hash);
+        }
+    };
+
+    for identifier in identifiers {
+        match resolve_programs(db, identifier) {
+            Ok(hashes) => hashes.into_iter().for_each(&mut add),
+            Err(e) => selection.errors.push(e.to_string()),
+        }
+    }
+    if let Some(status) = status {
+        let mut matching: Vec<&Program> = db.programs.values().filter(|p| &p.status == status).collect();
+        matching.sort_by(|a, b| a.name.cmp(&b.name));
+        matching.into_iter().for_each(|p| add(p.hash.clone()));
+    }
+    selection
+}
+
+// Show the programs an action is about to touch and ask to go ahead, unless
+// yes was given on the command line. Names and hash prefixes can match a
+// program other than the one meant, so the listing says exactly which.
+// Without a terminal to ask on (`interactive`, normally whether stdin is
+// one), refuses rather than waiting for an answer.
+fn confirm_programs(db: &Database, action: &str, hashes: &[String], yes: bool, interactive: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    if yes || hashes.is_empty() {
+        return Ok(true);
+    }
+    if !interactive {
+        return Err(format!("Not asking to {} {} program(s) without a terminal, pass --yes to go ahead", action, hashes.len()).into());
+    }
+    println!("About to {} {} program(s):", action, hashes.len());
+    for hash in hashes {
+        if let Some(program) = db.programs.get(hash) {
+            println!("  {} [{}] - {:?}, {}/{} episodes", program.hash, program.name, program.status,
+                program.current_episode, program.episodes.len());
+            println!("    {}", program.remote.as_deref().map_or_else(|| program.directory.display().to_string(), str::to_string));
+        }
+    }
+    print!("Continue? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+// Run action on every selected program, reporting failures per program
+// instead of stopping. Returns whether everything succeeded.
+fn for_each_program(
+    db: &mut Database,
+    selection: Selection,
+    mut action: impl FnMut(&mut Database, &str) -> Result<(), Box<dyn std::error::Error>>,
+) -> bool {
+    let mut ok = selection.errors.is_empty();
+    for error in selection.errors {
+        eprintln!("{}", error);
+    }
+    for hash in selection.hashes {
+        if let Err(e) = action(db, &hash) {
+            eprintln!("{}: {}", hash, e);
+            ok = false;
+        }
+    }
+    ok
+}
+
+// Shared by commands taking program identifiers plus --status and --yes.
+// Destructive actions, and selections made by status, are confirmed first.
+// Returns whether every selected program was handled.
+fn run_on_selection(
+    db: &mut Database,
+    matches: &clap::ArgMatches,
+    verb: &str,
+    destructive: bool,
+    action: impl FnMut(&mut Database, &str) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let identifiers: Vec<&str> = matches.get_many::<String>("program").into_iter().flatten()
+        .map(|s| s.as_str())
+        .collect();
+    run_on_identifiers(db, &identifiers, matches, verb, destructive, action)
+}
+
+// run_on_selection for a command that takes the identifiers apart itself
+fn run_on_identifiers(
+    db: &mut Database,
+    identifiers: &[&str],
+    matches: &clap::ArgMatches,
+    verb: &str,
+    destructive: bool,
+    action: impl FnMut(&mut Database, &str) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let status = matches.get_one::<String>("status").and_then(|s| parse_status(s));
+    let selection = select_programs(db, identifiers, status.as_ref());
+
+    if (destructive || status.is_some()) && !confirm_programs(db, verb, &selection.hashes, matches.get_flag("yes"), std::io::stdin().is_terminal())? {
+        println!("Nothing changed");
+        return Ok(true);
+    }
+    Ok(for_each_program(db, selection, action))
+}
+
+// Split `skip`'s positionals into program identifiers and the count, which
+// is a trailing number that doesn't name a program itself.
+fn skip_arguments<'a>(db: &Database, matches: &'a clap::ArgMatches) -> (Vec<&'a str>, Option<usize>) {
+    let mut identifiers: Vec<&str> = matches.get_many::<String>("program").into_iter().flatten()
+        .map(|s| s.as_str())
+        .collect();
+    let count = match identifiers.last() {
+        Some(last) if identifiers.len() > 1 || matches.contains_id("status") => match last.parse::<usize>() {
+            Ok(count) if resolve_programs(db, last).is_err() => Some(count),
+            _ => None,
+        },
+        _ => None,
+    };
+    if count.is_some() {
+        identifiers.pop();
+    }
+    (identifiers, count)
+}
+
+// Accepts both the list filter name "ran" and "finished"
+fn parse_status(value: &str) -> Option<ProgramStatus> {
+    match value {
+        "running" => Some(ProgramStatus::Running),
+        "ran" | "finished" => Some(ProgramStatus::Finished),
+        "ready" => Some(ProgramStatus::Ready),
+        "stopped" => Some(ProgramStatus::Stopped),
+        "awaiting" => Some(ProgramStatus::AwaitingConfirmation),
+        _ => None,
+    }
+}
+
+fn create_collection(db: &mut Database, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if name.is_empty() || name.starts_with('@') {
+        return Err("Collection name must be non-empty and must not start with '@'".into());
+    }
+    if db.collections.contains_key(name) {
+        return Err(format!("Collection '{}' already exists", name).into());
+    }
+
+    db.collections.insert(name.to_string(), BTreeSet::new());
+    println!("Created collection '{}'", name);
+    Ok(())
+}
+
+fn add_to_collection(db: &mut Database, name: &str, program_hashes: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if !db.collections.contains_key(name) {
+        return Err(format!("Collection '{}' not found", name).into());
+    }
+    for hash in program_hashes {
+        if !db.programs.contains_key(hash) {
+            return Err(format!("Program '{}' not found", hash).into());
+        }
+    }
+
+    let members = db.collections.get_mut(name).unwrap();
+    for hash in program_hashes {
+        if members.insert(hash.clone()) {
+            println!("Added {} to collection '{}'", hash, name);
+        }
+    }
+    Ok(())
+}
+
+fn remove_from_collection(db: &mut Database, name: &str, program_hashes: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let members = db.collections.get_mut(name)
+        .ok_or_else(|| format!("Collection '{}' not found", name))?;
+
+    for hash in program_hashes {
+        if members.remove(hash) {
+            if let Some(weights) = db.collection_weights.get_mut(name) {
+                weights.remove(hash);
+            }
+            println!("Removed {} from collection '{}'", hash, name);
+        } else {
+            eprintln!("Program {} is not in collection '{}'", hash, name);
+        }
+    }
+    Ok(())
+}
+
+fn set_collection_weight(db: &mut Database, name: &str, program_hash: &str, weight: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let members = db.collections.get(name)
+        .ok_or_else(|| format!("Collection '{}' not found", name))?;
+    if !members.contains(program_hash) {
+        return Err(format!("Program {} is not in collection '{}'", program_hash, name).into());
+    }
+
+    let weights = db.collection_weights.entry(name.to_string()).or_default();
+    if weight == 1 {
+        weights.remove(program_hash);
+    } else {
+        weights.insert(program_hash.to_string(), weight);
+    }
+    if weights.is_empty() {
+        db.collection_weights.remove(name);
+    }
+    println!("{} comes round with weight {} in '{}'", program_hash, weight, name);
+    Ok(())
+}
+
+fn collection_weight(db: &Database, name: &str, program_hash: &str) -> u32 {
+    db.collection_weights.get(name)
+        .and_then(|weights| weights.get(program_hash))
+        .copied()
+        .unwrap_or(1)
+}
+
+fn list_collections(db: &Database) -> Result<(), Box<dyn std::error::Error>> {
+    let mut names: Vec<_> = db.collections.keys().collect();
+    names.sort();
+
+    for name in names {
+        let members = &db.collections[name];
+        println!("@{} ({} programs)", name, members.len());
+        for hash in members {
+            let program_name = db.programs.get(hash).map_or("?", |p| p.name.as_str());
+            match collection_weight(db, name, hash) {
+                1 => println!("  {} [{}]", hash, program_name),
+                weight => println!("  {} [{}] weight {}", hash, program_name, weight),
+            }
+        }
+    }
+    Ok(())
+}
+
+// Validate the loaded database beyond what deserialization guarantees.
+// Returns one message per problem found.
+fn check_database(db: &Database) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for (name, dir) in [("symlink-dir", &db.symlink_dir), ("nextup-dir", &db.nextup_dir), ("archive-dir", &db.archive_dir)] {
+        if let Some(dir) = dir {
+            if dir.exists() && !dir.is_dir() {
+                problems.push(format!("{} {} is not a directory", name, dir.display()));
+            }
+        }
+    }
+
+    for (hash, program) in &db.programs {
+        if *hash != program.hash {
+            problems.push(format!("Program '{}' is stored under hash {} but records {}", program.name, hash, program.hash));
+        }
+        if program.current_episode > program.episodes.len() {
+            problems.push(format!("Program '{}' is at episode index {} of {}", program.name, program.current_episode, program.episodes.len()));
+        }
+        if program.interval_days == 0 || program.interval_secs == Some(0) {
+            problems.push(format!("Program '{}' has a zero interval", program.name));
+        }
+        if let Some(relation) = db.symlink_dir.as_deref().and_then(|dir| symlink_dir_overlap(dir, &program.directory)) {
+            problems.push(format!("The symlink directory {} the directory of '{}' ({})", relation, program.name, program.directory.display()));
+        }
+        if let Some(found) = find_renamed_directory(db, program) {
+            problems.push(format!("{}; 'queuecast check --accept-renames' points the program there", rename_evidence(program, &found)));
+        }
+    }
+
+    for (name, members) in &db.collections {
+        for hash in members {
+            if !db.programs.contains_key(hash) {
+                problems.push(format!("Collection '{}' references unknown program {}", name, hash));
+            }
+        }
+    }
+
+    problems
+}
+
+// A folder beside a program's missing directory holding its episode files
+struct RenamedDirectory {
+    directory: PathBuf,
+    // Episodes whose files are all there under the same relative paths
+    found: usize,
+    // Of those, the ones whose recorded size was compared, and matched
+    sizes_matched: usize,
+}
+
+// Share of a program's episodes a folder must hold to count as it renamed
+const RENAME_MIN_SHARE: f64 = 0.9;
+
+// Look for the program's directory under another name beside where it
+// was. Conservative, since adopting the wrong folder would air the wrong
+// files: at least 90% of the episodes (and two, unless there is only one)
+// must be found under their old relative paths, every recorded size must
+// match, the folder must not belong to another program, and exactly one
+// folder may qualify. A missing parent, like an unmounted drive, finds
+// nothing.
+fn find_renamed_directory(db: &Database, program: &Program) -> Option<RenamedDirectory> {
+    if program.curated || program.archive || program.remote.is_some() || program.episodes.is_empty() || program.directory.exists() {
+        return None;
+    }
+    let parent = program.directory.parent()?;
+    let mut candidates = Vec::new();
+    for entry in fs::read_dir(parent).ok()?.filter_map(|entry| entry.ok()) {
+        let directory = entry.path();
+        if !directory.is_dir() || db.programs.values().any(|p| p.directory == directory) {
+            continue;
+        }
+        let mut found = 0;
+        let mut sizes_matched = 0;
+        let mut size_mismatch = false;
+        for episode in &program.episodes {
+            let moved: Option<Vec<PathBuf>> = episode.parts()
+                .map(|part| part.strip_prefix(&program.directory).ok().map(|relative| directory.join(relative)))
+                .collect();
+            let Some(moved) = moved.filter(|parts| parts.iter().all(|part| part.exists())) else {
+                continue;
+            };
+            found += 1;
+            if let (Some(probed), Some(current)) = (&episode.probed, FileStamp::of(&moved)) {
+                if probed.size == current.size {
+                    sizes_matched += 1;
+                } else {
+                    size_mismatch = true;
+                }
+            }
+        }
+        let total = program.episodes.len();
+        let enough = found as f64 >= total as f64 * RENAME_MIN_SHARE && found >= total.min(2);
+        if enough && !size_mismatch {
+            candidates.push(RenamedDirectory { directory, found, sizes_matched });
+        }
+    }
+    match candidates.len() {
+        1 => candidates.pop(),
+        _ => None,
+    }
+}
+
+fn rename_evidence(program: &Program, found: &RenamedDirectory) -> String {
+    let sizes = match found.sizes_matched {
+        0 => "no sizes recorded to compare".to_string(),
+        matched => format!("{} with their recorded size", matched),
+    };
+    format!("The directory of '{}' ({}) is missing and looks renamed to {}: {} of its {} episodes are there under the same names, {}",
+        program.name, program.directory.display(), found.directory.display(),
+        found.found, program.episodes.len(), sizes)
+}
+
+// Point a program at its renamed directory, keeping its progress, and
+// repoint the links it has on air
+fn accept_rename(db: &mut Database, program_hash: &str, new_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let program = db.programs.get_mut(program_hash)
+        .ok_or("Program not found")?;
+    let old_dir = program.directory.clone();
+    let moved = |path: &Path| match path.strip_prefix(&old_dir) {
+        Ok(relative) => new_dir.join(relative),
+        Err(_) => path.to_path_buf(),
+    };
+
+    program.directory = new_dir.to_path_buf();
+    for episode in &mut program.episodes {
+        episode.path = moved(&episode.path);
+        for part in &mut episode.extra_parts {
+            *part = moved(part);
+        }
+    }
+    for link in &mut program.active_links {
+        link.target = moved(&link.target);
+        if fs::symlink_metadata(&link.path).is_ok_and(|m| m.file_type().is_symlink()) {
+            replace_symlink(&link.target, &link.path)?;
+        }
+    }
+    for entry in db.history.iter_mut().filter(|entry| entry.program_hash == program_hash) {
+        entry.path = moved(&entry.path);
+    }
+    Ok(())
+}
+
+fn run_check(verbose: bool, accept_renames: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut db = match Database::load() {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if accept_renames {
+        let renames: Vec<(String, RenamedDirectory)> = db.programs.values()
+            .filter_map(|program| Some((program.hash.clone(), find_renamed_directory(&db, program)?)))
+            .collect();
+        for (hash, found) in &renames {
+            println!("{}", rename_evidence(&db.programs[hash], found));
+            accept_rename(&mut db, hash, &found.directory)?;
+            println!("  Now using {}", found.directory.display());
+        }
+        if !renames.is_empty() {
+            db.save()?;
+        }
+    }
+
+    let problems = check_database(&db);
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    if !problems.is_empty() {
+        process::exit(1);
+    }
+
+    if verbose {
+        println!("Database OK: {} (schema version {}, {} programs)",
+            Database::get_database_path()?.display(), SCHEMA_VERSION, db.programs.len());
+    }
+    Ok(())
+}
+
+// Exit code bits of healthcheck, so a failing probe says what failed
+const HEALTH_DATABASE: i32 = 1;
+const HEALTH_SYMLINK_DIR: i32 = 2;
+const HEALTH_DAEMON_UNRESPONSIVE: i32 = 4;
+const HEALTH_DAEMON_STALE: i32 = 8;
+
+// How long past its planned pass a daemon may be before it counts as stuck
+const HEALTH_OVERDUE_MINUTES: i64 = 15;
+
+fn check_writable_dir(dir: &Path) -> Result<(), String> {
+    if !dir.is_dir() {
+        return Err(format!("Symlink directory {} does not exist", dir.display()));
+    }
+    dir_writable(dir).map_err(|e| format!("Symlink directory {} is not writable: {}", dir.display(), e))
+}
+
+// Asks rather than writing a probe file, which a media server watching the
+// directory could pick up
+#[cfg(unix)]
+fn dir_writable(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: c_path is NUL-terminated
+    if unsafe { libc::access(c_path.as_ptr(), libc::W_OK | libc::X_OK) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn dir_writable(dir: &Path) -> std::io::Result<()> {
+    if fs::metadata(dir)?.permissions().readonly() {
+        return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "read-only"));
+    }
+    Ok(())
+}
+
+// Problems with a running daemon, or none if no daemon is running
+fn check_daemon() -> Vec<(i32, String)> {
+    let socket_exists = daemon_socket_path().is_ok_and(|path| path.exists());
+    let status = match daemon_request(&DaemonRequest::Status, Some(Duration::from_secs(5))) {
+        Ok(Some(status)) => status,
+        Ok(None) if !socket_exists => return Vec::new(),
+        Ok(None) => return vec![(HEALTH_DAEMON_UNRESPONSIVE, "Daemon socket exists but nothing is listening".to_string())],
+        Err(e) => return vec![(HEALTH_DAEMON_UNRESPONSIVE, format!("Daemon did not answer: {}", e))],
+    };
+
+    let mut problems = Vec::new();
+    let next_pass = serde_json::from_value::<Option<DateTime<Utc>>>(status["next_pass"].clone()).ok().flatten();
+    if let Some(next_pass) = next_pass {
+        if Utc::now().signed_duration_since(next_pass) > chrono::Duration::minutes(HEALTH_OVERDUE_MINUTES) {
+            problems.push((HEALTH_DAEMON_STALE, format!("Daemon's pass planned for {} has not happened", format_time(next_pass))));
+        }
+    }
+    if let Some(warning) = status["missed_update"].as_str() {
+        problems.push((HEALTH_DAEMON_STALE, format!("Daemon: {}", warning)));
+    }
+    problems
+}
+
+// Liveness probe: the database loads and is consistent, the symlink directory
+// is writable and a running daemon answers and keeps up. Returns the exit
+// code, one bit per kind of failure.
+fn healthcheck(quiet: bool) -> i32 {
+    let mut problems = Vec::new();
+    match Database::load() {
+        Ok(db) => {
+            problems.extend(check_database(&db).into_iter().map(|problem| (HEALTH_DATABASE, problem)));
+            if let Some(symlink_dir) = &db.symlink_dir {
+                if let Err(problem) = check_writable_dir(symlink_dir) {
+                    problems.push((HEALTH_SYMLINK_DIR, problem));
+                }
+            }
+        }
+        Err(e) => problems.push((HEALTH_DATABASE, e.to_string())),
+    }
+    problems.extend(check_daemon());
+
+    if !quiet {
+        for (_, problem) in &problems {
+            eprintln!("{}", problem);
+        }
+    }
+    problems.iter().fold(0, |code, (bit, _)| code | bit)
+}
+
+const LAUNCHD_LABEL: &str = "com.queuecast.update";
+const SCHTASKS_NAME: &str = "queuecast-update";
+
+// OS schedulers that can run 'queuecast update' once a day
+#[derive(Debug, Clone, Copy)]
+enum ScheduleBackend {
+    Launchd,
+    Schtasks,
+}
+
+impl ScheduleBackend {
+    fn parse(value: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        match value {
+            Some("launchd") => Ok(ScheduleBackend::Launchd),
+            Some("schtasks") => Ok(ScheduleBackend::Schtasks),
+            Some(other) => Err(format!("Unknown scheduler '{}'", other).into()),
+            None if cfg!(target_os = "macos") => Ok(ScheduleBackend::Launchd),
+            None if cfg!(windows) => Ok(ScheduleBackend::Schtasks),
+            None => Err("No scheduler installer for this platform, run 'queuecast update' from cron or use 'queuecast watch'".into()),
+        }
+    }
+}
+
+fn launchd_plist_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let home_dir = env::var("HOME").map_err(|_| "Could not find home directory")?;
+    Ok(Path::new(&home_dir).join("Library").join("LaunchAgents").join(format!("{}.plist", LAUNCHD_LABEL)))
+}
+
+fn launchd_plist(exe: &Path, time: NaiveTime) -> Result<String, Box<dyn std::error::Error>> {
+    let home_dir = env::var("HOME").map_err(|_| "Could not find home directory")?;
+    let log = Path::new(&home_dir).join("Library").join("Logs").join("queuecast.log");
+    Ok(format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>update</string>
+    </array>
+    <key>StartCalendarInterval</key>
+    <dict>
+        <key>Hour</key>
+        <integer>{}</integer>
+        <key>Minute</key>
+        <integer>{}</integer>
+    </dict>
+    <key>StandardOutPath</key>
+    <string>{}</string>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#, LAUNCHD_LABEL, xml_escape(&exe.to_string_lossy()), time.hour(), time.minute(),
+        xml_escape(&log.to_string_lossy()), xml_escape(&log.to_string_lossy())))
+}
+
+fn schtasks_create_args(exe: &Path, time: NaiveTime) -> Vec<String> {
+    vec![
+        "/Create".to_string(),
+        "/TN".to_string(), SCHTASKS_NAME.to_string(),
+        "/TR".to_string(), format!("\"{}\" update", exe.display()),
+        "/SC".to_string(), "DAILY".to_string(),
+        "/ST".to_string(), time.format("%H:%M").to_string(),
+        // Replaces an existing task of the same name instead of failing
+        "/F".to_string(),
+    ]
+}
+
+fn run_scheduler_command(program: &str, args: &[&str]) -> Result<bool, Box<dyn std::error::Error>> {
+    let status = process::Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| format!("Could not run {}: {}", program, e))?;
+    Ok(status.success())
+}
+
+fn install_schedule(backend: ScheduleBackend, time: NaiveTime, print: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let exe = env::current_exe()?;
+    match backend {
+        ScheduleBackend::Launchd => {
+            let plist = launchd_plist(&exe, time)?;
+            if print {
+                print!("{}", plist);
+                return Ok(());
+            }
+
+            let plist_path = launchd_plist_path()?;
+            let plist_arg = plist_path.to_string_lossy().to_string();
+            if plist_path.exists() {
+                println!("Updating existing launchd agent {}", plist_path.display());
+                // Fails harmlessly when the agent isn't loaded
+                run_scheduler_command("launchctl", &["unload", &plist_arg])?;
+            }
+            if let Some(parent) = plist_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&plist_path, plist)?;
+            if !run_scheduler_command("launchctl", &["load", "-w", &plist_arg])? {
+                return Err(format!("launchctl could not load {}", plist_path.display()).into());
+            }
+            println!("Installed launchd agent running 'queuecast update' daily at {}", time.format("%H:%M"));
+        }
+        ScheduleBackend::Schtasks => {
+            let args = schtasks_create_args(&exe, time);
+            if print {
+                let quoted: Vec<String> = args.iter()
+                    .map(|arg| if arg.contains(' ') { format!("\"{}\"", arg.replace('"', "\\\"")) } else { arg.clone() })
+                    .collect();
+                println!("schtasks {}", quoted.join(" "));
+                return Ok(());
+            }
+
+            if run_scheduler_command("schtasks", &["/Query", "/TN", SCHTASKS_NAME])? {
+                println!("Updating existing scheduled task {}", SCHTASKS_NAME);
+            }
+            let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+            if !run_scheduler_command("schtasks", &args)? {
+                return Err("schtasks could not create the task".into());
+            }
+            println!("Installed scheduled task running 'queuecast update' daily at {}", time.format("%H:%M"));
+        }
+    }
+    Ok(())
+}
+
+fn uninstall_schedule(backend: ScheduleBackend) -> Result<(), Box<dyn std::error::Error>> {
+    match backend {
+        ScheduleBackend::Launchd => {
+            let plist_path = launchd_plist_path()?;
+            if !plist_path.exists() {
+                return Err("No launchd agent installed".into());
+            }
+            run_scheduler_command("launchctl", &["unload", "-w", &plist_path.to_string_lossy()])?;
+            fs::remove_file(&plist_path)?;
+            println!("Removed launchd agent {}", plist_path.display());
+        }
+        ScheduleBackend::Schtasks => {
+            if !run_scheduler_command("schtasks", &["/Delete", "/TN", SCHTASKS_NAME, "/F"])? {
+                return Err(format!("Could not delete scheduled task {}", SCHTASKS_NAME).into());
+            }
+            println!("Removed scheduled task {}", SCHTASKS_NAME);
+        }
+    }
+    Ok(())
+}
+
+fn print_version(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut features: Vec<&str> = Vec::new();
+    if cfg!(feature = "archives") {
+        features.push("archives");
+    }
+    if cfg!(feature = "sftp") {
+        features.push("sftp");
+    }
+    let config_path = Database::get_database_path()?;
+
+    if json {
+        let info = serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "schema_version": SCHEMA_VERSION,
+            "features": features,
+            "target": format!("{}-{}", env::consts::ARCH, env::consts::OS),
+            "config_path": config_path,
+        });
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        println!("queuecast {}", env!("CARGO_PKG_VERSION"));
+        println!("Schema version: {}", SCHEMA_VERSION);
+        println!("Features: {}", if features.is_empty() { "none".to_string() } else { features.join(", ") });
+        println!("Target: {}-{}", env::consts::ARCH, env::consts::OS);
+        println!("Config path: {}", config_path.display());
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = Command::new("queuecast")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Manage TV show files with weekly scheduling")
+        .arg(Arg::new("portable")
+            .long("portable")
+            .help("Keep all state in data/ next to the executable (also enabled by a queuecast.portable file there)")
+            .global(true)
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("force-refresh")
+            .long("force-refresh")
+            .help("Measure episode files again even when their size and modification time are unchanged")
+            .global(true)
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("no-save")
+            .long("no-save")
+            .help("Run against an in-memory copy of the database and drop its changes; links aren't touched and hooks don't run")
+            .global(true)
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("run-hooks-on-dry-run")
+            .long("run-hooks-on-dry-run")
+            .help("With --no-save or update --dry-run, run hooks anyway, with QC_DRY_RUN=1 set")
+            .global(true)
+            .action(clap::ArgAction::SetTrue))
+        .subcommand(
+            Command::new("add")
+                .about("Add directory to database")
+                .arg(Arg::new("directory").required_unless_present("from-file"))
+                .arg(Arg::new("force")
+                    .long("force")
+                    .help("Add even if the directory overlaps the symlink directory")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("extensions")
+                    .long("extensions")
+                    .help("Comma-separated file extensions to treat as episodes instead of video files, e.g. pdf or cbz,cbr"))
+                .arg(Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("List the episodes that would be added, in airing order, without adding anything")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("strict")
+                    .long("strict")
+                    .help("Refuse to add when SxxEyy numbering has gaps or duplicates")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("queue-top")
+                    .long("queue-top")
+                    .help("Start before the Ready programs already waiting instead of after them")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("from-file")
+                    .long("from-file")
+                    .help("Add every directory listed in this file (one per line, - for stdin)")
+                    .conflicts_with("directory"))
+                .arg(Arg::new("name")
+                    .long("name")
+                    .help("Name the program instead of taking the folder name; the name is used for links and the hash")
+                    .conflicts_with("from-file"))
+                .arg(Arg::new("interval")
+                    .long("interval")
+                    .help("Time between rollovers, e.g. 2d or 6h; a bare number is days [default: config default-interval, or 7]")
+                    .value_parser(Interval::parse))
+                .arg(Arg::new("minutes-per-rollover")
+                    .long("minutes-per-rollover")
+                    .help("Air consecutive episodes until roughly this many minutes per rollover")
+                    .value_parser(clap::value_parser!(u32).range(1..)))
+                .arg(Arg::new("batch-size")
+                    .long("batch-size")
+                    .help("Link this many episodes per rollover, like set-batch-size")
+                    .value_parser(clap::value_parser!(u32).range(1..)))
+                .arg(Arg::new("keep-window")
+                    .long("keep-window")
+                    .help("Only keep the links of the last this many rollovers on air")
+                    .value_parser(clap::value_parser!(u32).range(1..)))
+                .arg(Arg::new("no-defaults")
+                    .long("no-defaults")
+                    .help("Ignore the defaults set with 'config default' for this add")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("pause-between-seasons")
+                    .long("pause-between-seasons")
+                    .help("Wait for 'queuecast continue' before airing each new season")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("airing-days")
+                    .long("airing-days")
+                    .help("Only roll over on these weekdays, e.g. sat,sun"))
+                .arg(Arg::new("sort")
+                    .long("sort")
+                    .help("How to order episode files")
+                    .value_parser(["name", "natural", "mtime", "ctime"])
+                    .default_value("natural"))
+                .arg(Arg::new("adopt")
+                    .long("adopt")
+                    .help("Take episode order and progress from hand-made symlinks in this directory")
+                    .conflicts_with("from-file"))
+                .arg(Arg::new("from-archive")
+                    .long("from-archive")
+                    .help("Treat <directory> as a zip file of episodes, extracted as they air")
+                    .conflicts_with_all(["from-file", "adopt", "folder-episodes"])
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("folder-episodes")
+                    .long("folder-episodes")
+                    .help("Also treat subdirectories holding VIDEO_TS or BDMV as episodes")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("add-file")
+                .about("Add a single file as a new program, or as the next episode of an existing one")
+                .arg(Arg::new("path").required(true))
+                .arg(Arg::new("force")
+                    .long("force")
+                    .help("Add even if the directory overlaps the symlink directory")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("program")
+                    .long("program")
+                    .help("Append the file to this program instead of creating a new one"))
+                .arg(Arg::new("interval")
+                    .long("interval")
+                    .help("Time between rollovers for a new program, e.g. 2d or 6h [default: config default-interval, or 7]")
+                    .value_parser(Interval::parse)
+                    .conflicts_with("program"))
+        )
+        .subcommand(
+            Command::new("list")
+                .about("List programs")
+                .arg(Arg::new("filter").value_parser(["running", "ran", "ready", "stopped", "awaiting"]))
+                .arg(Arg::new("stalled")
+                    .long("stalled")
+                    .help("Only programs whose last few due rollovers all failed, and since when")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("added-since")
+                    .long("added-since")
+                    .value_name("WHEN")
+                    .help("Only programs added since a date (YYYY-MM-DD) or a span ago like 30d")
+                    .value_parser(parse_since))
+                .arg(Arg::new("updated-since")
+                    .long("updated-since")
+                    .value_name("WHEN")
+                    .help("Only programs whose latest rollover was since a date or a span ago")
+                    .value_parser(parse_since))
+                .arg(Arg::new("updated-before")
+                    .long("updated-before")
+                    .value_name("WHEN")
+                    .help("Only programs whose latest rollover was before a date or a span ago")
+                    .value_parser(parse_since))
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Show a program's settings and episode list")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("check")
+                    .long("check")
+                    .help("Only report gaps and duplicates in the SxxEyy numbering, exiting non-zero if there are any")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("update")
+                .about("Update symlinks for programs (all programs by default, or specific programs or @collections)")
+                .arg(Arg::new("program").num_args(0..))
+                .arg(Arg::new("programs")
+                    .long("program")
+                    .help("Force a rollover of this program or @collection too (repeatable)")
+                    .action(clap::ArgAction::Append))
+                .arg(Arg::new("verify")
+                    .long("verify")
+                    .help("Afterwards check every program's links like verify-links, exiting 1 on problems")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("really-force")
+                    .long("really-force")
+                    .help("Force even a program that rolled over within the force cooldown")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("unpin-first")
+                    .long("unpin-first")
+                    .help("Unpin the programs before forcing them")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("catch-up-limit")
+                    .long("catch-up-limit")
+                    .value_name("N")
+                    .help("Also air rollovers missed while no update ran, at most N per program; the rest air on later runs")
+                    .conflicts_with_all(["program", "programs"])
+                    .value_parser(clap::value_parser!(u32).range(1..)))
+                .arg(Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("Simulate the update like --no-save: print what would air, change no links and run no hooks")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("remove")
+                .about("Remove programs from database")
+                .arg(Arg::new("program")
+                    .num_args(1..)
+                    .required_unless_present("status")
+                    .help("Program hashes, names or @collections"))
+                .arg(Arg::new("status")
+                    .long("status")
+                    .help("Also select every program with this status, after confirmation")
+                    .value_parser(["running", "finished", "ready", "stopped", "awaiting"]))
+                .arg(Arg::new("yes")
+                    .long("yes")
+                    .short('y')
+                    .help("Don't ask for confirmation, which is required when not run from a terminal")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("stop")
+                .about("Stop programs from broadcasting")
+                .arg(Arg::new("program")
+                    .num_args(1..)
+                    .required_unless_present("status")
+                    .help("Program hashes, names or @collections"))
+                .arg(Arg::new("status")
+                    .long("status")
+                    .help("Also select every program with this status, after confirmation")
+                    .value_parser(["running", "finished", "ready", "stopped", "awaiting"]))
+                .arg(Arg::new("yes")
+                    .long("yes")
+                    .short('y')
+                    .help("Don't ask for confirmation")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("set-status")
+                .about("Label a program with a status of your own, like 'On Hiatus'; updates leave it alone while labelled")
+                .arg(Arg::new("program").required_unless_present("from"))
+                .arg(Arg::new("label").required_unless_present_any(["clear", "from"]))
+                .arg(Arg::new("clear")
+                    .long("clear")
+                    .help("Drop the label so the program is scheduled again")
+                    .conflicts_with("label")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("from")
+                    .long("from")
+                    .help("Instead change every program with this status...")
+                    .value_parser(["running", "finished", "ready", "stopped", "awaiting"])
+                    .requires("to")
+                    .conflicts_with_all(["program", "label", "clear"]))
+                .arg(Arg::new("to")
+                    .long("to")
+                    .help("...to this one: stop ready or running programs, or resume stopped ones")
+                    .value_parser(["running", "finished", "ready", "stopped", "awaiting"])
+                    .requires("from"))
+                .arg(Arg::new("yes")
+                    .long("yes")
+                    .short('y')
+                    .help("Don't ask for confirmation, which is required when not run from a terminal")
+                    .requires("from")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("set-title")
+                .about("Give an episode a title of your own, used for {title} in link names and .nfo files, info and playlists")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("episode")
+                    .required_unless_present("from-file")
+                    .value_parser(clap::value_parser!(usize)))
+                .arg(Arg::new("title").required_unless_present_any(["clear", "from-file"]))
+                .arg(Arg::new("clear")
+                    .long("clear")
+                    .help("Go back to the title from the file name")
+                    .conflicts_with("title")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("from-file")
+                    .long("from-file")
+                    .help("Set many titles from a file of <number><TAB><title> lines (- for stdin)")
+                    .conflicts_with_all(["episode", "title", "clear"]))
+        )
+        .subcommand(
+            Command::new("set-hook")
+                .about("Override the global rollover hooks for a program")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("pre")
+                    .long("pre")
+                    .help("Command run before each rollover, a non-zero exit skips it"))
+                .arg(Arg::new("post")
+                    .long("post")
+                    .help("Command run after each rollover"))
+                .arg(Arg::new("none")
+                    .long("none")
+                    .help("Run no hooks for this program, not even the global ones")
+                    .conflicts_with_all(["pre", "post", "inherit"])
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("inherit")
+                    .long("inherit")
+                    .help("Drop the overrides and use the global hooks")
+                    .conflicts_with_all(["pre", "post"])
+                    .action(clap::ArgAction::SetTrue))
+                .group(clap::ArgGroup::new("hook").args(["pre", "post", "none", "inherit"]).required(true).multiple(true))
+        )
+        .subcommand(
+            Command::new("mark-watched")
+                .about("Record that you've watched a program up to this episode, whatever has aired")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("episode")
+                    .required(true)
+                    .help("Last episode watched, 0 to reset")
+                    .value_parser(clap::value_parser!(usize)))
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show what the next update would change, exiting 1 if anything would")
+                .arg(Arg::new("program").help("Show what 'update <program>' would do instead"))
+                .arg(Arg::new("really-force")
+                    .long("really-force")
+                    .help("Show what 'update <program> --really-force' would do")
+                    .requires("program")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("format")
+                    .long("format")
+                    .value_parser(["text", "json"])
+                    .default_value("text"))
+        )
+        .subcommand(
+            Command::new("rescan")
+                .about("Scan a program's directory again, keeping its place")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("new-only")
+                    .long("new-only")
+                    .help("Only append files not seen before, by name; cheap enough to run often")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("accept-empty")
+                    .long("accept-empty")
+                    .help("Take a scan that finds no episodes, or none left to air, instead of keeping the episode list")
+                    .conflicts_with("new-only")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("renumber")
+                .about("Rescan a program after its files were renamed or reordered, keeping the episode on air")
+                .arg(Arg::new("program").required(true))
+        )
+        .subcommand(
+            Command::new("duplicate")
+                .about("Copy a program into a rerun that airs again from the start while the original continues")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("name")
+                    .long("name")
+                    .help("Name of the rerun (default: \"<name> (rerun)\")"))
+                .arg(Arg::new("link-dir")
+                    .long("link-dir")
+                    .value_name("PATH")
+                    .help("Put the rerun's links in this folder, relative to the symlink directory"))
+                .arg(Arg::new("offset-episodes")
+                    .long("offset-episodes")
+                    .value_name("N")
+                    .help("Start after the first N episodes")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("0"))
+                .arg(Arg::new("follow-rescans")
+                    .long("follow-rescans")
+                    .help("Pick up episodes found when the original is rescanned")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Find programs and episodes whose name, status label, title or file name contains the query; exits 1 if nothing matches")
+                .arg(Arg::new("query").required(true))
+                .arg(Arg::new("field")
+                    .long("field")
+                    .help("Only search this field; repeat for several")
+                    .value_parser(SEARCH_FIELDS)
+                    .action(clap::ArgAction::Append))
+                .arg(Arg::new("json")
+                    .long("json")
+                    .help("Print machine-readable JSON")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("compare")
+                .about("Show how two programs' episode lists, progress and status differ; exits 1 unless the episodes match")
+                .arg(Arg::new("program_a").required(true))
+                .arg(Arg::new("program_b").required(true))
+                .arg(Arg::new("json")
+                    .long("json")
+                    .help("Print machine-readable JSON")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("pin")
+                .about("Freeze a program on its current episode: updates, skips and renumbering leave it alone")
+                .arg(Arg::new("program").required(true))
+        )
+        .subcommand(
+            Command::new("unpin")
+                .about("Let a pinned program air on again")
+                .arg(Arg::new("program").required(true))
+        )
+        .subcommand(
+            Command::new("last")
+                .about("Show the episode a program aired last, the one linked now, and when it went live")
+                .arg(Arg::new("program").required(true))
+        )
+        .subcommand(
+            Command::new("touch")
+                .about("Start a program's interval over from now without changing its episode")
+                .arg(Arg::new("program").required(true))
+        )
+        .subcommand(
+            Command::new("set-extensions")
+                .about("Change which file extensions count as a program's episodes, then rescan it")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("extensions")
+                    .required_unless_present("reset")
+                    .help("Comma-separated extensions, e.g. cbz,cbr"))
+                .arg(Arg::new("reset")
+                    .long("reset")
+                    .help("Go back to the default video extensions")
+                    .conflicts_with("extensions")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("no-rescan")
+                    .long("no-rescan")
+                    .help("Only store the extensions, keep the current episode list")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("trim")
+                .about("Permanently drop junk episodes from the start or end of a program")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("after")
+                    .long("after")
+                    .help("Drop every episode numbered above this")
+                    .value_parser(clap::value_parser!(usize)))
+                .arg(Arg::new("before")
+                    .long("before")
+                    .help("Drop every episode numbered below this and renumber the rest from 1")
+                    .value_parser(clap::value_parser!(usize)))
+                .group(clap::ArgGroup::new("bound").args(["after", "before"]).required(true).multiple(true))
+        )
+        .subcommand(
+            Command::new("split-parts")
+                .about("Air the parts of a multi-part episode as separate episodes")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("episode").required(true).value_parser(clap::value_parser!(usize)))
+        )
+        .subcommand(
+            Command::new("join-parts")
+                .about("Join an episode with the one after it so both air together")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("episode").required(true).value_parser(clap::value_parser!(usize)))
+        )
+        .subcommand(
+            Command::new("set-batch-size")
+                .about("Link several episodes per rollover, like a streaming-style drop")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("size")
+                    .required(true)
+                    .help("Episodes per rollover, 1 for one at a time")
+                    .value_parser(clap::value_parser!(u32).range(1..)))
+                .arg(Arg::new("keep-window")
+                    .long("keep-window")
+                    .value_name("ROLLOVERS")
+                    .help("Unlink episodes once they are older than this many rollovers, instead of keeping them all")
+                    .value_parser(clap::value_parser!(u32).range(1..)))
+        )
+        .subcommand(
+            Command::new("set-season-release")
+                .about("Air a program's seasons episode by episode, or release each new season whole for a marathon")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("release")
+                    .required(true)
+                    .value_parser(["per-episode", "whole-season"]))
+                .arg(Arg::new("cooldown")
+                    .long("cooldown")
+                    .value_name("DAYS")
+                    .help("Days to wait after a whole season instead of the interval times its episodes")
+                    .value_parser(clap::value_parser!(u32).range(1..)))
+        )
+        .subcommand(
+            Command::new("set-airing-days")
+                .about("Restrict rollovers to certain weekdays (e.g. sat,sun; omit to allow any day)")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("days").required(false))
+        )
+        .subcommand(
+            Command::new("set-timezone")
+                .about("Reckon a program's airing days in an IANA time zone instead of the database's")
+                .arg(Arg::new("program").required(true))
+                .arg(Arg::new("zone")
+                    .required_unless_present("reset")
+                    .help("e.g. Asia/Tokyo"))
+                .arg(Arg::new("reset")
+                    .long("reset")
+                    .help("Follow the database's time zone again")
+                    .conflicts_with("zone")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("continue")
+                .about("Confirm airing the next season of a paused program")
+                .arg(Arg::new("program").required(true))
+        )
+        .subcommand(
+            Command::new("skip")
                 .about("Skip episodes")
+                .arg(Arg::new("program")
+                    .num_args(1..)
+                    .required_unless_present("status")
+                    .help("Program hashes, names or @collections, then optionally how many episodes to skip (default 1)"))
+                .arg(Arg::new("status")
+                    .long("status")
+                    .help("Also select every program with this status, after confirmation")
+                    .value_parser(["running", "finished", "ready", "stopped", "awaiting"]))
+                .arg(Arg::new("yes")
+                    .long("yes")
+                    .short('y')
+                    .help("Don't ask for confirmation")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("episodes")
+                    .long("episodes")
+                    .help("Skip these upcoming episodes when they come up, e.g. 3-5,7")
+                    .conflicts_with("to-episode"))
+                .arg(Arg::new("to-episode")
+                    .long("to-episode")
+                    .help("Jump forward so this episode airs next")
+                    .value_parser(clap::value_parser!(usize)))
+                .arg(Arg::new("to-next-season")
+                    .long("to-next-season")
+                    .help("Jump forward to the first episode of the next season")
+                    .conflicts_with_all(["episodes", "to-episode"])
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("exclude")
+                .about("Permanently leave episodes out of a program's schedule")
                 .arg(Arg::new("program").required(true))
-                .arg(Arg::new("count").value_parser(clap::value_parser!(usize)).default_value("1"))
+                .arg(Arg::new("episodes").required(true).help("Episode numbers, e.g. 3-5,7"))
+                .arg(Arg::new("undo")
+                    .long("undo")
+                    .help("Air the episodes again (also clears manual skips)")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("verify-links")
+                .about("Check that each program's current symlink exists and points at its episode (exits 1 if any is broken)")
+                .arg(Arg::new("fix")
+                    .long("fix")
+                    .help("Repoint symlinks that lead to the wrong file (regular files are never touched)")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("relink")
+                .about("Rename the links on air to match the current link template and layout, leaving schedules alone")
+                .arg(Arg::new("program").help("Only this program's links (hash, name or @collection)"))
+                .arg(Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("List the links that would be renamed or recreated")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("maintenance")
+                .about("Find references to programs that are gone and links tracked outside the symlink directory; exits 1 if any")
+                .arg(Arg::new("fix")
+                    .long("fix")
+                    .help("Drop the dangling references and move history to the program that now has the file")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("clean-orphans")
+                .about("Remove symlinks in the symlink directory that no program claims, even ones whose target still exists")
+                .arg(Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("List the links that would be removed")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("playlist")
+                .about("Write an M3U playlist of every running program's current symlink")
+                .arg(Arg::new("output")
+                    .long("output")
+                    .short('o')
+                    .help("Playlist file to write, or - for stdout")
+                    .default_value("-"))
+                .arg(Arg::new("aired")
+                    .long("aired")
+                    .help("List every episode aired so far instead, pointing at the source files")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("program")
+                    .long("program")
+                    .help("Program (or @collection) whose entry the next --episode replaces (repeatable)")
+                    .requires("episode")
+                    .conflicts_with_all(["aired", "interleave"])
+                    .action(clap::ArgAction::Append))
+                .arg(Arg::new("episode")
+                    .long("episode")
+                    .value_name("N")
+                    .help("Episode to list for the matching --program instead of the one on air")
+                    .value_parser(clap::value_parser!(usize))
+                    .requires("program")
+                    .action(clap::ArgAction::Append))
+                .arg(Arg::new("rebuild")
+                    .long("rebuild")
+                    .help("Regenerate the archive playlists in the archive directory from history")
+                    .requires("aired")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("prune")
+                    .long("prune")
+                    .help("Leave out episodes of programs that have been removed")
+                    .requires("aired")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("interleave")
+                    .long("interleave")
+                    .help("Alternate between programs: episode 1 of each, then episode 2 of each, ... from the aired episodes still linked")
+                    .conflicts_with("aired")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("channel")
+                    .long("channel")
+                    .value_name("COLLECTION")
+                    .help("Only interleave the programs of this collection")
+                    .requires("interleave"))
+                .arg(Arg::new("history")
+                    .long("history")
+                    .value_name("N")
+                    .help("Interleave the last N aired episodes of each program instead, from the source files")
+                    .value_parser(clap::value_parser!(usize))
+                    .requires("interleave"))
+                .arg(Arg::new("paths")
+                    .long("paths")
+                    .help("Print one path per line instead of M3U")
+                    .requires("interleave")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("milestones")
+                .about("List the milestones running programs are projected to reach, soonest first")
+        )
+        .subcommand(
+            Command::new("schedule")
+                .about("Show the episodes aired and projected per week against the weekly budget")
+                .arg(Arg::new("weeks")
+                    .long("weeks")
+                    .help("Weeks to show, starting with this one")
+                    .value_parser(clap::value_parser!(u32).range(1..))
+                    .default_value("4"))
+        )
+        .subcommand(
+            Command::new("calendar")
+                .about("Write the projected rollovers of running programs as an iCalendar file")
+                .arg(Arg::new("output")
+                    .long("output")
+                    .short('o')
+                    .help("Calendar file to write, or - for stdout")
+                    .default_value("-"))
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Move airing history between machines")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("export")
+                        .about("Write every history event as JSON")
+                        .arg(Arg::new("file")
+                            .help("File to write, or - for stdout")
+                            .default_value("-"))
+                )
+                .subcommand(
+                    Command::new("import")
+                        .about("Merge events from a history export, skipping ones already present")
+                        .arg(Arg::new("file")
+                            .required(true)
+                            .help("File written by history export"))
+                )
+        )
+        .subcommand(
+            Command::new("blackout")
+                .about("Date ranges when scheduled updates air nothing")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Air nothing from the first to the last date, inclusive")
+                        .arg(Arg::new("range")
+                            .required(true)
+                            .help("YYYY-MM-DD..YYYY-MM-DD, or one date"))
+                        .arg(Arg::new("shift")
+                            .long("shift")
+                            .help("Air rollovers due during it the day after, later ones follow on (default)")
+                            .action(clap::ArgAction::SetTrue))
+                        .arg(Arg::new("skip")
+                            .long("skip")
+                            .help("Drop rollovers due during it, keeping to the interval afterwards")
+                            .conflicts_with("shift")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove a blackout")
+                        .arg(Arg::new("range").required(true).help("As given to blackout add"))
+                )
+                .subcommand(Command::new("list").about("List blackouts and their policies"))
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Stay running and update each program when its next rollover is due")
+                .arg(Arg::new("min-sleep")
+                    .long("min-sleep")
+                    .help("Seconds to wait before retrying programs that failed to update")
+                    .value_parser(clap::value_parser!(u64).range(1..))
+                    .default_value("60"))
+                .arg(Arg::new("pid-file")
+                    .long("pid-file")
+                    .value_name("PATH")
+                    .help("Write the daemon's process id here, removed again on exit"))
+        )
+        .subcommand(
+            Command::new("daemon-status")
+                .about("Show what the running watch daemon is doing")
+        )
+        .subcommand(
+            Command::new("trigger")
+                .about("Ask the watch daemon for an immediate update, or update here if none is running")
+                .arg(Arg::new("program").help("Force a rollover of this program instead of a scheduled pass"))
+                .arg(Arg::new("really-force")
+                    .long("really-force")
+                    .help("Force even a program that rolled over within the force cooldown")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("install-schedule")
+                .about("Have the OS scheduler run 'queuecast update' every day (launchd on macOS, Task Scheduler on Windows)")
+                .arg(Arg::new("at")
+                    .long("at")
+                    .help("Time of day to run, HH:MM")
+                    .default_value("03:00"))
+                .arg(Arg::new("scheduler")
+                    .long("scheduler")
+                    .help("Scheduler to target instead of this platform's")
+                    .value_parser(["launchd", "schtasks"]))
+                .arg(Arg::new("print")
+                    .long("print")
+                    .help("Print the plist or schtasks command instead of installing it")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("uninstall-schedule")
+                .about("Remove the scheduled update installed by install-schedule")
+                .arg(Arg::new("scheduler")
+                    .long("scheduler")
+                    .help("Scheduler to target instead of this platform's")
+                    .value_parser(["launchd", "schtasks"]))
+        )
+        .subcommand(
+            Command::new("health")
+                .about("Warn and exit non-zero when scheduled updates have stopped running")
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Check that the database loads and is consistent, exiting non-zero if not")
+                .arg(Arg::new("verbose")
+                    .long("verbose")
+                    .short('v')
+                    .help("Report success too")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("accept-renames")
+                    .long("accept-renames")
+                    .help("Point programs whose directory was renamed at the new name, keeping their progress")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("healthcheck")
+                .about("Liveness probe: exit non-zero if the database, symlink directory or running daemon is unhealthy")
+                .after_help("Exit code bits: 1 database, 2 symlink directory, 4 daemon not answering, 8 daemon falling behind")
+                .arg(Arg::new("quiet")
+                    .long("quiet")
+                    .short('q')
+                    .help("Only set the exit code")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("version")
+                .about("Show version, schema and build information")
+                .arg(Arg::new("json")
+                    .long("json")
+                    .help("Print machine-readable JSON")
+                    .action(clap::ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("queue")
+                .about("Show the Ready programs in the order they start, or reorder them")
+                .subcommand(
+                    Command::new("move")
+                        .about("Move a Ready program to a position in the queue")
+                        .arg(Arg::new("program").required(true))
+                        .arg(Arg::new("position")
+                            .required_unless_present_any(["top", "bottom"])
+                            .value_parser(clap::value_parser!(u32).range(1..)))
+                        .arg(Arg::new("top")
+                            .long("top")
+                            .help("Move to the head of the queue")
+                            .conflicts_with_all(["position", "bottom"])
+                            .action(clap::ArgAction::SetTrue))
+                        .arg(Arg::new("bottom")
+                            .long("bottom")
+                            .help("Move to the tail of the queue")
+                            .conflicts_with("position")
+                            .action(clap::ArgAction::SetTrue))
+                )
+        )
+        .subcommand(
+            Command::new("collection")
+                .about("Manage named groups of programs, usable as @name in place of a program")
+                .subcommand(
+                    Command::new("create")
+                        .about("Create an empty collection")
+                        .arg(Arg::new("name").required(true))
+                )
+                .subcommand(
+                    Command::new("add")
+                        .about("Add programs to a collection")
+                        .arg(Arg::new("name").required(true))
+                        .arg(Arg::new("programs").required(true).num_args(1..))
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove programs from a collection")
+                        .arg(Arg::new("name").required(true))
+                        .arg(Arg::new("programs").required(true).num_args(1..))
+                )
+                .subcommand(
+                    Command::new("weight")
+                        .about("Make a program come round more often in the collection's interleaved playlist")
+                        .arg(Arg::new("name").required(true))
+                        .arg(Arg::new("program").required(true))
+                        .arg(Arg::new("weight")
+                            .required(true)
+                            .help("Turns per rotation relative to the other members, 1 by default")
+                            .value_parser(clap::value_parser!(u32).range(1..=100)))
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List collections and their members")
+                )
         )
         .subcommand(
             Command::new("config")
@@ -345,43 +9737,975 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     Command::new("symlink-dir")
                         .about("Set the symlink directory")
                         .arg(Arg::new("path").required(true))
+                        .arg(Arg::new("force")
+                            .long("force")
+                            .help("Allow a directory that overlaps a program's directory")
+                            .action(clap::ArgAction::SetTrue))
+                        .arg(Arg::new("migrate")
+                            .long("migrate")
+                            .help("Move the links on air from the old directory to the new one")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("nextup-dir")
+                        .about("Keep a fixed-name link per program pointing at the episode on air")
+                        .arg(Arg::new("path").required_unless_present("disable"))
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Remove next-up links and stop maintaining them")
+                            .conflicts_with("path")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("serve-root")
+                        .about("Write playlist entries in the served folder as URLs under this prefix")
+                        .arg(Arg::new("url")
+                            .required_unless_present("disable")
+                            .help("URL the folder is served at, e.g. http://media.local/tv"))
+                        .arg(Arg::new("path")
+                            .long("path")
+                            .help("Folder served at the URL [default: the symlink directory, following it when it changes]"))
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Point playlists at files again")
+                            .conflicts_with_all(["url", "path"])
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("dashboard-file")
+                        .about("Rewrite a JSON summary of the running programs on every update, for home dashboards")
+                        .arg(Arg::new("path").required_unless_present("disable"))
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Stop updating the file (it is left in place)")
+                            .conflicts_with("path")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("link-template")
+                        .about("Name new symlinks from a template of {name}, {ep}, {season} and {original}")
+                        .arg(Arg::new("template").required(true).help("Default: {name}_ep{ep}"))
+                        .arg(Arg::new("on-duplicate")
+                            .long("on-duplicate")
+                            .help("When episodes of a program would get the same name: refuse, or append _sNN")
+                            .value_parser(["error", "season"])
+                            .default_value("error"))
+                )
+                .subcommand(
+                    Command::new("link-replacement")
+                        .about("Character standing in for spaces and for characters some system refuses in file names, in new link names (default _)")
+                        .arg(Arg::new("char")
+                            .required(true)
+                            .value_parser(clap::value_parser!(char)))
+                )
+                .subcommand(
+                    Command::new("program-dirs")
+                        .about("Put each program's new symlinks in a folder named after the program")
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Put new symlinks straight into the symlink directory again")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Show every setting and whether it comes from the defaults, the file or the environment")
+                        .arg(Arg::new("json")
+                            .long("json")
+                            .help("Print the settings as JSON")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("ignore")
+                        .about("File name patterns that are never episodes, e.g. 'sample*' (case-insensitive, * and ?)")
+                        .subcommand_required(true)
+                        .subcommand(
+                            Command::new("add")
+                                .about("Ignore files matching these patterns in every scan")
+                                .arg(Arg::new("patterns").required(true).num_args(1..))
+                        )
+                        .subcommand(
+                            Command::new("remove")
+                                .about("Stop ignoring these patterns")
+                                .arg(Arg::new("patterns").required(true).num_args(1..))
+                        )
+                        .subcommand(
+                            Command::new("list")
+                                .about("Show the ignore patterns")
+                        )
+                )
+                .subcommand(
+                    Command::new("date-format")
+                        .about("How times are shown, as a strftime format")
+                        .arg(Arg::new("format")
+                            .required_unless_present("reset")
+                            .help("e.g. '%a %d %b %H:%M'"))
+                        .arg(Arg::new("reset")
+                            .long("reset")
+                            .help("Go back to the default, %Y-%m-%d %H:%M")
+                            .conflicts_with("format")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("timezone")
+                        .about("IANA time zone for airing days and shown times, for databases shared across zones")
+                        .arg(Arg::new("zone")
+                            .required_unless_present("reset")
+                            .help("e.g. Europe/Berlin or America/New_York"))
+                        .arg(Arg::new("reset")
+                            .long("reset")
+                            .help("Go back to the system's local time")
+                            .conflicts_with("zone")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("hash-length")
+                        .about("Hex digits in the hash of programs added from now on")
+                        .arg(Arg::new("length")
+                            .required(true)
+                            .value_parser(clap::value_parser!(u64).range(*HASH_LENGTHS.start() as u64..=*HASH_LENGTHS.end() as u64)))
+                )
+                .subcommand(
+                    Command::new("min-free")
+                        .about("Space to keep free where archive episodes are extracted and remote ones downloaded, e.g. 10G")
+                        .arg(Arg::new("size").required_unless_present("disable"))
+                        .arg(Arg::new("prune")
+                            .long("prune")
+                            .help("Delete a program's oldest copies that are off air, and their links, when space runs short")
+                            .action(clap::ArgAction::SetTrue))
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Stop checking free space")
+                            .conflicts_with_all(["size", "prune"])
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("on-finish-symlink")
+                        .about("Whether a finished program's last episode stays on air (keep, the default) or its links are removed")
+                        .arg(Arg::new("mode")
+                            .required(true)
+                            .value_parser(["keep", "remove"]))
+                )
+                .subcommand(
+                    Command::new("force-grace")
+                        .about("Seconds after a rollover during which forcing the program again does nothing, for repeated commands (0 to turn off)")
+                        .arg(Arg::new("seconds")
+                            .required(true)
+                            .value_parser(clap::value_parser!(u32)))
+                )
+                .subcommand(
+                    Command::new("weekly-budget")
+                        .about("Air at most this many episodes a week (Monday to Sunday) across all programs in scheduled updates")
+                        .arg(Arg::new("count")
+                            .required_unless_present("disable")
+                            .value_parser(clap::value_parser!(u32).range(1..)))
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Air whatever is due")
+                            .conflicts_with("count")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("max-running")
+                        .about("Run at most this many programs at once; Ready programs start from the head of 'queuecast queue' as slots free up")
+                        .arg(Arg::new("count")
+                            .required_unless_present("disable")
+                            .value_parser(clap::value_parser!(u32).range(1..)))
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Start every Ready program at the next update")
+                            .conflicts_with("count")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("force-cooldown")
+                        .about("Hours after a rollover during which forcing the program again needs --really-force (0 to turn off)")
+                        .arg(Arg::new("hours")
+                            .required(true)
+                            .value_parser(clap::value_parser!(u32)))
+                )
+                .subcommand(
+                    Command::new("default")
+                        .about("Preset one of add's flags for new programs added without it, e.g. 'config default batch-size 2'")
+                        .arg(Arg::new("key")
+                            .required(true)
+                            .help("interval, minutes-per-rollover, batch-size, keep-window, pause-between-seasons, airing-days, sort, folder-episodes, extensions or strict"))
+                        .arg(Arg::new("value").required_unless_present("clear"))
+                        .arg(Arg::new("clear")
+                            .long("clear")
+                            .help("Go back to add's own default")
+                            .conflicts_with("value")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("default-interval")
+                        .about("Days between rollovers for new programs added without --interval")
+                        .arg(Arg::new("days")
+                            .required(true)
+                            .value_parser(clap::value_parser!(u32).range(1..)))
+                )
+                .subcommand(
+                    Command::new("path-root")
+                        .about("Store paths under this folder relative to it, so the library can move")
+                        .arg(Arg::new("path")
+                            .required_unless_present("disable")
+                            .help("Relative paths are taken from the folder holding the database"))
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Store absolute paths again")
+                            .conflicts_with("path")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("nfo")
+                        .about("Write a .nfo sidecar next to every new symlink")
+                        .arg(Arg::new("template")
+                            .long("template")
+                            .help("File with the sidecar content; {program}, {hash}, {season}, {episode} and {title} are filled in"))
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Stop writing sidecars")
+                            .conflicts_with("template")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("ready-marker")
+                        .about("Write <link>.ready once each new symlink and its sidecar are in place")
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Stop writing markers")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("hook")
+                        .about("Set commands run before and after every rollover")
+                        .arg(Arg::new("pre")
+                            .long("pre")
+                            .help("Command run before each rollover, a non-zero exit skips it"))
+                        .arg(Arg::new("post")
+                            .long("post")
+                            .help("Command run after each rollover"))
+                        .arg(Arg::new("milestone")
+                            .long("milestone")
+                            .help("Command run when a program reaches a milestone, with QC_MILESTONE set"))
+                        .arg(Arg::new("clear")
+                            .long("clear")
+                            .help("Remove all global hooks")
+                            .conflicts_with_all(["pre", "post", "milestone"])
+                            .action(clap::ArgAction::SetTrue))
+                        .group(clap::ArgGroup::new("hook").args(["pre", "post", "milestone", "clear"]).required(true).multiple(true))
+                )
+                .subcommand(
+                    Command::new("milestones")
+                        .about("Set what counts as a milestone: percentages like 25% or 50%, and 'finale' for one episode left (default: 50% finale)")
+                        .arg(Arg::new("milestones")
+                            .num_args(0..)
+                            .help("Milestones, none to turn them off"))
+                )
+                .subcommand(
+                    Command::new("part-markers")
+                        .about("Set the words that mark a file as one part of a multi-part episode, like 'Show E01 Part1' (default: cd part pt)")
+                        .arg(Arg::new("markers")
+                            .num_args(0..)
+                            .help("Marker words, none to stop grouping files by name"))
+                        .arg(Arg::new("reset")
+                            .long("reset")
+                            .help("Go back to the default markers")
+                            .conflicts_with("markers")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("download-markers")
+                        .about("Set the suffixes of files a download client keeps next to an unfinished episode, like 'Show.mkv.part' (default: .part .!qB .crdownload)")
+                        .arg(Arg::new("markers")
+                            .num_args(0..)
+                            .allow_hyphen_values(true)
+                            .help("Suffixes, none to stop looking for marker files"))
+                        .arg(Arg::new("reset")
+                            .long("reset")
+                            .help("Go back to the default markers")
+                            .conflicts_with("markers")
+                            .action(clap::ArgAction::SetTrue))
+                )
+                .subcommand(
+                    Command::new("download-settle")
+                        .about("Seconds to watch the size of episodes about to air; one still growing waits for the next update (default 2, 0 to turn off)")
+                        .arg(Arg::new("seconds")
+                            .required(true)
+                            .value_parser(clap::value_parser!(u32)))
+                )
+                .subcommand(
+                    Command::new("archive-dir")
+                        .about("Keep an aired.m3u8 archive of every episode as it airs")
+                        .arg(Arg::new("path").required_unless_present("disable"))
+                        .arg(Arg::new("per-program")
+                            .long("per-program")
+                            .help("Also keep one archive playlist per program")
+                            .action(clap::ArgAction::SetTrue))
+                        .arg(Arg::new("disable")
+                            .long("disable")
+                            .help("Stop appending to the archive")
+                            .conflicts_with_all(["path", "per-program"])
+                            .action(clap::ArgAction::SetTrue))
                 )
         )
         .get_matches();
 
+    init_portable_mode(matches.get_flag("portable"))?;
+    let dry_run = matches.subcommand_matches("update").is_some_and(|sub_matches| sub_matches.get_flag("dry-run"));
+    NO_SAVE.store(matches.get_flag("no-save") || dry_run, AtomicOrdering::SeqCst);
+    RUN_HOOKS_ON_DRY_RUN.store(matches.get_flag("run-hooks-on-dry-run"), AtomicOrdering::SeqCst);
+    FORCE_REFRESH.store(matches.get_flag("force-refresh"), AtomicOrdering::SeqCst);
+
+    // Works even when the database can't be loaded, which is when it's most useful
+    if let Some(("version", sub_matches)) = matches.subcommand() {
+        return print_version(sub_matches.get_flag("json"));
+    }
+    // Manages its own load/save cycle
+    if let Some(("watch", sub_matches)) = matches.subcommand() {
+        // Every pass would simulate the same rollovers again from the file
+        if simulating() {
+            return Err("watch can't run with --no-save, try 'queuecast --no-save update' instead".into());
+        }
+        return watch(*sub_matches.get_one::<u64>("min-sleep").unwrap(),
+            sub_matches.get_one::<String>("pid-file").map(Path::new));
+    }
+    if let Some(("daemon-status", _)) = matches.subcommand() {
+        if !daemon_status()? {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    // The daemon would make the changes for real, so --no-save simulates here
+    if let Some(("trigger", sub_matches)) = matches.subcommand().filter(|_| !simulating()) {
+        if trigger_daemon(sub_matches.get_one::<String>("program").map(|s| s.as_str()), sub_matches.get_flag("really-force"))? {
+            return Ok(());
+        }
+    }
+    // Read-only, so a healthcheck never rewrites the database
+    if let Some(("check", sub_matches)) = matches.subcommand() {
+        return run_check(sub_matches.get_flag("verbose"), sub_matches.get_flag("accept-renames"));
+    }
+    // Read-only, and the exit code is the answer
+    if let Some(("diff", sub_matches)) = matches.subcommand() {
+        let db = Database::load()?;
+        let program = sub_matches.get_one::<String>("program").map(|s| s.as_str());
+        let force = if sub_matches.get_flag("really-force") { Force::Really } else { Force::Yes };
+        let json = sub_matches.get_one::<String>("format").is_some_and(|format| format == "json");
+        process::exit(diff_update(&db, program, force, json)?);
+    }
+    if let Some(("healthcheck", sub_matches)) = matches.subcommand() {
+        process::exit(healthcheck(sub_matches.get_flag("quiet")));
+    }
+
     let mut db = Database::load()?;
+    let mut exit_code = 0;
 
     match matches.subcommand() {
         Some(("add", sub_matches)) => {
-            let directory = sub_matches.get_one::<String>("directory").unwrap();
-            add_program(&mut db, directory)?;
+            let mut options = AddOptions {
+                name: sub_matches.get_one::<String>("name").map(|name| clean_program_name(name)).transpose()?,
+                interval: sub_matches.get_one::<Interval>("interval").copied()
+                    .unwrap_or(Interval::Days(default_interval_days())),
+                minutes_per_rollover: sub_matches.get_one::<u32>("minutes-per-rollover").copied(),
+                pause_between_seasons: sub_matches.get_flag("pause-between-seasons"),
+                airing_days: match sub_matches.get_one::<String>("airing-days") {
+                    Some(days) => parse_weekdays(days)?,
+                    None => Vec::new(),
+                },
+                sort: EpisodeSort::parse(sub_matches.get_one::<String>("sort").unwrap()).unwrap(),
+                folder_episodes: sub_matches.get_flag("folder-episodes"),
+                adopt: sub_matches.get_one::<String>("adopt").map(PathBuf::from),
+                from_archive: sub_matches.get_flag("from-archive"),
+                force: sub_matches.get_flag("force"),
+                extensions: match sub_matches.get_one::<String>("extensions") {
+                    Some(list) => parse_extensions(list)?,
+                    None => Vec::new(),
+                },
+                strict: sub_matches.get_flag("strict"),
+                dry_run: sub_matches.get_flag("dry-run"),
+                queue_top: sub_matches.get_flag("queue-top"),
+                batch_size: sub_matches.get_one::<u32>("batch-size").copied().filter(|&size| size > 1),
+                keep_window: sub_matches.get_one::<u32>("keep-window").copied(),
+            };
+            if !sub_matches.get_flag("no-defaults") {
+                let given = |id: &str| sub_matches.value_source(id) == Some(clap::parser::ValueSource::CommandLine);
+                apply_add_defaults(&db, &mut options, given)?;
+            }
+            match sub_matches.get_one::<String>("from-file") {
+                Some(source) => {
+                    if add_from_file(&mut db, source, &options)? > 0 {
+                        exit_code = 1;
+                    }
+                }
+                None => {
+                    let directory = sub_matches.get_one::<String>("directory").unwrap();
+                    add_program(&mut db, directory, &options)?;
+                }
+            }
+        }
+        Some(("add-file", sub_matches)) => {
+            let path = sub_matches.get_one::<String>("path").unwrap();
+            let program = sub_matches.get_one::<String>("program").map(|s| s.as_str());
+            let options = AddOptions {
+                interval: sub_matches.get_one::<Interval>("interval").copied()
+                    .unwrap_or(Interval::Days(db.default_interval.unwrap_or(7))),
+                force: sub_matches.get_flag("force"),
+                ..AddOptions::default()
+            };
+            add_file(&mut db, path, program, &options)?;
+        }
+        Some(("list", sub_matches)) => {
+            let filter = sub_matches.get_one::<String>("filter").map(|s| s.as_str()).unwrap_or("all");
+            let times = ListTimes {
+                added_since: sub_matches.get_one::<DateTime<Utc>>("added-since").copied(),
+                updated_since: sub_matches.get_one::<DateTime<Utc>>("updated-since").copied(),
+                updated_before: sub_matches.get_one::<DateTime<Utc>>("updated-before").copied(),
+            };
+            list_programs(&db, filter, sub_matches.get_flag("stalled"), &times)?;
+        }
+        Some(("info", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            for hash in resolve_programs(&db, program)? {
+                if !sub_matches.get_flag("check") {
+                    show_info(&db, &hash)?;
+                    continue;
+                }
+                let program = &db.programs[&hash];
+                let problems = numbering_problems(&program.episodes);
+                if problems.is_empty() {
+                    println!("Episode numbering of '{}' is complete", program.name);
+                } else {
+                    print_numbering_problems(&program.name, &problems);
+                    exit_code = 1;
+                }
+            }
+        }
+        // trigger only gets here when no daemon is running
+        Some(("update", sub_matches)) | Some(("trigger", sub_matches)) => {
+            let program_args: Vec<&str> = sub_matches.get_many::<String>("program").into_iter().flatten()
+                .chain(sub_matches.try_get_many::<String>("programs").ok().flatten().into_iter().flatten())
+                .map(|s| s.as_str())
+                .collect();
+            if program_args.is_empty() {
+                let catch_up_limit = sub_matches.try_get_one::<u32>("catch-up-limit").ok().flatten().copied();
+                update_symlinks(&mut db, None, catch_up_limit)?;
+            } else {
+                let selection = select_programs(&db, &program_args, None);
+                let force = if sub_matches.get_flag("really-force") { Force::Really } else { Force::Yes };
+                let unpin_first = sub_matches.try_get_one::<bool>("unpin-first").ok().flatten() == Some(&true);
+                let update = |db: &mut Database, hash: &str| {
+                    if unpin_first && db.programs.get(hash).is_some_and(|p| p.pinned) {
+                        set_pinned(db, hash, false)?;
+                    }
+                    update_symlinks(db, Some((hash, force)), None).map(|_| ())
+                };
+                if !for_each_program(&mut db, selection, update) {
+                    exit_code = 1;
+                }
+            }
+            if sub_matches.try_get_one::<bool>("verify").ok().flatten() == Some(&true) && verify_links(&db, false)? {
+                exit_code = 1;
+            }
+        }
+        Some(("install-schedule", sub_matches)) => {
+            let backend = ScheduleBackend::parse(sub_matches.get_one::<String>("scheduler").map(|s| s.as_str()))?;
+            let at = sub_matches.get_one::<String>("at").unwrap();
+            let time = NaiveTime::parse_from_str(at, "%H:%M")
+                .map_err(|_| format!("Invalid time '{}', expected HH:MM", at))?;
+            install_schedule(backend, time, sub_matches.get_flag("print"))?;
+        }
+        Some(("uninstall-schedule", sub_matches)) => {
+            let backend = ScheduleBackend::parse(sub_matches.get_one::<String>("scheduler").map(|s| s.as_str()))?;
+            uninstall_schedule(backend)?;
+        }
+        Some(("health", _)) => {
+            if !health(&db) {
+                exit_code = 1;
+            }
+        }
+        Some(("remove", sub_matches)) => {
+            if !run_on_selection(&mut db, sub_matches, "remove", true, remove_program)? {
+                exit_code = 1;
+            }
+        }
+        Some(("stop", sub_matches)) => {
+            if !run_on_selection(&mut db, sub_matches, "stop", false, stop_program)? {
+                exit_code = 1;
+            }
+        }
+        Some(("set-title", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let hash = match resolve_programs(&db, program)?.as_slice() {
+                [hash] => hash.clone(),
+                _ => return Err(format!("'{}' names more than one program, episode titles are per program", program).into()),
+            };
+            match sub_matches.get_one::<String>("from-file") {
+                Some(source) => import_episode_titles(&mut db, &hash, source)?,
+                None => {
+                    let episode = *sub_matches.get_one::<usize>("episode").unwrap();
+                    let title = sub_matches.get_one::<String>("title").map(|s| s.as_str());
+                    set_episode_title(&mut db, &hash, episode, title)?;
+                }
+            }
+            // With {title} in the link template two episodes could now share a link name
+            check_link_names(&db.link_naming, &db.programs[&hash])?;
+        }
+        Some(("set-status", sub_matches)) if sub_matches.contains_id("from") => {
+            let status = |id: &str| sub_matches.get_one::<String>(id).and_then(|s| parse_status(s)).unwrap();
+            transition_programs(&mut db, status("from"), status("to"), sub_matches.get_flag("yes"))?;
+        }
+        Some(("set-status", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let label = sub_matches.get_one::<String>("label").map(|s| s.as_str());
+            for hash in resolve_programs(&db, program)? {
+                set_custom_status(&mut db, &hash, label)?;
+            }
+        }
+        Some(("set-hook", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let pre = sub_matches.get_one::<String>("pre").map(|s| s.as_str());
+            let post = sub_matches.get_one::<String>("post").map(|s| s.as_str());
+            for hash in resolve_programs(&db, program)? {
+                set_program_hooks(&mut db, &hash, pre, post, sub_matches.get_flag("none"), sub_matches.get_flag("inherit"))?;
+            }
+        }
+        Some(("mark-watched", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let episode = *sub_matches.get_one::<usize>("episode").unwrap();
+            for hash in resolve_programs(&db, program)? {
+                mark_watched(&mut db, &hash, episode)?;
+            }
+        }
+        Some(("rescan", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            for hash in resolve_programs(&db, program)? {
+                let rescanned = if sub_matches.get_flag("new-only") {
+                    rescan_new_only(&mut db, &hash)?
+                } else {
+                    rescan_program(&mut db, &hash, sub_matches.get_flag("accept-empty"))?
+                };
+                if !rescanned {
+                    exit_code = 1;
+                }
+            }
+        }
+        Some(("renumber", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            for hash in resolve_programs(&db, program)? {
+                renumber_program(&mut db, &hash)?;
+            }
+        }
+        Some(("duplicate", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let options = DuplicateOptions {
+                name: sub_matches.get_one::<String>("name").cloned(),
+                link_dir: sub_matches.get_one::<String>("link-dir").map(PathBuf::from),
+                offset_episodes: *sub_matches.get_one::<usize>("offset-episodes").unwrap(),
+                follow_rescans: sub_matches.get_flag("follow-rescans"),
+            };
+            for hash in resolve_programs(&db, program)? {
+                duplicate_program(&mut db, &hash, &options)?;
+            }
+        }
+        Some(("search", sub_matches)) => {
+            let fields: Vec<&str> = match sub_matches.get_many::<String>("field") {
+                Some(fields) => fields.map(String::as_str).collect(),
+                None => SEARCH_FIELDS.to_vec(),
+            };
+            if !show_search(&db, sub_matches.get_one::<String>("query").unwrap(), &fields, sub_matches.get_flag("json"))? {
+                exit_code = 1;
+            }
+        }
+        Some(("compare", sub_matches)) => {
+            let resolve_one = |arg: &str| -> Result<String, Box<dyn std::error::Error>> {
+                match resolve_programs(&db, arg)?.as_slice() {
+                    [hash] => Ok(hash.clone()),
+                    _ => Err(format!("'{}' names more than one program, compare takes two programs", arg).into()),
+                }
+            };
+            let hash_a = resolve_one(sub_matches.get_one::<String>("program_a").unwrap())?;
+            let hash_b = resolve_one(sub_matches.get_one::<String>("program_b").unwrap())?;
+            if !show_comparison(&db, &hash_a, &hash_b, sub_matches.get_flag("json"))? {
+                exit_code = 1;
+            }
+        }
+        Some((command @ ("pin" | "unpin"), sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            for hash in resolve_programs(&db, program)? {
+                set_pinned(&mut db, &hash, command == "pin")?;
+            }
         }
-        Some(("list", sub_matches)) => {
-            let filter = sub_matches.get_one::<String>("filter").map(|s| s.as_str()).unwrap_or("all");
-            list_programs(&db, filter)?;
+        Some(("last", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            for hash in resolve_programs(&db, program)? {
+                show_last_aired(&db, &hash)?;
+            }
         }
-        Some(("update", sub_matches)) => {
-            let program = sub_matches.get_one::<String>("program").map(|s| s.as_str());
-            update_symlinks(&mut db, program)?;
+        Some(("touch", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            for hash in resolve_programs(&db, program)? {
+                touch_program(&mut db, &hash)?;
+            }
         }
-        Some(("remove", sub_matches)) => {
+        Some(("set-extensions", sub_matches)) => {
             let program = sub_matches.get_one::<String>("program").unwrap();
-            remove_program(&mut db, program)?;
+            let extensions = match sub_matches.get_one::<String>("extensions") {
+                Some(list) => parse_extensions(list)?,
+                None => Vec::new(),
+            };
+            for hash in resolve_programs(&db, program)? {
+                set_extensions(&mut db, &hash, extensions.clone(), !sub_matches.get_flag("no-rescan"))?;
+            }
         }
-        Some(("stop", sub_matches)) => {
+        Some(("trim", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let after = sub_matches.get_one::<usize>("after").copied();
+            let before = sub_matches.get_one::<usize>("before").copied();
+            for hash in resolve_programs(&db, program)? {
+                trim_episodes(&mut db, &hash, after, before)?;
+            }
+        }
+        Some(("split-parts", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let episode = *sub_matches.get_one::<usize>("episode").unwrap();
+            split_parts(&mut db, program, episode)?;
+        }
+        Some(("join-parts", sub_matches)) => {
             let program = sub_matches.get_one::<String>("program").unwrap();
-            stop_program(&mut db, program)?;
+            let episode = *sub_matches.get_one::<usize>("episode").unwrap();
+            join_parts(&mut db, program, episode)?;
+        }
+        Some(("set-batch-size", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let size = *sub_matches.get_one::<u32>("size").unwrap();
+            let keep_window = sub_matches.get_one::<u32>("keep-window").copied();
+            for hash in resolve_programs(&db, program)? {
+                set_batch_size(&mut db, &hash, size, keep_window)?;
+            }
+        }
+        Some(("set-season-release", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let release = match sub_matches.get_one::<String>("release").map(|s| s.as_str()) {
+                Some("whole-season") => SeasonRelease::WholeSeason,
+                _ => SeasonRelease::PerEpisode,
+            };
+            let cooldown = sub_matches.get_one::<u32>("cooldown").copied();
+            for hash in resolve_programs(&db, program)? {
+                set_season_release(&mut db, &hash, release, cooldown)?;
+            }
+        }
+        Some(("set-airing-days", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let airing_days = match sub_matches.get_one::<String>("days") {
+                Some(days) => parse_weekdays(days)?,
+                None => Vec::new(),
+            };
+            for hash in resolve_programs(&db, program)? {
+                set_airing_days(&mut db, &hash, airing_days.clone())?;
+            }
+        }
+        Some(("set-timezone", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            let zone = sub_matches.get_one::<String>("zone").map(|s| s.as_str());
+            for hash in resolve_programs(&db, program)? {
+                set_program_timezone(&mut db, &hash, zone)?;
+            }
+        }
+        Some(("continue", sub_matches)) => {
+            let program = sub_matches.get_one::<String>("program").unwrap();
+            for hash in resolve_programs(&db, program)? {
+                continue_program(&mut db, &hash)?;
+            }
         }
         Some(("skip", sub_matches)) => {
+            let (identifiers, count) = skip_arguments(&db, sub_matches);
+            if count.is_some() && (sub_matches.contains_id("episodes") || sub_matches.contains_id("to-episode") || sub_matches.get_flag("to-next-season")) {
+                return Err("A count can't be combined with --episodes, --to-episode or --to-next-season".into());
+            }
+            let skipped = run_on_identifiers(&mut db, &identifiers, sub_matches, "skip episodes of", false, |db, hash| {
+                if let Some(spec) = sub_matches.get_one::<String>("episodes") {
+                    let numbers = parse_episode_ranges(spec, highest_episode_number(db, &[hash.to_string()]))?;
+                    skip_episode_numbers(db, hash, &numbers, SkipReason::Manual)
+                } else if let Some(&episode) = sub_matches.get_one::<usize>("to-episode") {
+                    skip_to_episode(db, hash, episode)
+                } else if sub_matches.get_flag("to-next-season") {
+                    skip_to_next_season(db, hash)
+                } else {
+                    skip_episodes(db, hash, count.unwrap_or(1))
+                }
+            })?;
+            if !skipped {
+                exit_code = 1;
+            }
+        }
+        Some(("exclude", sub_matches)) => {
             let program = sub_matches.get_one::<String>("program").unwrap();
-            let count = *sub_matches.get_one::<usize>("count").unwrap();
-            skip_episodes(&mut db, program, count)?;
+            let hashes = resolve_programs(&db, program)?;
+            let numbers = parse_episode_ranges(sub_matches.get_one::<String>("episodes").unwrap(), highest_episode_number(&db, &hashes))?;
+            for hash in hashes {
+                if sub_matches.get_flag("undo") {
+                    include_episode_numbers(&mut db, &hash, &numbers)?;
+                } else {
+                    skip_episode_numbers(&mut db, &hash, &numbers, SkipReason::Excluded)?;
+                }
+            }
+        }
+        Some(("verify-links", sub_matches)) => {
+            if verify_links(&db, sub_matches.get_flag("fix"))? {
+                exit_code = 1;
+            }
+        }
+        Some(("relink", sub_matches)) => {
+            let hashes = match sub_matches.get_one::<String>("program") {
+                Some(program) => resolve_programs(&db, program)?,
+                None => db.programs.keys().cloned().collect(),
+            };
+            relink(&mut db, &hashes, sub_matches.get_flag("dry-run"))?;
+        }
+        Some(("maintenance", sub_matches)) => {
+            if maintenance(&mut db, sub_matches.get_flag("fix"))? {
+                exit_code = 1;
+            }
+        }
+        Some(("clean-orphans", sub_matches)) => {
+            clean_orphans(&db, sub_matches.get_flag("dry-run"))?;
+        }
+        Some(("playlist", sub_matches)) => {
+            let output = sub_matches.get_one::<String>("output").unwrap();
+            if sub_matches.get_flag("interleave") {
+                write_interleaved_playlist(
+                    &db,
+                    output,
+                    sub_matches.get_one::<String>("channel").map(|s| s.as_str()),
+                    sub_matches.get_one::<usize>("history").copied(),
+                    sub_matches.get_flag("paths"),
+                )?;
+            } else if sub_matches.get_flag("aired") {
+                write_aired_playlist(&db, output, sub_matches.get_flag("rebuild"), sub_matches.get_flag("prune"))?;
+            } else {
+                let programs: Vec<&String> = sub_matches.get_many::<String>("program").into_iter().flatten().collect();
+                let episodes: Vec<usize> = sub_matches.get_many::<usize>("episode").into_iter().flatten().copied().collect();
+                if programs.len() != episodes.len() {
+                    return Err("Give one --episode for every --program".into());
+                }
+                let mut overrides = HashMap::new();
+                for (program, episode) in programs.into_iter().zip(episodes) {
+                    for hash in resolve_programs(&db, program)? {
+                        overrides.insert(hash, episode);
+                    }
+                }
+                write_playlist(&db, output, &overrides)?;
+            }
+        }
+        Some(("milestones", _)) => {
+            show_milestones(&db)?;
+        }
+        Some(("schedule", sub_matches)) => {
+            show_schedule(&db, *sub_matches.get_one::<u32>("weeks").unwrap())?;
+        }
+        Some(("calendar", sub_matches)) => {
+            write_calendar(&db, sub_matches.get_one::<String>("output").unwrap())?;
+        }
+        Some(("history", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("export", history_matches)) => {
+                    export_history(&db, history_matches.get_one::<String>("file").unwrap())?;
+                }
+                Some(("import", history_matches)) => {
+                    import_history(&mut db, Path::new(history_matches.get_one::<String>("file").unwrap()))?;
+                }
+                _ => unreachable!(),
+            }
+        }
+        Some(("blackout", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("add", blackout_matches)) => {
+                    let policy = if blackout_matches.get_flag("skip") { BlackoutPolicy::Skip } else { BlackoutPolicy::Shift };
+                    add_blackout(&mut db, blackout_matches.get_one::<String>("range").unwrap(), policy)?;
+                }
+                Some(("remove", blackout_matches)) => {
+                    remove_blackout(&mut db, blackout_matches.get_one::<String>("range").unwrap())?;
+                }
+                Some(("list", _)) => list_blackouts(&db),
+                _ => unreachable!(),
+            }
+        }
+        Some(("queue", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("move", queue_matches)) => {
+                    let program = queue_matches.get_one::<String>("program").unwrap();
+                    let position = if queue_matches.get_flag("top") {
+                        1
+                    } else if queue_matches.get_flag("bottom") {
+                        usize::MAX
+                    } else {
+                        *queue_matches.get_one::<u32>("position").unwrap() as usize
+                    };
+                    let hash = match resolve_programs(&db, program)?.as_slice() {
+                        [hash] => hash.clone(),
+                        _ => return Err(format!("'{}' names more than one program, move them one at a time", program).into()),
+                    };
+                    move_in_queue(&mut db, &hash, position)?;
+                }
+                _ => show_queue(&db)?,
+            }
+        }
+        Some(("collection", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("create", collection_matches)) => {
+                    let name = collection_matches.get_one::<String>("name").unwrap();
+                    create_collection(&mut db, name)?;
+                }
+                Some(("add", collection_matches)) => {
+                    let name = collection_matches.get_one::<String>("name").unwrap();
+                    let programs: Vec<String> = collection_matches.get_many::<String>("programs").unwrap().cloned().collect();
+                    add_to_collection(&mut db, name, &programs)?;
+                }
+                Some(("remove", collection_matches)) => {
+                    let name = collection_matches.get_one::<String>("name").unwrap();
+                    let programs: Vec<String> = collection_matches.get_many::<String>("programs").unwrap().cloned().collect();
+                    remove_from_collection(&mut db, name, &programs)?;
+                }
+                Some(("weight", collection_matches)) => {
+                    let name = collection_matches.get_one::<String>("name").unwrap();
+                    let program = collection_matches.get_one::<String>("program").unwrap();
+                    let weight = *collection_matches.get_one::<u32>("weight").unwrap();
+                    set_collection_weight(&mut db, name, program, weight)?;
+                }
+                Some(("list", _)) => {
+                    list_collections(&db)?;
+                }
+                _ => {
+                    println!("Use 'queuecast collection --help' for collection commands");
+                }
+            }
         }
         Some(("config", sub_matches)) => {
             match sub_matches.subcommand() {
                 Some(("symlink-dir", config_matches)) => {
                     let path = config_matches.get_one::<String>("path").unwrap();
-                    set_symlink_dir(&mut db, path)?;
+                    set_symlink_dir(&mut db, path, config_matches.get_flag("force"), config_matches.get_flag("migrate"))?;
+                }
+                Some(("nextup-dir", config_matches)) => {
+                    let path = config_matches.get_one::<String>("path").map(|s| s.as_str());
+                    set_nextup_dir(&mut db, path)?;
+                }
+                Some(("serve-root", config_matches)) => {
+                    let url = config_matches.get_one::<String>("url").map(|s| s.as_str());
+                    let path = config_matches.get_one::<String>("path").map(|s| s.as_str());
+                    set_serve_root(&mut db, url, path)?;
+                }
+                Some(("dashboard-file", config_matches)) => {
+                    set_dashboard_file(&mut db, config_matches.get_one::<String>("path").map(|s| s.as_str()))?;
+                }
+                Some(("link-template", config_matches)) => {
+                    let template = config_matches.get_one::<String>("template").unwrap();
+                    let on_duplicate = match config_matches.get_one::<String>("on-duplicate").map(|s| s.as_str()) {
+                        Some("season") => DuplicateLinkNames::AppendSeason,
+                        _ => DuplicateLinkNames::Error,
+                    };
+                    set_link_naming(&mut db, template, on_duplicate)?;
+                }
+                Some(("link-replacement", config_matches)) => {
+                    set_link_replacement(&mut db, *config_matches.get_one::<char>("char").unwrap())?;
+                }
+                Some(("program-dirs", config_matches)) => {
+                    set_program_dirs(&mut db, !config_matches.get_flag("disable"))?;
+                }
+                Some(("show", show_matches)) => {
+                    show_config(&db, show_matches.get_flag("json"))?;
+                }
+                Some(("ignore", ignore_matches)) => {
+                    match ignore_matches.subcommand() {
+                        Some(("add", add_matches)) => {
+                            let patterns: Vec<String> = add_matches.get_many::<String>("patterns").unwrap().cloned().collect();
+                            add_ignore_patterns(&mut db, &patterns)?;
+                        }
+                        Some(("remove", remove_matches)) => {
+                            let patterns: Vec<String> = remove_matches.get_many::<String>("patterns").unwrap().cloned().collect();
+                            remove_ignore_patterns(&mut db, &patterns)?;
+                        }
+                        Some(("list", _)) => list_ignore_patterns(&db),
+                        _ => unreachable!(),
+                    }
+                }
+                Some(("date-format", config_matches)) => {
+                    set_date_format(&mut db, config_matches.get_one::<String>("format").map(|s| s.as_str()))?;
+                }
+                Some(("timezone", config_matches)) => {
+                    set_timezone(&mut db, config_matches.get_one::<String>("zone").map(|s| s.as_str()))?;
+                }
+                Some(("hash-length", config_matches)) => {
+                    set_hash_length(&mut db, *config_matches.get_one::<u64>("length").unwrap() as usize)?;
+                }
+                Some(("min-free", config_matches)) => {
+                    let size = config_matches.get_one::<String>("size").map(|raw| parse_size(raw)).transpose()?;
+                    set_min_free(&mut db, size, config_matches.get_flag("prune"))?;
+                }
+                Some(("on-finish-symlink", config_matches)) => {
+                    let mode = match config_matches.get_one::<String>("mode").unwrap().as_str() {
+                        "remove" => OnFinishSymlink::Remove,
+                        _ => OnFinishSymlink::Keep,
+                    };
+                    set_on_finish_symlink(&mut db, mode)?;
+                }
+                Some(("force-grace", config_matches)) => {
+                    set_force_grace(&mut db, *config_matches.get_one::<u32>("seconds").unwrap())?;
+                }
+                Some(("weekly-budget", config_matches)) => {
+                    set_weekly_budget(&mut db, config_matches.get_one::<u32>("count").copied())?;
+                }
+                Some(("max-running", config_matches)) => {
+                    set_max_running(&mut db, config_matches.get_one::<u32>("count").copied())?;
+                }
+                Some(("force-cooldown", config_matches)) => {
+                    set_force_cooldown(&mut db, *config_matches.get_one::<u32>("hours").unwrap())?;
+                }
+                Some(("default", config_matches)) => {
+                    let key = config_matches.get_one::<String>("key").unwrap();
+                    let value = config_matches.get_one::<String>("value").map(|s| s.as_str());
+                    set_add_default(&mut db, key, value)?;
+                }
+                Some(("default-interval", config_matches)) => {
+                    set_default_interval(&mut db, *config_matches.get_one::<u32>("days").unwrap())?;
+                }
+                Some(("path-root", config_matches)) => {
+                    let path = config_matches.get_one::<String>("path").map(|s| s.as_str());
+                    set_path_root(&mut db, path)?;
+                }
+                Some(("nfo", config_matches)) => {
+                    let template = config_matches.get_one::<String>("template").map(|s| s.as_str());
+                    set_nfo_template(&mut db, template, config_matches.get_flag("disable"))?;
+                }
+                Some(("ready-marker", config_matches)) => {
+                    set_ready_markers(&mut db, !config_matches.get_flag("disable"))?;
+                }
+                Some(("hook", config_matches)) => {
+                    let pre = config_matches.get_one::<String>("pre").map(|s| s.as_str());
+                    let post = config_matches.get_one::<String>("post").map(|s| s.as_str());
+                    let milestone = config_matches.get_one::<String>("milestone").map(|s| s.as_str());
+                    set_global_hooks(&mut db, pre, post, milestone, config_matches.get_flag("clear"))?;
+                }
+                Some(("milestones", config_matches)) => {
+                    let milestones = config_matches.get_many::<String>("milestones").into_iter().flatten()
+                        .map(|raw| Milestone::parse(raw))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    set_milestones(&mut db, milestones)?;
+                }
+                Some(("part-markers", config_matches)) => {
+                    let markers = config_matches.get_many::<String>("markers").into_iter().flatten().cloned().collect();
+                    set_part_markers(&mut db, (!config_matches.get_flag("reset")).then_some(markers))?;
+                }
+                Some(("download-markers", config_matches)) => {
+                    let markers = config_matches.get_many::<String>("markers").into_iter().flatten().cloned().collect();
+                    set_download_markers(&mut db, (!config_matches.get_flag("reset")).then_some(markers))?;
+                }
+                Some(("download-settle", config_matches)) => {
+                    set_download_settle(&mut db, *config_matches.get_one::<u32>("seconds").unwrap())?;
+                }
+                Some(("archive-dir", config_matches)) => {
+                    let path = config_matches.get_one::<String>("path").map(|s| s.as_str());
+                    set_archive_dir(&mut db, path, config_matches.get_flag("per-program"))?;
                 }
                 _ => {
                     println!("Use 'queuecast config --help' for configuration options");
@@ -394,5 +10718,787 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     db.save()?;
+    if exit_code != 0 {
+        process::exit(exit_code);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A scratch folder, removed when the test ends, that also holds the
+    // database of the test's thread
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Scratch {
+            let dir = env::temp_dir().join(format!("queuecast-test-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("state")).unwrap();
+            let dir = dir.canonicalize().unwrap();
+            TEST_DATA_DIR.with(|data| *data.borrow_mut() = Some(dir.join("state")));
+            Scratch(dir)
+        }
+
+        fn path(&self, relative: &str) -> PathBuf {
+            self.0.join(relative)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            TEST_DATA_DIR.with(|data| *data.borrow_mut() = None);
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // A running program named Show over `files`, nothing aired yet
+    fn program(directory: &Path, files: &[PathBuf]) -> Program {
+        let episodes: Vec<serde_json::Value> = files.iter().enumerate().map(|(i, path)| {
+            serde_json::json!({ "path": path, "episode_number": i + 1, "season": 1 })
+        }).collect();
+        serde_json::from_value(serde_json::json!({
+            "name": "Show",
+            "hash": "abcd1234",
+            "directory": directory,
+            "episodes": episodes,
+            "current_episode": 0,
+            "start_date": null,
+            "last_update": null,
+            "status": "Running",
+        })).unwrap()
+    }
+
+    // Show over `count` episode files in the scratch folder
+    fn show(scratch: &Scratch, count: usize) -> Program {
+        let dir = scratch.path("media/Show");
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<PathBuf> = (1..=count).map(|number| {
+            let path = dir.join(format!("Show.S01E{:02}.mkv", number));
+            fs::write(&path, b"video").unwrap();
+            path
+        }).collect();
+        program(&dir, &files)
+    }
+
+    fn database(scratch: &Scratch, programs: Vec<Program>) -> Database {
+        let links = scratch.path("links");
+        fs::create_dir_all(&links).unwrap();
+        let mut db = Database {
+            symlink_dir: Some(links),
+            download_settle_seconds: Some(0),
+            ..Database::default()
+        };
+        for program in programs {
+            db.programs.insert(program.hash.clone(), program);
+        }
+        db
+    }
+
+    #[test]
+    fn program_hooks_override_the_global_ones() {
+        let program = HookOverride::Command("notify-program".to_string());
+        assert_eq!(program.resolve(Some("notify-all")), Some("notify-program"));
+        assert_eq!(program.resolve(None), Some("notify-program"));
+        assert_eq!(HookOverride::Inherit.resolve(Some("notify-all")), Some("notify-all"));
+        assert_eq!(HookOverride::Inherit.resolve(None), None);
+    }
+
+    #[test]
+    fn no_hooks_beats_program_and_global_hooks() {
+        let mut db = Database {
+            hooks: Hooks {
+                pre: Some("notify-all".to_string()),
+                post: Some("notify-all".to_string()),
+                ..Hooks::default()
+            },
+            ..Database::default()
+        };
+        db.programs.insert("abcd1234".to_string(), program(Path::new("/media/Show"), &[]));
+
+        set_program_hooks(&mut db, "abcd1234", Some("notify-program"), Some("notify-program"), false, false).unwrap();
+        set_program_hooks(&mut db, "abcd1234", None, None, true, false).unwrap();
+        let hooks = &db.programs["abcd1234"].hooks;
+        assert_eq!(hooks.pre.resolve(db.hooks.pre.as_deref()), None);
+        assert_eq!(hooks.post.resolve(db.hooks.post.as_deref()), None);
+
+        // --inherit hands both back to the global hooks
+        set_program_hooks(&mut db, "abcd1234", None, None, false, true).unwrap();
+        let hooks = &db.programs["abcd1234"].hooks;
+        assert_eq!(hooks.pre.resolve(db.hooks.pre.as_deref()), Some("notify-all"));
+    }
+
+    #[test]
+    fn paths_follow_the_volume_to_another_mount_point() {
+        let old_root = Path::new("/media/stick");
+        let new_root = Path::new("/run/media/user/STICK");
+        let directory = old_root.join("tv/Show");
+        let files = [directory.join("Show.S01E01.mkv"), Path::new("/srv/other-disk/Show.S01E02.mkv").to_path_buf()];
+        let mut db = Database {
+            symlink_dir: Some(old_root.join("links")),
+            ..Database::default()
+        };
+        db.programs.insert("abcd1234".to_string(), program(&directory, &files));
+
+        // Saved relative to the old root, loaded under the new one
+        db.map_paths(|path| path.strip_prefix(old_root).unwrap_or(path).to_path_buf());
+        let saved = serde_json::to_value(&db).unwrap();
+        assert_eq!(saved["symlink_dir"], "links");
+        assert_eq!(saved["programs"]["abcd1234"]["directory"], "tv/Show");
+        let mut db: Database = serde_json::from_value(saved.clone()).unwrap();
+        db.map_paths(|path| new_root.join(path));
+
+        let program = &db.programs["abcd1234"];
+        assert_eq!(db.symlink_dir, Some(new_root.join("links")));
+        assert_eq!(program.directory, new_root.join("tv/Show"));
+        assert_eq!(program.episodes[0].path, new_root.join("tv/Show/Show.S01E01.mkv"));
+        // Paths on other volumes stay absolute
+        assert_eq!(program.episodes[1].path, files[1]);
+
+        // Saving under the new root gives the same file back
+        db.map_paths(|path| path.strip_prefix(new_root).unwrap_or(path).to_path_buf());
+        assert_eq!(serde_json::to_value(&db).unwrap(), saved);
+    }
+
+    fn is_link(path: &Path) -> bool {
+        fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+    }
+
+    // Journal entries of a process that has exited, as a crash leaves them
+    fn hand_journal_to_dead_process() {
+        let mut child = process::Command::new(env::current_exe().unwrap())
+            .arg("--help")
+            .stdout(process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        let path = journal_path().unwrap();
+        let mut intents: Vec<RolloverIntent> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        for intent in &mut intents {
+            intent.pid = pid;
+        }
+        fs::write(path.with_file_name(format!("{}-{}.json", JOURNAL_PREFIX, pid)), serde_json::to_string(&intents).unwrap()).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rollover_failing_part_way_undoes_its_links() {
+        let scratch = Scratch::new("link-clash");
+        let mut program = show(&scratch, 2);
+        program.batch_size = Some(2);
+        let mut db = database(&scratch, vec![program]);
+        // The second link's place is taken by a real file
+        fs::write(scratch.path("links/Show_ep02.mkv"), b"mine").unwrap();
+
+        let Err(RolloverFailure(reason, _)) = air_batch(&mut db, "abcd1234", &scratch.path("links"), None) else {
+            panic!("the rollover should fail");
+        };
+        assert_eq!(reason, StallReason::LinkClash);
+        assert!(!is_link(&scratch.path("links/Show_ep01.mkv")));
+        assert_eq!(fs::read(scratch.path("links/Show_ep02.mkv")).unwrap(), b"mine");
+        let program = &db.programs["abcd1234"];
+        assert_eq!(program.current_episode, 0);
+        assert!(program.active_links.is_empty());
+        assert!(db.history.is_empty());
+        assert!(db.intents.is_empty());
+        assert!(!journal_path().unwrap().exists());
+    }
+
+    #[test]
+    fn failed_sidecar_write_undoes_the_link() {
+        let scratch = Scratch::new("sidecar");
+        let mut db = database(&scratch, vec![show(&scratch, 2)]);
+        db.nfo_template = Some("<episodedetails/>".to_string());
+        fs::create_dir(scratch.path("links/Show_ep01.mkv.nfo")).unwrap();
+
+        assert!(air_batch(&mut db, "abcd1234", &scratch.path("links"), None).is_err());
+        assert!(!is_link(&scratch.path("links/Show_ep01.mkv")));
+        assert_eq!(db.programs["abcd1234"].current_episode, 0);
+        assert!(db.history.is_empty());
+        assert!(!journal_path().unwrap().exists());
+    }
+
+    #[test]
+    fn rollover_never_saved_is_undone_on_the_next_load() {
+        let scratch = Scratch::new("crash");
+        let mut db = database(&scratch, vec![show(&scratch, 3)]);
+        db.save().unwrap();
+        assert!(air_batch(&mut db, "abcd1234", &scratch.path("links"), None).is_ok());
+        assert!(is_link(&scratch.path("links/Show_ep01.mkv")));
+
+        // The process dies here, before saving
+        hand_journal_to_dead_process();
+        let db = Database::load().unwrap();
+        assert_eq!(db.programs["abcd1234"].current_episode, 0);
+        assert!(!is_link(&scratch.path("links/Show_ep01.mkv")));
+        assert!(fs::read_dir(scratch.path("state")).unwrap()
+            .all(|entry| !entry.unwrap().file_name().to_string_lossy().starts_with(JOURNAL_PREFIX)));
+    }
+
+    #[test]
+    fn saved_rollover_keeps_its_links() {
+        let scratch = Scratch::new("saved");
+        let mut db = database(&scratch, vec![show(&scratch, 3)]);
+        assert!(air_batch(&mut db, "abcd1234", &scratch.path("links"), None).is_ok());
+        db.save().unwrap();
+        assert!(!journal_path().unwrap().exists());
+        assert!(!scratch.path("state/queuecast.json.tmp").exists());
+
+        let db = Database::load().unwrap();
+        assert_eq!(db.programs["abcd1234"].current_episode, 1);
+        assert!(is_link(&scratch.path("links/Show_ep01.mkv")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signals_set_the_reload_and_shutdown_flags() {
+        use signal_hook::consts::{SIGHUP, SIGTERM};
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let reload = Arc::new(AtomicBool::new(false));
+        install_signal_handlers(&shutdown, &reload).unwrap();
+
+        signal_hook::low_level::raise(SIGHUP).unwrap();
+        assert!(reload.load(AtomicOrdering::SeqCst));
+        assert!(!shutdown.load(AtomicOrdering::SeqCst));
+
+        // Only the first SIGTERM is handed to the daemon, a second one exits
+        signal_hook::low_level::raise(SIGTERM).unwrap();
+        assert!(shutdown.load(AtomicOrdering::SeqCst));
+    }
+
+    #[test]
+    fn only_a_changed_file_is_probed_again() {
+        let scratch = Scratch::new("probe");
+        let mut program = show(&scratch, 3);
+        program.minutes_per_rollover = Some(60);
+        for episode in &mut program.episodes {
+            episode.duration_secs = Some(1500);
+            episode.probed = FileStamp::of(episode.parts());
+        }
+        let changed = scratch.path("media/Show/Show.S01E02.mkv");
+        fs::write(&changed, b"a different cut").unwrap();
+
+        // The measured files don't hold real video, so probing them again
+        // leaves no duration
+        rollover_batch(&mut program);
+        let durations: Vec<Option<u32>> = program.episodes.iter().map(|e| e.duration_secs).collect();
+        assert_eq!(durations, vec![Some(1500), None, Some(1500)]);
+        assert_eq!(program.episodes[1].probed, FileStamp::of([&changed]));
+    }
+
+    #[test]
+    fn confirmation_without_a_terminal_needs_yes() {
+        let mut db = Database::default();
+        db.programs.insert("abcd1234".to_string(), program(Path::new("/media/Show"), &[]));
+        let hashes = vec!["abcd1234".to_string()];
+        assert!(confirm_programs(&db, "remove", &hashes, false, false).is_err());
+        assert!(confirm_programs(&db, "remove", &hashes, true, false).unwrap());
+        assert!(confirm_programs(&db, "remove", &[], false, false).unwrap());
+    }
+
+    #[test]
+    fn position_past_the_end_is_an_error_not_a_panic() {
+        let scratch = Scratch::new("position");
+        let mut program = show(&scratch, 3);
+        program.current_episode = 99;
+        database(&scratch, vec![program]).save().unwrap();
+
+        let mut db = Database::load().unwrap();
+        let links = scratch.path("links");
+        let program = db.programs["abcd1234"].clone();
+        for force in [Force::No, Force::Yes] {
+            assert!(planned_changes(&db, &links, &program, force, &mut None).is_err());
+            assert!(update_program_symlink(&mut db, "abcd1234", force).is_err());
+        }
+        let errors = update_symlinks(&mut db, None, None).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(db.programs["abcd1234"].current_episode, 99);
+    }
+
+    #[test]
+    fn sanitized_names_are_safe_everywhere() {
+        assert_eq!(sanitize_file_name(r#"Re:Zero? <"Cut"> *|"#, '_'), "Re_Zero_ __Cut__ __");
+        assert_eq!(sanitize_file_name("con", '_'), "con_");
+        assert_eq!(sanitize_file_name("Lpt1.Show", '_'), "Lpt1_.Show");
+        assert_eq!(sanitize_file_name("Console", '_'), "Console");
+        assert_eq!(sanitize_file_name("..", '_'), "_");
+        assert_eq!(sanitize_file_name(r#"/\:*?"<>|"#, '-'), "---------");
+        assert_eq!(sanitize_file_name("Tab\there\n", '_'), "Tab_here_");
+
+        let long = "日".repeat(100);
+        let sanitized = sanitize_file_name(&long, '_');
+        assert_eq!(sanitized, "日".repeat(66));
+        assert!(sanitized.len() <= MAX_FILE_NAME_BYTES);
+    }
+
+    #[test]
+    fn rescan_finding_nothing_keeps_the_episodes() {
+        let scratch = Scratch::new("rescan-empty");
+        let mut program = show(&scratch, 3);
+        program.current_episode = 1;
+        let mut db = database(&scratch, vec![program]);
+        for number in 1..=3 {
+            fs::remove_file(scratch.path(&format!("media/Show/Show.S01E{:02}.mkv", number))).unwrap();
+        }
+
+        assert!(!rescan_program(&mut db, "abcd1234", false).unwrap());
+        let program = &db.programs["abcd1234"];
+        assert_eq!(program.episodes.len(), 3);
+        assert_eq!(program.current_episode, 1);
+        assert_eq!(program.status, ProgramStatus::Running);
+        assert!(program.stall.as_ref().is_some_and(|stall| matches!(stall.reason, StallReason::SourceUnavailable)));
+
+        assert!(rescan_program(&mut db, "abcd1234", true).unwrap());
+        assert!(db.programs["abcd1234"].episodes.is_empty());
+    }
+
+    #[test]
+    fn rescan_left_with_only_aired_episodes_keeps_its_place() {
+        let scratch = Scratch::new("rescan-shrink");
+        let mut program = show(&scratch, 4);
+        program.current_episode = 2;
+        let mut db = database(&scratch, vec![program]);
+        for number in 2..=4 {
+            fs::remove_file(scratch.path(&format!("media/Show/Show.S01E{:02}.mkv", number))).unwrap();
+        }
+
+        assert!(!rescan_program(&mut db, "abcd1234", false).unwrap());
+        let program = &db.programs["abcd1234"];
+        assert_eq!(program.episodes.len(), 4);
+        assert_eq!(program.current_episode, 2);
+    }
+
+    #[test]
+    fn rescan_shrinks_only_when_nothing_is_left_to_air() {
+        let scratch = Scratch::new("rescan-resume");
+        let mut program = show(&scratch, 4);
+        program.current_episode = 2;
+        let episodes = program.episodes.clone();
+
+        // Resumes after the last aired file still there
+        assert_eq!(resume_position(&program, &episodes), 2);
+        assert_eq!(resume_position(&program, &episodes[1..]), 1);
+        assert_eq!(resume_position(&program, &episodes[2..]), 0);
+        assert!(!rescan_shrinks(&program, &episodes[1..]));
+        assert!(!rescan_shrinks(&program, &episodes[2..]));
+        assert!(rescan_shrinks(&program, &episodes[..2]));
+        assert!(rescan_shrinks(&program, &episodes[..1]));
+        assert!(rescan_shrinks(&program, &[]));
+
+        // A finished program, or a pinned one, may lose episodes
+        program.current_episode = 4;
+        assert!(!rescan_shrinks(&program, &episodes[..2]));
+        program.current_episode = 2;
+        program.pinned = true;
+        assert!(!rescan_shrinks(&program, &[]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn journal_of_a_running_process_is_left_alone() {
+        let scratch = Scratch::new("running");
+        let db = database(&scratch, vec![show(&scratch, 3)]);
+        db.save().unwrap();
+        let mut other = process::Command::new("sleep").arg("30").spawn().unwrap();
+        let link = scratch.path("links/Show_ep01.mkv");
+        create_symlink(&db.programs["abcd1234"].episodes[0].path, &link).unwrap();
+        let intent = RolloverIntent {
+            pid: other.id(),
+            program_hash: "abcd1234".to_string(),
+            from: 0,
+            to: 1,
+            links: vec![IntentLink { path: link.clone(), target: db.programs["abcd1234"].episodes[0].path.clone(), previous: None }],
+            unlinked: Vec::new(),
+        };
+        let journal = scratch.path(&format!("state/{}-{}.json", JOURNAL_PREFIX, other.id()));
+        fs::write(&journal, serde_json::to_string(&[intent]).unwrap()).unwrap();
+
+        // Our own save neither undoes nor removes the other process's journal
+        let mut db = Database::load().unwrap();
+        db.programs.get_mut("abcd1234").unwrap().pinned = true;
+        db.save().unwrap();
+        let _ = other.kill();
+        let _ = other.wait();
+        assert!(is_link(&link));
+        assert!(journal.exists());
+    }
+
+    #[test]
+    fn sftp_user_and_host_cannot_pass_for_options() {
+        assert!(SftpSource::parse("sftp://-oProxyCommand=touch%20x/shows").is_err());
+        assert!(SftpSource::parse("sftp://-oProxyCommand=x@host/shows").is_err());
+        assert!(SftpSource::parse("sftp://user@-host:22/shows").is_err());
+        let source = SftpSource::parse("sftp://user@nas-1:2222/shows/").unwrap();
+        assert_eq!(source.destination, "user@nas-1");
+        assert_eq!(source.port, Some(2222));
+        assert_eq!(source.path, "/shows");
+    }
+
+    // A program of four episodes, the first on air since `last_update`
+    fn airing(scratch: &Scratch, interval_days: u32, airing_days: Vec<Weekday>, last_update: DateTime<Utc>) -> Database {
+        let mut program = show(scratch, 4);
+        program.interval_days = interval_days;
+        program.airing_days = airing_days;
+        program.current_episode = 1;
+        program.last_update = Some(last_update);
+        database(scratch, vec![program])
+    }
+
+    fn tomorrow(db: &Database) -> Weekday {
+        Zone::of(&db.programs["abcd1234"]).weekday(Utc::now() + chrono::Duration::days(1))
+    }
+
+    #[test]
+    fn forced_rollover_off_the_airing_days_takes_the_next_slot() {
+        let scratch = Scratch::new("forced-off-day");
+        let mut db = airing(&scratch, 7, Vec::new(), Utc::now() - chrono::Duration::days(2));
+        let day = tomorrow(&db);
+        db.programs.get_mut("abcd1234").unwrap().airing_days = vec![day];
+        let slot = next_rollover_time(&db.programs["abcd1234"], &db.blackouts).unwrap();
+        assert!(slot > Utc::now());
+
+        update_symlinks(&mut db, Some(("abcd1234", Force::Yes)), None).unwrap();
+        let program = &db.programs["abcd1234"];
+        assert_eq!(program.current_episode, 2);
+        assert_eq!(program.schedule_slot, Some(slot));
+        assert!(program.last_update.unwrap() <= Utc::now());
+        assert!(next_rollover_time(program, &db.blackouts).unwrap() >= slot + chrono::Duration::days(7));
+        assert!(plan_rollover(&db, &db.programs["abcd1234"], Force::Yes, None).is_err());
+
+        // Neither a scheduled update nor catch-up airs it again
+        update_symlinks(&mut db, None, None).unwrap();
+        update_symlinks(&mut db, None, Some(3)).unwrap();
+        assert_eq!(db.programs["abcd1234"].current_episode, 2);
+    }
+
+    #[test]
+    fn forced_rollover_in_the_airing_window_counts_as_the_due_one() {
+        let scratch = Scratch::new("forced-due");
+        let mut db = airing(&scratch, 7, Vec::new(), Utc::now() - chrono::Duration::days(8));
+
+        update_symlinks(&mut db, Some(("abcd1234", Force::Yes)), None).unwrap();
+        let last = db.programs["abcd1234"].last_update.unwrap();
+        assert!(Utc::now() - last < chrono::Duration::minutes(1));
+
+        update_symlinks(&mut db, None, Some(3)).unwrap();
+        update_symlinks(&mut db, None, None).unwrap();
+        assert_eq!(db.programs["abcd1234"].current_episode, 2);
+    }
+
+    #[test]
+    fn overdue_rollover_forced_off_day_stands_in_for_the_missed_slot() {
+        let scratch = Scratch::new("forced-overdue");
+        let mut db = airing(&scratch, 1, Vec::new(), Utc::now() - chrono::Duration::days(15));
+        let day = tomorrow(&db);
+        db.programs.get_mut("abcd1234").unwrap().airing_days = vec![day];
+        let missed = next_rollover_time(&db.programs["abcd1234"], &db.blackouts).unwrap();
+        assert!(missed < Utc::now());
+
+        update_symlinks(&mut db, Some(("abcd1234", Force::Yes)), None).unwrap();
+        let program = &db.programs["abcd1234"];
+        assert_eq!(program.schedule_slot, Some(missed));
+        assert!(Utc::now() - program.last_update.unwrap() < chrono::Duration::minutes(1));
+        // The following slot is owed too, but only airs on an airing day
+        assert!(next_rollover_time(program, &db.blackouts).unwrap() < Utc::now());
+        update_symlinks(&mut db, None, Some(3)).unwrap();
+        assert_eq!(db.programs["abcd1234"].current_episode, 2);
+    }
+
+    #[test]
+    fn adopting_resumes_after_the_highest_numbered_link() {
+        let scratch = Scratch::new("adopt");
+        let files = show(&scratch, 5).episodes;
+        let manual = scratch.path("manual");
+        fs::create_dir_all(&manual).unwrap();
+        // The older links were deleted as the show went on
+        create_symlink(&files[1].path, &manual.join("Show 02.mkv")).unwrap();
+        create_symlink(&files[3].path, &manual.join("Show 04.mkv")).unwrap();
+        let mut db = database(&scratch, Vec::new());
+        let options = AddOptions { adopt: Some(manual.clone()), ..AddOptions::default() };
+        add_program(&mut db, scratch.path("media/Show").to_str().unwrap(), &options).unwrap();
+
+        let program = db.programs.values().next().unwrap();
+        let paths: Vec<&Path> = program.episodes.iter().map(|e| e.path.as_path()).collect();
+        assert_eq!(paths, files.iter().map(|e| e.path.as_path()).collect::<Vec<_>>());
+        assert_eq!(program.current_episode, 4);
+        assert_eq!(db.history.len(), 2);
+
+        // Links numbered against the scan order take the places of their files
+        let mut db = database(&scratch, Vec::new());
+        fs::remove_file(manual.join("Show 02.mkv")).unwrap();
+        fs::remove_file(manual.join("Show 04.mkv")).unwrap();
+        create_symlink(&files[2].path, &manual.join("Show 01.mkv")).unwrap();
+        create_symlink(&files[0].path, &manual.join("Show 02.mkv")).unwrap();
+        add_program(&mut db, scratch.path("media/Show").to_str().unwrap(), &options).unwrap();
+
+        let program = db.programs.values().next().unwrap();
+        let order: Vec<&Path> = program.episodes.iter().map(|e| e.path.as_path()).collect();
+        assert_eq!(order, [&files[2], &files[1], &files[0], &files[3], &files[4]].map(|e| e.path.as_path()));
+        assert_eq!(program.current_episode, 3);
+    }
+
+    #[test]
+    fn episode_ranges_stop_at_the_last_episode() {
+        assert_eq!(parse_episode_ranges("3-5, 7", 10).unwrap(), vec![3, 4, 5, 7]);
+        assert_eq!(parse_episode_ranges("7,1-2", 10).unwrap(), vec![1, 2, 7]);
+        assert!(parse_episode_ranges("1-4000000000", 10).is_err());
+        assert!(parse_episode_ranges("11", 10).is_err());
+        assert!(parse_episode_ranges("3-5,5", 10).is_err());
+        assert!(parse_episode_ranges("0-2", 10).is_err());
+        assert!(parse_episode_ranges("5-3", 10).is_err());
+    }
+
+    #[test]
+    fn sidecars_of_dotted_folder_links_do_not_collide() {
+        assert_eq!(sidecar_path(Path::new("/links/Mr.Robot_ep01")), Path::new("/links/Mr.Robot_ep01.nfo"));
+        assert_ne!(sidecar_path(Path::new("/links/Mr.Robot_ep01")), sidecar_path(Path::new("/links/Mr.Robot_ep02")));
+        assert_eq!(sidecar_path(Path::new("/links/Show_ep01.mkv")), Path::new("/links/Show_ep01.mkv.nfo"));
+        assert_eq!(legacy_sidecar_path(Path::new("/links/Show_ep01.MKV")), Some(PathBuf::from("/links/Show_ep01.nfo")));
+        assert_eq!(legacy_sidecar_path(Path::new("/links/Mr.Robot_ep01")), None);
+    }
+
+    #[test]
+    fn removing_a_program_takes_its_links_down() {
+        let scratch = Scratch::new("remove");
+        let mut db = database(&scratch, vec![show(&scratch, 2)]);
+        db.nfo_template = Some("<episodedetails/>".to_string());
+        db.ready_markers = true;
+        assert!(air_batch(&mut db, "abcd1234", &scratch.path("links"), None).is_ok());
+        let link = scratch.path("links/Show_ep01.mkv");
+        assert!(is_link(&link) && sidecar_path(&link).exists() && ready_marker_path(&link).exists());
+
+        remove_program(&mut db, "abcd1234").unwrap();
+        assert!(!is_link(&link));
+        assert!(!sidecar_path(&link).exists());
+        assert!(!ready_marker_path(&link).exists());
+    }
+
+    #[test]
+    fn removing_a_program_keeps_episodes_a_rerun_airs() {
+        let scratch = Scratch::new("remove-rerun");
+        let mut db = database(&scratch, vec![show(&scratch, 2)]);
+        assert!(air_batch(&mut db, "abcd1234", &scratch.path("links"), None).is_ok());
+        // As if downloaded, with a rerun cloned from it
+        let original = db.programs.get_mut("abcd1234").unwrap();
+        original.remote = Some("sftp://host/Show".to_string());
+        let mut rerun = original.clone();
+        rerun.hash = "rerun123".to_string();
+        rerun.active_links.clear();
+        db.programs.insert(rerun.hash.clone(), rerun);
+        let link = db.programs["abcd1234"].active_links[0].path.clone();
+
+        remove_program(&mut db, "abcd1234").unwrap();
+        assert!(!is_link(&link));
+        assert!(scratch.path("media/Show/Show.S01E01.mkv").exists());
+
+        remove_program(&mut db, "rerun123").unwrap();
+        assert!(!scratch.path("media/Show").exists());
+    }
+
+    #[test]
+    fn removing_a_program_clears_its_nested_link_folders() {
+        let scratch = Scratch::new("remove-folders");
+        let mut program = show(&scratch, 2);
+        program.link_dir = Some(PathBuf::from("Anime/Seasonal"));
+        let mut db = database(&scratch, vec![program]);
+        db.link_naming.program_dirs = true;
+        assert!(air_batch(&mut db, "abcd1234", &scratch.path("links"), None).is_ok());
+        assert!(is_link(&db.programs["abcd1234"].active_links[0].path));
+
+        remove_program(&mut db, "abcd1234").unwrap();
+        assert!(!scratch.path("links/Anime").exists());
+        assert!(scratch.path("links").exists());
+    }
+
+    #[test]
+    fn numbering_gaps_are_reported_as_ranges() {
+        let episodes: Vec<Episode> = ["Show.S01E01.mkv", "Show.S01E03.mkv", "Show.S01E05-E4000000000.mkv", "Show.S01E4000000000.mp4"].iter()
+            .map(|name| program(Path::new("/media/Show"), &[PathBuf::from(format!("/media/Show/{}", name))]).episodes.remove(0))
+            .collect();
+        assert_eq!(numbering_problems(&episodes), vec![
+            "Season 1: missing episode 2, 4, 6-3999999999".to_string(),
+            "Season 1: episode 4000000000 appears in 2 files".to_string(),
+        ]);
+    }
+
+    // The current episode after 'diff' plans a rollover, and after update
+    // makes it
+    fn planned_and_made(db: &mut Database, force: Force) -> (Result<usize, String>, Result<usize, String>) {
+        let links = db.symlink_dir.clone().unwrap();
+        let program = db.programs["abcd1234"].clone();
+        let planned = planned_changes(db, &links, &program, force, &mut budget_left(db))
+            .map(|changes| changes.iter().find_map(|change| match change {
+                PlannedChange::CurrentEpisode { to, .. } => Some(*to),
+                _ => None,
+            }).unwrap_or(program.current_episode))
+            .map_err(|e| e.to_string());
+        let made = match force {
+            Force::No => update_symlinks(db, None, None).map(|_| ()),
+            _ => update_symlinks(db, Some(("abcd1234", force)), None).map(|_| ()),
+        };
+        (planned, made.map(|_| db.programs["abcd1234"].current_episode).map_err(|e| e.to_string()))
+    }
+
+    #[test]
+    fn diff_and_update_both_refuse_forcing_in_the_cooldown() {
+        let scratch = Scratch::new("diff-cooldown");
+        let mut db = airing(&scratch, 1, Vec::new(), Utc::now() - chrono::Duration::hours(2));
+        let (planned, made) = planned_and_made(&mut db, Force::Yes);
+        assert!(planned.is_err() && made.is_err());
+        assert_eq!(planned_and_made(&mut db, Force::Really), (Ok(2), Ok(2)));
+    }
+
+    #[test]
+    fn diff_and_update_both_leave_a_repeated_forced_update() {
+        let scratch = Scratch::new("diff-grace");
+        let mut db = airing(&scratch, 1, Vec::new(), Utc::now() - chrono::Duration::seconds(10));
+        assert_eq!(planned_and_made(&mut db, Force::Yes), (Ok(1), Ok(1)));
+    }
+
+    #[test]
+    fn diff_and_update_agree_on_a_pinned_program() {
+        let scratch = Scratch::new("diff-pinned");
+        let mut db = airing(&scratch, 1, Vec::new(), Utc::now() - chrono::Duration::days(2));
+        db.programs.get_mut("abcd1234").unwrap().pinned = true;
+        let (planned, made) = planned_and_made(&mut db, Force::Really);
+        assert!(planned.is_err() && made.is_err());
+        assert_eq!(planned_and_made(&mut db, Force::No), (Ok(1), Ok(1)));
+    }
+
+    #[test]
+    fn diff_and_update_air_only_what_the_weekly_budget_leaves() {
+        let scratch = Scratch::new("diff-budget");
+        let mut db = airing(&scratch, 1, Vec::new(), Utc::now() - chrono::Duration::days(2));
+        db.programs.get_mut("abcd1234").unwrap().batch_size = Some(3);
+        db.weekly_budget = Some(aired_this_week(&db) as u32 + 2);
+
+        // A batch of three cut to the two the budget has left
+        assert_eq!(planned_and_made(&mut db, Force::No), (Ok(3), Ok(3)));
+
+        // Due again with the budget used up: deferred by both
+        db.programs.get_mut("abcd1234").unwrap().last_update = Some(Utc::now() - chrono::Duration::days(2));
+        assert_eq!(planned_and_made(&mut db, Force::No), (Ok(3), Ok(3)));
+
+        // Forcing goes past the budget
+        assert_eq!(planned_and_made(&mut db, Force::Really), (Ok(4), Ok(4)));
+    }
+
+    #[test]
+    fn catching_up_stops_at_the_weekly_budget() {
+        let scratch = Scratch::new("catch-up-budget");
+        let mut db = airing(&scratch, 1, Vec::new(), Utc::now() - chrono::Duration::days(3));
+        db.weekly_budget = Some(aired_this_week(&db) as u32 + 1);
+        update_symlinks(&mut db, None, Some(3)).unwrap();
+        assert_eq!(db.programs["abcd1234"].current_episode, 2);
+    }
+
+    #[test]
+    fn diff_spends_the_weekly_budget_on_the_programs_update_airs() {
+        let scratch = Scratch::new("diff-order");
+        let mut db = airing(&scratch, 1, Vec::new(), Utc::now() - chrono::Duration::days(2));
+        // Due longer, so it gets the one episode the budget has left
+        let mut other = db.programs["abcd1234"].clone();
+        other.hash = "efgh5678".to_string();
+        other.name = "Other".to_string();
+        other.link_dir = Some(PathBuf::from("Other"));
+        other.last_update = Some(Utc::now() - chrono::Duration::days(3));
+        db.programs.insert(other.hash.clone(), other);
+        db.weekly_budget = Some(aired_this_week(&db) as u32 + 1);
+
+        let links = db.symlink_dir.clone().unwrap();
+        let planned: Vec<(String, bool)> = planned_update(&db, &links, None).into_iter()
+            .map(|(program, changes)| (program.hash.clone(), !changes.unwrap().is_empty()))
+            .collect();
+        update_symlinks(&mut db, None, None).unwrap();
+        for (hash, changes) in planned {
+            assert_eq!(changes, db.programs[&hash].current_episode == 2, "{}", hash);
+        }
+        assert_eq!(db.programs["efgh5678"].current_episode, 2);
+    }
+
+    #[test]
+    fn paths_follow_the_root_to_another_mount_point() {
+        let old = Scratch::new("root-old");
+        let mut db = database(&old, vec![show(&old, 2)]);
+        db.path_root = Some(PathBuf::from(".."));
+        assert!(air_batch(&mut db, "abcd1234", &old.path("links"), None).is_ok());
+        db.save().unwrap();
+        export_history(&db, old.path("history.json").to_str().unwrap()).unwrap();
+
+        // Stored relative to the root, so nothing names the old mount point
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(old.path("state/queuecast.json")).unwrap()).unwrap();
+        assert_eq!(saved["symlink_dir"], "links");
+        assert_eq!(saved["programs"]["abcd1234"]["directory"], "media/Show");
+        assert_eq!(saved["programs"]["abcd1234"]["episodes"][1]["path"], "media/Show/Show.S01E02.mkv");
+
+        let new = Scratch::new("root-new");
+        fs::remove_dir_all(&new.0).unwrap();
+        fs::rename(&old.0, &new.0).unwrap();
+        let mut db = Database::load().unwrap();
+        let program = &db.programs["abcd1234"];
+        assert_eq!(db.symlink_dir, Some(new.path("links")));
+        assert_eq!(program.directory, new.path("media/Show"));
+        assert_eq!(program.episodes[1].path, new.path("media/Show/Show.S01E02.mkv"));
+        assert!(program.episodes[1].path.exists());
+
+        // Saving under the new root writes the same relative paths back
+        db.save().unwrap();
+        let resaved: serde_json::Value = serde_json::from_str(&fs::read_to_string(new.path("state/queuecast.json")).unwrap()).unwrap();
+        assert_eq!(resaved["programs"], saved["programs"]);
+        assert_eq!(resaved["symlink_dir"], saved["symlink_dir"]);
+
+        // History exported on the old mount point lands under the new one
+        db.history.clear();
+        import_history(&mut db, &new.path("history.json")).unwrap();
+        assert_eq!(db.history[0].path, new.path("media/Show/Show.S01E01.mkv"));
+    }
+
+    #[test]
+    fn a_symlink_dir_outside_the_root_is_left_alone() {
+        let scratch = Scratch::new("root-outside");
+        let mut db = database(&scratch, vec![show(&scratch, 1)]);
+        db.path_root = Some(PathBuf::from("../media"));
+        db.save().unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(scratch.path("state/queuecast.json")).unwrap()).unwrap();
+        assert_eq!(saved["symlink_dir"], serde_json::json!(scratch.path("links")));
+        assert_eq!(saved["programs"]["abcd1234"]["directory"], "Show");
+        assert_eq!(Database::load().unwrap().symlink_dir, Some(scratch.path("links")));
+    }
+
+    #[test]
+    fn a_setting_set_to_its_environment_value_is_saved() {
+        let mut db = Database {
+            force_grace_seconds: Some(30),
+            env_overrides: vec![EnvOverride {
+                pointer: "/force_grace_seconds",
+                file_value: serde_json::json!(90),
+                loaded_value: serde_json::json!(30),
+            }],
+            ..Database::default()
+        };
+        assert_eq!(db.without_env_overrides().unwrap().force_grace_seconds, Some(90));
+        set_force_grace(&mut db, 30).unwrap();
+        assert_eq!(db.without_env_overrides().unwrap().force_grace_seconds, Some(30));
+    }
+
+    #[test]
+    fn episode_extensions_match_regardless_of_case() {
+        let extensions = parse_extensions("PDF,.Cbz").unwrap();
+        assert_eq!(extensions, vec!["pdf".to_string(), "cbz".to_string()]);
+        assert!(has_episode_extension(Path::new("Scan.PDF"), &extensions));
+        assert!(has_episode_extension(Path::new("Issue 1.cbz"), &extensions));
+        assert!(!has_episode_extension(Path::new("Episode 1.mkv"), &extensions));
+        assert!(has_episode_extension(Path::new("Episode 1.MKV"), &[]));
+    }
+
+    #[cfg(feature = "sftp")]
+    #[test]
+    fn remote_paths_are_escaped_against_sftp_globbing() {
+        assert_eq!(sftp_remote_arg("/media/Show [1080p]/Ep*1?.mkv"), r#""/media/Show \[1080p\]/Ep\*1\?.mkv""#);
+        assert_eq!(sftp_remote_arg("/media/a\\b/ spaced "), r#""/media/a\\b/ spaced ""#);
+    }
+}